@@ -0,0 +1,170 @@
+//! Parsing benchmarks on the built-in configs: `cargo bench --bench parsing`
+
+use std::hint::black_box;
+use std::path::Path;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use universal_cli_parser::Analyzer;
+use universal_cli_parser::config_store::ConfigStore;
+use universal_cli_parser::parser_config::ParserConfig;
+
+/// A curl command-line using options from all over its (large) config
+const CURL_ARGS: &[&str] = &[
+    "-sSL",
+    "--retry",
+    "3",
+    "--connect-timeout=10",
+    "-H",
+    "Accept: application/json",
+    "--header",
+    "X-Client: ucp",
+    "-o",
+    "/tmp/out.json",
+    "--compressed",
+    "--retry=5",
+    "https://example.com/api",
+];
+
+/// Command-lines of the built-in programs, as a monitoring agent would see them
+const CORPUS: &[(&str, &[&str])] = &[
+    ("ls", &["-la", "--color", "/etc"]),
+    (
+        "ls",
+        &["-lhtr", "--block-size", "K", "--hide", "*.o", "/var/log"],
+    ),
+    (
+        "tar",
+        &[
+            "-xvf",
+            "archive.tar",
+            "--listed-incremental",
+            "/tmp/snapshot.db",
+        ],
+    ),
+    (
+        "tar",
+        &[
+            "--create",
+            "--file",
+            "backup.tar.gz",
+            "-v",
+            "/home/user/docs",
+        ],
+    ),
+    ("chmod", &["-R", "--verbose", "755", "./scripts"]),
+    ("chmod", &["--reference", "/etc/passwd", "-c", "secret.sh"]),
+    ("curl", CURL_ARGS),
+    (
+        "curl",
+        &[
+            "-fsSL",
+            "https://get.example.com/install.sh",
+            "-o",
+            "install.sh",
+        ],
+    ),
+    ("python3", &["-m", "http.server", "8080"]),
+    ("python3", &["-B", "-c", "import os; os.system('id')"]),
+    (
+        "ab",
+        &[
+            "-k",
+            "-c",
+            "10",
+            "-C",
+            "session=1",
+            "http://localhost:8080/",
+        ],
+    ),
+];
+
+fn option_lookups(c: &mut Criterion) {
+    let analyzer = Analyzer::builtin();
+    let curl = analyzer
+        .cache()
+        .get_config("curl")
+        .expect("The curl config loads");
+    let string_options: Vec<String> = curl
+        .string_options
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect();
+    let char_options: Vec<char> = curl.char_options.iter().map(|(name, _)| *name).collect();
+
+    c.bench_function("string option has_arg (every curl option)", |b| {
+        b.iter(|| {
+            for name in &string_options {
+                black_box(curl.does_string_option_have_arg(black_box(name)).ok());
+            }
+        })
+    });
+    c.bench_function("char option has_arg (every curl option)", |b| {
+        b.iter(|| {
+            for name in &char_options {
+                black_box(curl.does_char_option_have_arg(black_box(name)).ok());
+            }
+        })
+    });
+    c.bench_function("behaviours (every curl option)", |b| {
+        b.iter(|| {
+            for name in &string_options {
+                black_box(curl.get_behaviours(black_box(name)).ok());
+            }
+        })
+    });
+    c.bench_function("embedded option split", |b| {
+        b.iter(|| black_box(curl.try_split_embedded_option(black_box("connect-timeout=10"))))
+    });
+}
+
+fn analyze(c: &mut Criterion) {
+    let analyzer = Analyzer::builtin();
+    // Load the configs once, outside of the measurements
+    analyzer
+        .analyze("curl", CURL_ARGS)
+        .expect("The curl command-line is analyzed");
+    for (program, args) in CORPUS {
+        analyzer
+            .analyze(program, args)
+            .unwrap_or_else(|err| panic!("{program} {args:?}: {err}"));
+    }
+
+    c.bench_function("analyze curl", |b| {
+        b.iter(|| black_box(analyzer.analyze("curl", black_box(CURL_ARGS))))
+    });
+    c.bench_function("parse curl", |b| {
+        b.iter(|| black_box(analyzer.parse("curl", black_box(CURL_ARGS))))
+    });
+    c.bench_function("analyze corpus", |b| {
+        b.iter(|| {
+            for (program, args) in black_box(CORPUS) {
+                black_box(analyzer.analyze(program, args).ok());
+            }
+        })
+    });
+    c.bench_function("parse corpus", |b| {
+        b.iter(|| {
+            for (program, args) in black_box(CORPUS) {
+                black_box(analyzer.parse(program, args).ok());
+            }
+        })
+    });
+    c.bench_function("analyze tar", |b| {
+        b.iter(|| black_box(analyzer.analyze("tar", black_box(&["-xvf", "archive.tar"]))))
+    });
+}
+
+fn load(c: &mut Criterion) {
+    let store = ConfigStore::builtin();
+    c.bench_function("load curl config", |b| {
+        b.iter(|| black_box(ParserConfig::from_store(&store, Path::new("curl.toml")).is_ok()))
+    });
+    let cache = Analyzer::builtin().cache().clone();
+    cache.get_config("curl").expect("The curl config loads");
+    c.bench_function("cached config lookup", |b| {
+        b.iter(|| black_box(cache.get_config(black_box("curl")).is_ok()))
+    });
+}
+
+criterion_group!(benches, option_lookups, analyze, load);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+use std::path::Path;
+
+fn main() {
+    embed_configs();
+
+    // Only the `grpc` feature needs the protobuf code, compiled with protox so that no `protoc`
+    // binary is required
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/ucp.proto");
+        let file_descriptors =
+            protox::compile(["proto/ucp.proto"], ["proto"]).expect("Failed to compile ucp.proto");
+        tonic_prost_build::configure()
+            .compile_fds(file_descriptors)
+            .expect("Failed to generate the gRPC code");
+    }
+}
+
+/// Generate the list of the built-in configs: every TOML, JSON or YAML file under `configs/`, with
+/// its path relative to it
+fn embed_configs() {
+    println!("cargo:rerun-if-changed=configs");
+    let configs_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("configs");
+    let mut files = vec![];
+    collect_config_files(&configs_dir, &mut files);
+    files.sort();
+
+    let mut code = String::from("&[\n");
+    for file in &files {
+        let name = file
+            .strip_prefix(&configs_dir)
+            .expect("Config files are under the configs directory")
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        code.push_str(&format!(
+            "    ({:?}, include_str!({:?})),\n",
+            name,
+            file.display().to_string()
+        ));
+    }
+    code.push_str("]\n");
+
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    std::fs::write(Path::new(&out_dir).join("builtin_configs.rs"), code)
+        .expect("Failed to write the built-in config list");
+}
+
+fn collect_config_files(dir: &Path, files: &mut Vec<std::path::PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        if path.is_dir() {
+            println!("cargo:rerun-if-changed={}", path.display());
+            collect_config_files(&path, files);
+        } else if path.extension().is_some_and(|ext| {
+            ["toml", "json", "yaml", "yml"]
+                .iter()
+                .any(|config| ext == *config)
+        }) {
+            files.push(path);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! Query the gRPC service through an in-process client, without opening any socket:
+//! `cargo run --example grpc_in_process --features grpc`
+
+use universal_cli_parser::Analyzer;
+use universal_cli_parser::grpc::in_process_client;
+use universal_cli_parser::grpc::proto::{AnalyzeRequest, ListProgramsRequest};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let analyzer = Analyzer::builtin();
+    let mut client = in_process_client(analyzer).await?;
+
+    let programs = client.list_programs(ListProgramsRequest {}).await?;
+    println!("Programs: {:?}", programs.into_inner().programs);
+
+    let response = client
+        .analyze(AnalyzeRequest {
+            program: "tar".to_string(),
+            args: vec!["-xvf".to_string(), "archive.tar".to_string()],
+            // Resolve `archive.tar` to `/tmp/archive.tar`
+            cwd: Some("/tmp".to_string()),
+            ..Default::default()
+        })
+        .await?;
+    for element in response.into_inner().elements {
+        println!("\t{:?}", element.kind);
+    }
+
+    let requests = tokio_stream::iter(vec![
+        AnalyzeRequest {
+            program: "curl".to_string(),
+            args: vec!["-o".to_string(), "out.html".to_string()],
+            // Only keep the most general behaviours
+            depth: Some(0),
+            ..Default::default()
+        },
+        AnalyzeRequest {
+            program: "unknown-program".to_string(),
+            args: vec![],
+            ..Default::default()
+        },
+    ]);
+    let mut responses = client.analyze_batch(requests).await?.into_inner();
+    while let Some(response) = responses.message().await? {
+        println!(
+            "{}: {} element(s), error: {:?}",
+            response.program,
+            response.elements.len(),
+            response.error
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,799 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::borrow::Cow;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+use crate::completion_import::shell_words;
+use crate::config_cache::ParserConfigCache;
+use crate::config_store::ConfigStore;
+use crate::env_vars::{self, EnvAssignment};
+use crate::heuristic;
+use crate::parser::{analyze_the_split, parse_the_split};
+use crate::parser_config::ParserConfig;
+use crate::parser_structs::CLElement;
+use crate::path_classifier::PathContext;
+use crate::registry;
+use crate::taxonomy::{self, BehaviourSet, Taxonomy};
+
+/// A parsed command-line where every option carries its behaviours, preceded by the inherent
+/// behaviours of the program. It borrows from the analyzed command-line (see
+/// `CLElement::into_owned`).
+pub type BehaviourTree<'a> = Vec<CLElement<'a>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnalyzerError {
+    /// The config directory does not exist or is not a directory
+    ConfigDir { path: PathBuf, reason: String },
+    /// No config of the config directory applies to the program
+    UnknownProgram { program: String },
+    /// No config could be loaded for the program
+    ConfigNotFound { program: String, reason: String },
+    /// The behaviours of an option could not be retrieved from the program's config
+    UnknownOption { program: String, option: String },
+}
+
+impl fmt::Display for AnalyzerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnalyzerError::ConfigDir { path, reason } => {
+                write!(f, "Invalid config directory {}: {}", path.display(), reason)
+            }
+            AnalyzerError::UnknownProgram { program } => {
+                write!(f, "Unknown program \"{}\"", program)
+            }
+            AnalyzerError::ConfigNotFound { program, reason } => {
+                write!(
+                    f,
+                    "Failed to load config for program {}: {}",
+                    program, reason
+                )
+            }
+            AnalyzerError::UnknownOption { program, option } => {
+                write!(f, "The \"{}\" option of {} is unknown", option, program)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AnalyzerError {}
+
+/// How deep command-lines held by variables holding command-lines (`EDITOR='EDITOR=vi' ...`) are
+/// analyzed
+const MAX_COMMAND_DEPTH: usize = 8;
+
+/// Entry point of the library: turns the command-line of a program into a behaviour tree, using
+/// the program configs of a directory
+#[derive(Clone)]
+pub struct Analyzer {
+    cache: ParserConfigCache,
+    /// Whether programs without a config are parsed heuristically instead of being rejected
+    heuristic_fallback: bool,
+}
+
+impl Analyzer {
+    /// Build an analyzer loading (and caching) its program configs from `config_dir` only
+    pub fn from_config_dir(config_dir: impl AsRef<Path>) -> Result<Analyzer, AnalyzerError> {
+        Analyzer::from_store(ConfigStore::dir(config_dir.as_ref()))
+    }
+
+    /// Build an analyzer using the configs built into the library
+    pub fn builtin() -> Analyzer {
+        Analyzer {
+            cache: ParserConfigCache::new(ConfigStore::builtin()),
+            heuristic_fallback: false,
+        }
+    }
+
+    /// Build an analyzer loading (and caching) its program configs from `store`
+    pub fn from_store(store: ConfigStore) -> Result<Analyzer, AnalyzerError> {
+        if let Some(overlay) = store.overlays().iter().find(|overlay| !overlay.is_dir()) {
+            return Err(AnalyzerError::ConfigDir {
+                path: overlay.clone(),
+                reason: "not a directory".to_string(),
+            });
+        }
+        Ok(Analyzer {
+            cache: ParserConfigCache::new(store),
+            heuristic_fallback: false,
+        })
+    }
+
+    /// Parse the command-lines of programs without a config heuristically (see the `heuristic`
+    /// module) instead of failing with `AnalyzerError::UnknownProgram`
+    pub fn with_heuristic_fallback(mut self, enabled: bool) -> Analyzer {
+        self.heuristic_fallback = enabled;
+        self
+    }
+
+    /// Keep at most `capacity` configs in memory (see `ParserConfigCache::with_capacity`)
+    pub fn with_cache_capacity(mut self, capacity: usize) -> Analyzer {
+        self.cache = self.cache.with_capacity(capacity);
+        self
+    }
+
+    /// Resolve the behaviours of the configs, and those the analyzer attaches itself, in
+    /// `taxonomy` instead of the built-in one
+    pub fn with_taxonomy(mut self, taxonomy: &'static Taxonomy) -> Analyzer {
+        self.cache = self.cache.with_taxonomy(taxonomy);
+        self
+    }
+
+    /// Taxonomy the behaviours of the behaviour trees belong to
+    pub fn taxonomy(&self) -> &'static Taxonomy {
+        self.cache.taxonomy()
+    }
+
+    /// The config cache backing this analyzer
+    pub fn cache(&self) -> &ParserConfigCache {
+        &self.cache
+    }
+
+    /// Names of the configs of the registry, those of every layer of the config store (built-in
+    /// and overlays), sorted
+    pub fn programs(&self) -> Vec<String> {
+        self.cache
+            .registry()
+            .entries()
+            .iter()
+            .map(|entry| entry.config.clone())
+            .collect()
+    }
+
+    /// Parse `args` according to the config of `program`, without behaviours. Variables assigned
+    /// before the command (`LD_PRELOAD=/tmp/x.so ls`) become `CLEnvAssignment`s.
+    pub fn parse<'a, S: AsRef<str>>(
+        &self,
+        program: &str,
+        args: &'a [S],
+    ) -> Result<Vec<CLElement<'a>>, AnalyzerError> {
+        self.taxonomy()
+            .enter(|| run_in_place(self.parse_command_line(&LoadInPlace, program, args)))
+    }
+
+    /// Parse `args` according to the config of `program` and attach behaviours to its options
+    pub fn analyze<'a, S: AsRef<str>>(
+        &self,
+        program: &str,
+        args: &'a [S],
+    ) -> Result<BehaviourTree<'a>, AnalyzerError> {
+        self.analyze_in(program, args, &PathContext::default())
+    }
+
+    /// `analyze` in the context of the command-line: local paths are resolved in it (see
+    /// `resolve_behaviour_tree_paths`), and the variables of its environment that bring
+    /// behaviours are reported along with those assigned before the command
+    pub fn analyze_in<'a, S: AsRef<str>>(
+        &self,
+        program: &str,
+        args: &'a [S],
+        context: &PathContext,
+    ) -> Result<BehaviourTree<'a>, AnalyzerError> {
+        self.taxonomy().enter(|| {
+            run_in_place(self.analyze_command_line(&LoadInPlace, program, args, context, 0))
+        })
+    }
+
+    /// `parse` for async code, loading the config off the runtime if it is not cached
+    #[cfg(feature = "server")]
+    pub async fn parse_async<'a, S: AsRef<str>>(
+        &self,
+        program: &str,
+        args: &'a [S],
+    ) -> Result<Vec<CLElement<'a>>, AnalyzerError> {
+        self.taxonomy()
+            .scope(self.parse_command_line(&LoadOffRuntime, program, args))
+            .await
+    }
+
+    /// `analyze` for async code, loading the config off the runtime if it is not cached
+    #[cfg(feature = "server")]
+    pub async fn analyze_async<'a, S: AsRef<str>>(
+        &self,
+        program: &str,
+        args: &'a [S],
+    ) -> Result<BehaviourTree<'a>, AnalyzerError> {
+        self.analyze_in_async(program, args, &PathContext::default())
+            .await
+    }
+
+    /// `analyze_in` for async code, loading configs off the runtime if they are not cached
+    #[cfg(feature = "server")]
+    pub async fn analyze_in_async<'a, S: AsRef<str>>(
+        &self,
+        program: &str,
+        args: &'a [S],
+        context: &PathContext,
+    ) -> Result<BehaviourTree<'a>, AnalyzerError> {
+        self.taxonomy()
+            .scope(self.analyze_command_line(&LoadOffRuntime, program, args, context, 0))
+            .await
+    }
+
+    async fn parse_command_line<'a, S: AsRef<str>>(
+        &self,
+        loader: &impl ConfigLoader,
+        program: &str,
+        args: &'a [S],
+    ) -> Result<Vec<CLElement<'a>>, AnalyzerError> {
+        let (assignments, command) = split_assignments(program, args);
+        let parsed_cmdline = match command {
+            Some((program, args)) => match self.resolve(program, args) {
+                Ok((config, args)) => {
+                    let parser_config = loader.load(self, program, &config).await?;
+                    parse_the_split(args, &parser_config)
+                }
+                Err(AnalyzerError::UnknownProgram { .. }) if self.heuristic_fallback => {
+                    heuristic::parse_heuristic(args)
+                }
+                Err(err) => return Err(err),
+            },
+            None => vec![],
+        };
+        Ok(with_assignments(
+            parsed_cmdline,
+            parsed_assignments(assignments),
+        ))
+    }
+
+    /// `analyze_in` for a command-line held by `depth` nested variables. The variables of the
+    /// environment of `context` are only reported at depth 0: command-lines held by variables
+    /// inherit it without repeating it.
+    async fn analyze_command_line<'a, S: AsRef<str>>(
+        &self,
+        loader: &impl ConfigLoader,
+        program: &str,
+        args: &'a [S],
+        context: &PathContext,
+        depth: usize,
+    ) -> Result<BehaviourTree<'a>, AnalyzerError> {
+        let (assignments, command) = split_assignments(program, args);
+        let (behaviour_tree, parser_config) = match command {
+            Some((program, args)) => match self.resolve(program, args) {
+                Ok((config, args)) => {
+                    let parser_config = loader.load(self, program, &config).await?;
+                    (
+                        analyze_the_split(args, &parser_config)?,
+                        Some(parser_config),
+                    )
+                }
+                Err(AnalyzerError::UnknownProgram { .. }) if self.heuristic_fallback => {
+                    let parsed_cmdline = heuristic::parse_heuristic(args);
+                    (heuristic::enrich_heuristic(parsed_cmdline), None)
+                }
+                Err(err) => return Err(err),
+            },
+            None => (vec![], None),
+        };
+
+        let mut env_assignments = vec![];
+        for (mut assignment, command) in
+            analyzed_assignments(assignments, context, parser_config.as_deref(), depth == 0)
+        {
+            if let Some(command) = command {
+                assignment.command = Some(
+                    self.analyze_command(loader, &command, context, depth + 1)
+                        .await,
+                );
+            }
+            env_assignments.push(assignment);
+        }
+        let behaviour_tree = with_assignments(behaviour_tree, env_assignments);
+        Ok(resolve_behaviour_tree_paths(behaviour_tree, context))
+    }
+
+    /// Behaviour tree of the command-line held by a variable (`GIT_SSH_COMMAND`) at `depth`, or
+    /// the reason it could not be analyzed
+    async fn analyze_command(
+        &self,
+        loader: &impl ConfigLoader,
+        command: &str,
+        context: &PathContext,
+        depth: usize,
+    ) -> Vec<CLElement<'static>> {
+        if depth > MAX_COMMAND_DEPTH {
+            return vec![CLElement::ParsingError(Some(format!(
+                "Command-lines held by more than {MAX_COMMAND_DEPTH} nested variables are not analyzed"
+            )))];
+        }
+        let words = shell_words(command);
+        let behaviour_tree = match words.split_first() {
+            // Boxed, as the command-line may hold variables holding command-lines in turn
+            Some((program, args)) => {
+                Box::pin(self.analyze_command_line(loader, program, args, context, depth)).await
+            }
+            None => Ok(vec![]),
+        };
+        owned_command(behaviour_tree)
+    }
+
+    /// Name of the config the command-line of `program` is parsed with, None if it has none.
+    /// Unlike `program`, it is one of the configs of the registry (see `ProgramRegistry::resolve`).
+    pub fn config_name<S: AsRef<str>>(&self, program: &str, args: &[S]) -> Option<String> {
+        let (program, args) = split_assignments(program, args).1?;
+        let registry = self.cache.registry();
+        resolve_command(
+            |program| registry.resolve(program).map(str::to_string),
+            program,
+            args,
+        )
+        .map(|(config, _)| config)
+    }
+
+    /// Name of the config of `program` (see `ProgramRegistry::resolve`) and the arguments it
+    /// applies to
+    fn resolve<'a, S: AsRef<str>>(
+        &self,
+        program: &str,
+        args: &'a [S],
+    ) -> Result<(String, &'a [S]), AnalyzerError> {
+        resolve_command(|program| self.cache.resolve(program), program, args).ok_or_else(|| {
+            AnalyzerError::UnknownProgram {
+                program: program.to_string(),
+            }
+        })
+    }
+
+    fn load(&self, program: &str, config: &str) -> Result<Arc<ParserConfig>, AnalyzerError> {
+        self.cache
+            .get_config(config)
+            .map_err(|reason| config_not_found(program, reason))
+    }
+
+    #[cfg(feature = "server")]
+    async fn load_async(
+        &self,
+        program: &str,
+        config: &str,
+    ) -> Result<Arc<ParserConfig>, AnalyzerError> {
+        self.cache
+            .get_config_async(config)
+            .await
+            .map_err(|reason| config_not_found(program, reason))
+    }
+}
+
+/// How the configs of a parse are loaded. Parsing is written once, as async code, for both the
+/// sync and the async entry points of `Analyzer`: only loading differs.
+trait ConfigLoader: Sync {
+    fn load(
+        &self,
+        analyzer: &Analyzer,
+        program: &str,
+        config: &str,
+    ) -> impl Future<Output = Result<Arc<ParserConfig>, AnalyzerError>> + Send;
+}
+
+/// Loads configs on the calling thread, so that parsing never waits (see `run_in_place`)
+struct LoadInPlace;
+
+impl ConfigLoader for LoadInPlace {
+    fn load(
+        &self,
+        analyzer: &Analyzer,
+        program: &str,
+        config: &str,
+    ) -> impl Future<Output = Result<Arc<ParserConfig>, AnalyzerError>> + Send {
+        std::future::ready(analyzer.load(program, config))
+    }
+}
+
+/// Loads the configs that are not cached off the async runtime
+#[cfg(feature = "server")]
+struct LoadOffRuntime;
+
+#[cfg(feature = "server")]
+impl ConfigLoader for LoadOffRuntime {
+    fn load(
+        &self,
+        analyzer: &Analyzer,
+        program: &str,
+        config: &str,
+    ) -> impl Future<Output = Result<Arc<ParserConfig>, AnalyzerError>> + Send {
+        analyzer.load_async(program, config)
+    }
+}
+
+/// Run a parse loading its configs with `LoadInPlace` to completion
+fn run_in_place<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    match future
+        .as_mut()
+        .poll(&mut Context::from_waker(Waker::noop()))
+    {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("Parses loading their configs in place never wait"),
+    }
+}
+
+/// Name of the config of `program` according to `resolve`, and the arguments it applies to. For
+/// multi-call programs such as `busybox`, the applet is taken from `args`.
+fn resolve_command<'a, S: AsRef<str>>(
+    resolve: impl Fn(&str) -> Option<String>,
+    program: &str,
+    args: &'a [S],
+) -> Option<(String, &'a [S])> {
+    if let Some(config) = resolve(program) {
+        return Some((config, args));
+    }
+    match args.split_first() {
+        Some((applet, applet_args)) if registry::is_multi_call(program) => {
+            resolve(applet.as_ref()).map(|config| (config, applet_args))
+        }
+        _ => None,
+    }
+}
+
+/// The name and value of a variable assigned before a command
+type Assignment<'a> = (Cow<'a, str>, Cow<'a, str>);
+
+/// Variables assigned before the command, then the program and its arguments if there is a
+/// command after them. `env` (`/usr/bin/env`) is unwrapped wherever it runs a command, after its
+/// options: `env -i NAME=value command` is understood as `NAME=value command`.
+fn split_assignments<'p, 'a: 'p, S: AsRef<str>>(
+    program: &'p str,
+    args: &'a [S],
+) -> (Vec<Assignment<'a>>, Option<(&'p str, &'a [S])>) {
+    let mut assignments = vec![];
+    let mut args = match env_vars::split_assignment(program) {
+        Some((name, value)) => {
+            assignments.push((Cow::Owned(name.to_string()), Cow::Owned(value.to_string())));
+            args
+        }
+        None => match env_command(program, args) {
+            Some(args) => args,
+            None => return (assignments, Some((program, args))),
+        },
+    };
+    while let Some((first, rest)) = args.split_first() {
+        if let Some((name, value)) = env_vars::split_assignment(first.as_ref()) {
+            assignments.push((Cow::Borrowed(name), Cow::Borrowed(value)));
+            args = rest;
+        } else {
+            match env_command(first.as_ref(), rest) {
+                Some(env_args) => args = env_args,
+                None => return (assignments, Some((first.as_ref(), rest))),
+            }
+        }
+    }
+    (assignments, None)
+}
+
+/// The arguments of `env` after its options, if `program` is `env` running a command. `-S` is
+/// skipped with its string, which is not split into a command-line.
+fn env_command<'a, S: AsRef<str>>(program: &str, args: &'a [S]) -> Option<&'a [S]> {
+    let name = Path::new(program).file_name()?.to_str()?;
+    if name != "env" {
+        return None;
+    }
+    let mut idx = 0;
+    while let Some(arg) = args.get(idx).map(AsRef::as_ref) {
+        idx += 1;
+        match arg {
+            "--" => break,
+            "-" => {}
+            "--unset" | "--chdir" | "--split-string" => idx += 1,
+            _ if arg.starts_with("--") => {}
+            _ if arg.starts_with('-') => {
+                // Bundled flags, up to an option taking the rest of the argument or the next one
+                let flags = &arg[1..];
+                if let Some(pos) = flags.find(['u', 'C', 'S'])
+                    && pos + 1 == flags.len()
+                {
+                    idx += 1;
+                }
+            }
+            _ => {
+                idx -= 1;
+                break;
+            }
+        }
+    }
+    args.get(idx..).filter(|args| !args.is_empty())
+}
+
+/// The variables assigned before the command, without behaviours
+fn parsed_assignments<'a>(assignments: Vec<Assignment<'a>>) -> Vec<EnvAssignment<'a>> {
+    assignments
+        .into_iter()
+        .map(|(name, value)| EnvAssignment::new(name, value, true))
+        .collect()
+}
+
+/// The variables assigned before the command and, with `environment`, those of the environment
+/// of `context` that bring behaviours, with their behaviours: those of the config of the program,
+/// or else those of the global rules (see `env_vars`). Command-valued variables come with their
+/// value, to be analyzed.
+fn analyzed_assignments<'a>(
+    assignments: Vec<Assignment<'a>>,
+    context: &PathContext,
+    parser_config: Option<&ParserConfig>,
+    environment: bool,
+) -> Vec<(EnvAssignment<'a>, Option<Cow<'a, str>>)> {
+    let rule = |name: &str| {
+        parser_config
+            .and_then(|parser_config| parser_config.env_var(name))
+            .or_else(|| env_vars::global_rule(name))
+    };
+    let mut inherited: Vec<(&String, &String)> = context
+        .env
+        .iter()
+        .filter(|(name, _)| {
+            environment
+                && rule(name).is_some()
+                // Assignments before the command override the environment
+                && !assignments.iter().any(|(assigned, _)| assigned == *name)
+        })
+        .collect();
+    inherited.sort();
+    let inherited = inherited
+        .into_iter()
+        .map(|(name, value)| (Cow::Owned(name.clone()), Cow::Owned(value.clone()), false));
+
+    assignments
+        .into_iter()
+        .map(|(name, value)| (name, value, true))
+        .chain(inherited)
+        .map(|(name, value, inline)| {
+            let rule = rule(&name);
+            let command = rule.is_some_and(|rule| rule.command).then(|| value.clone());
+            let mut assignment = EnvAssignment::new(name, value, inline);
+            // A `file:` proxy reads a file whatever the variable
+            assignment.behaviours = rule
+                .map(|rule| rule.behaviours)
+                .unwrap_or_default()
+                .union(assignment.value.implied_behaviours());
+            (assignment, command)
+        })
+        .collect()
+}
+
+/// Put the variable assignments in front of the command-line, after the elements describing the
+/// whole parse
+fn with_assignments<'a>(
+    mut parsed_cmdline: Vec<CLElement<'a>>,
+    assignments: Vec<EnvAssignment<'a>>,
+) -> Vec<CLElement<'a>> {
+    let position = parsed_cmdline
+        .iter()
+        .take_while(|elem| {
+            matches!(
+                elem,
+                CLElement::CLHeuristic(_) | CLElement::CLInherentBehaviour(_)
+            )
+        })
+        .count();
+    parsed_cmdline.splice(
+        position..position,
+        assignments.into_iter().map(CLElement::CLEnvAssignment),
+    );
+    parsed_cmdline
+}
+
+fn owned_command(
+    behaviour_tree: Result<BehaviourTree<'_>, AnalyzerError>,
+) -> Vec<CLElement<'static>> {
+    match behaviour_tree {
+        Ok(behaviour_tree) => behaviour_tree
+            .into_iter()
+            .map(CLElement::into_owned)
+            .collect(),
+        Err(err) => vec![CLElement::ParsingError(Some(err.to_string()))],
+    }
+}
+
+fn config_not_found(program: &str, reason: String) -> AnalyzerError {
+    AnalyzerError::ConfigNotFound {
+        program: program.to_string(),
+        reason,
+    }
+}
+
+/// Replace every behaviour of `behaviour_tree` with its ancestor at `depth` in the taxonomy
+/// (0 being the most general level), merging the behaviours that end up identical
+pub fn roll_up_behaviour_tree(
+    behaviour_tree: BehaviourTree<'_>,
+    depth: usize,
+) -> BehaviourTree<'_> {
+    let roll_up = |behaviours: BehaviourSet| {
+        taxonomy::intern_behaviours(&taxonomy::roll_up(&behaviours, depth))
+    };
+    behaviour_tree
+        .into_iter()
+        .map(|elem| match elem {
+            CLElement::CLBehaviouredOption((name, behaviours, argument)) => {
+                CLElement::CLBehaviouredOption((name, roll_up(behaviours), argument))
+            }
+            CLElement::CLInherentBehaviour(behaviours) => {
+                CLElement::CLInherentBehaviour(roll_up(behaviours))
+            }
+            CLElement::CLBehaviouredArgument((argument, behaviours)) => {
+                CLElement::CLBehaviouredArgument((argument, roll_up(behaviours)))
+            }
+            CLElement::CLEnvAssignment(mut assignment) => {
+                assignment.behaviours = roll_up(assignment.behaviours);
+                assignment.command = assignment
+                    .command
+                    .map(|command| roll_up_behaviour_tree(command, depth));
+                CLElement::CLEnvAssignment(assignment)
+            }
+            elem => elem,
+        })
+        .collect()
+}
+
+/// Resolve the local paths of `behaviour_tree` in `context` (see `LocalPath::resolve`). The
+/// behaviours of a heuristic parse, which depend on where paths point to, are inferred again.
+pub fn resolve_behaviour_tree_paths<'a>(
+    mut behaviour_tree: BehaviourTree<'a>,
+    context: &PathContext,
+) -> BehaviourTree<'a> {
+    if context.is_empty() {
+        return behaviour_tree;
+    }
+    for elem in &mut behaviour_tree {
+        elem.resolve_paths(context);
+    }
+    if heuristic::is_heuristic(&behaviour_tree) {
+        heuristic::reenrich_heuristic(behaviour_tree)
+    } else {
+        behaviour_tree
+    }
+}
+
+/// Turns a parsed command-line into a behaviour tree: prepends the inherent behaviours of the
+/// program and replaces every `CLOption` with a `CLBehaviouredOption`. `Analyzer::analyze` does
+/// both in a single pass (see `parser::analyze_the_split`).
+pub fn enrich_parsed_cmdline<'a>(
+    parsed_cmdline: Vec<CLElement<'a>>,
+    parser_config: &ParserConfig,
+) -> Result<BehaviourTree<'a>, AnalyzerError> {
+    let mut enriched_parsed_cmdline: BehaviourTree = Vec::with_capacity(parsed_cmdline.len() + 1);
+
+    //Add the inherent behaviours of the program
+    let inherent_behaviours = parser_config.inherent_behaviours();
+    if !inherent_behaviours.is_empty() {
+        enriched_parsed_cmdline.push(CLElement::CLInherentBehaviour(inherent_behaviours));
+    }
+
+    for elem in parsed_cmdline {
+        //If elem is not a CLOption, just move it into the new vector
+        let new_element = match elem {
+            CLElement::CLOption((name, argument)) => {
+                //Let's get the behaviour of the option
+                let behaviours = parser_config.behaviours(&name).ok_or_else(|| {
+                    AnalyzerError::UnknownOption {
+                        program: parser_config.name.clone(),
+                        option: name.to_string(),
+                    }
+                })?;
+                CLElement::CLBehaviouredOption((name, behaviours, argument))
+            }
+            elem => elem,
+        };
+        enriched_parsed_cmdline.push(new_element);
+    }
+
+    Ok(enriched_parsed_cmdline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser_structs::{CLArgument, Host};
+
+    #[test]
+    fn nested_command_variables_are_analyzed_up_to_a_depth() {
+        let program = format!("{}vi", "EDITOR=".repeat(18000));
+        let behaviour_tree = Analyzer::builtin().analyze(&program, &["ls"]).unwrap();
+
+        let mut depth = 0;
+        let mut elements = &behaviour_tree;
+        while let Some(CLElement::CLEnvAssignment(assignment)) = elements
+            .iter()
+            .find(|elem| matches!(elem, CLElement::CLEnvAssignment(_)))
+        {
+            assert_eq!(assignment.name, "EDITOR");
+            elements = assignment.command.as_ref().unwrap();
+            depth += 1;
+        }
+        assert_eq!(depth, MAX_COMMAND_DEPTH + 1);
+        assert!(matches!(
+            elements.as_slice(),
+            [CLElement::ParsingError(Some(_))]
+        ));
+    }
+
+    #[test]
+    fn ab_proxy_is_a_host_port() {
+        let args = ["-X", "proxy:8080", "http://a.com/"];
+        let parsed_cmdline = Analyzer::builtin().parse("ab", &args).unwrap();
+        assert!(matches!(
+            &parsed_cmdline[0],
+            CLElement::CLOption((name, Some(CLArgument::HostPort {
+                host: Host::Name(host),
+                port: 8080,
+            }))) if name == "X" && host == "proxy"
+        ));
+    }
+
+    #[test]
+    fn config_names_are_those_of_the_registry() {
+        let analyzer = Analyzer::builtin();
+        let config_name = |program: &str, args: &[&str]| analyzer.config_name(program, args);
+        assert_eq!(
+            config_name("/any/dir/tar", &["-xf", "a.tar"]).as_deref(),
+            Some("tar")
+        );
+        assert_eq!(config_name("tar@anything", &[]).as_deref(), Some("tar"));
+        assert_eq!(config_name("bsdtar", &[]).as_deref(), Some("tar@bsd"));
+        assert_eq!(
+            config_name("python3.123456", &[]).as_deref(),
+            Some("python3")
+        );
+        assert_eq!(config_name("env", &["A=1", "ls"]).as_deref(), Some("ls"));
+        assert_eq!(config_name("env", &["ls"]).as_deref(), Some("ls"));
+        assert_eq!(config_name("env", &[]), None);
+        assert_eq!(config_name("busybox", &["ls", "-l"]).as_deref(), Some("ls"));
+        assert_eq!(config_name("no-such-program", &[]), None);
+        assert_eq!(config_name("A=1", &[]), None);
+    }
+
+    #[test]
+    fn env_is_unwrapped() {
+        let analyzer = Analyzer::builtin();
+        let commands: [&[&str]; 11] = [
+            &["env", "LD_PRELOAD=/tmp/x.so", "ls"],
+            &["env", "-i", "LD_PRELOAD=/tmp/x.so", "ls"],
+            &["env", "-0", "-i", "LD_PRELOAD=/tmp/x.so", "ls"],
+            &["env", "-u", "FOO", "LD_PRELOAD=/tmp/x.so", "ls"],
+            &["env", "-uFOO", "--unset=BAR", "LD_PRELOAD=/tmp/x.so", "ls"],
+            &["env", "-iC", "/tmp", "LD_PRELOAD=/tmp/x.so", "ls"],
+            &["env", "--chdir", "/tmp", "LD_PRELOAD=/tmp/x.so", "ls"],
+            &["env", "-S", "-v", "-", "LD_PRELOAD=/tmp/x.so", "ls"],
+            &["env", "--", "LD_PRELOAD=/tmp/x.so", "ls"],
+            &["/usr/bin/env", "LD_PRELOAD=/tmp/x.so", "ls"],
+            &["LD_PRELOAD=/tmp/x.so", "env", "-i", "A=1", "env", "ls"],
+        ];
+        for command in commands {
+            let (program, args) = command.split_first().unwrap();
+            assert_eq!(
+                analyzer.config_name(program, args).as_deref(),
+                Some("ls"),
+                "{command:?}"
+            );
+            let behaviour_tree = analyzer.analyze(program, args).unwrap();
+            let ld_preload = behaviour_tree.iter().find_map(|elem| match elem {
+                CLElement::CLEnvAssignment(assignment) if assignment.name == "LD_PRELOAD" => {
+                    Some(assignment)
+                }
+                _ => None,
+            });
+            let behaviours: Vec<&str> = ld_preload
+                .unwrap_or_else(|| panic!("{command:?}: {behaviour_tree:?}"))
+                .behaviours
+                .iter()
+                .map(|b| b.name())
+                .collect();
+            assert_eq!(behaviours, ["CMD_EXEC"], "{command:?}");
+        }
+    }
+}
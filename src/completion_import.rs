@@ -0,0 +1,683 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Extraction of option definitions from shell completion scripts: bash (`compgen -W` word lists
+//! and `case "$prev"` branches), zsh (`_arguments` specs) and fish (`complete -c` lines). The
+//! options are rendered, or merged into existing configs, by `config_gen`.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+use crate::config_gen::{OptionArg, OptionSpec};
+
+/// Name given to file arguments, as opposed to other values
+const FILE_ARG: &str = "FILE";
+const VALUE_ARG: &str = "VALUE";
+
+/// Shells whose completion scripts can be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    /// Guess the shell a completion script is written for
+    pub fn detect(script: &str) -> Shell {
+        if script
+            .lines()
+            .any(|line| line.trim_start().starts_with("complete -c"))
+        {
+            Shell::Fish
+        } else if script.starts_with("#compdef") || script.contains("_arguments") {
+            Shell::Zsh
+        } else {
+            Shell::Bash
+        }
+    }
+}
+
+/// Options of the program completed by `script`, and the name of the program when the script
+/// gives it
+pub fn import_completion(script: &str, shell: Shell) -> (Option<String>, Vec<OptionSpec>) {
+    match shell {
+        Shell::Bash => parse_bash(script),
+        Shell::Zsh => parse_zsh(script),
+        Shell::Fish => parse_fish(script),
+    }
+}
+
+/// fish: `complete -c tar -s f -l file -r -F -d 'Archive file'`
+fn parse_fish(script: &str) -> (Option<String>, Vec<OptionSpec>) {
+    let mut program = None;
+    let mut options = vec![];
+    for line in logical_lines(script) {
+        let words = shell_words(&line);
+        if words.first().map(String::as_str) != Some("complete") {
+            continue;
+        }
+        let mut option = empty_option();
+        let (mut requires_arg, mut no_files, mut force_files) = (false, false, false);
+        let mut condition = None;
+        let mut words = words.into_iter().skip(1);
+        while let Some(word) = words.next() {
+            match word.as_str() {
+                "-c" | "--command" => {
+                    if let Some(command) = words.next() {
+                        program.get_or_insert(command);
+                    }
+                }
+                "-s" | "--short-option" => {
+                    if let Some(name) = words.next().and_then(|name| single_char(&name)) {
+                        option.char_names.push(name);
+                    }
+                }
+                "-l" | "--long-option" => option.string_names.extend(words.next()),
+                // Single-dash long options can't be described by a config
+                "-o" | "--old-option" | "-w" | "--wraps" => {
+                    words.next();
+                }
+                "-a" | "--arguments" => {
+                    let arguments = words.next().unwrap_or_default();
+                    force_files |= arguments.contains("__fish_complete_directories")
+                        || arguments.contains("__fish_complete_path");
+                }
+                "-d" | "--description" => option.description = words.next(),
+                "-n" | "--condition" => condition = words.next(),
+                "-r" | "--require-parameter" => requires_arg = true,
+                "-f" | "--no-files" => no_files = true,
+                "-F" | "--force-files" => force_files = true,
+                "-x" | "--exclusive" => {
+                    requires_arg = true;
+                    no_files = true;
+                }
+                _ => {}
+            }
+        }
+        if requires_arg {
+            let arg = if force_files || !no_files {
+                FILE_ARG
+            } else {
+                VALUE_ARG
+            };
+            option.arg = OptionArg::Required(arg.to_string());
+        }
+        // Options of subcommands are kept, noting the subcommand
+        if let Some(subcommand) = condition.as_deref().and_then(fish_subcommand) {
+            let description = option.description.take().unwrap_or_default();
+            option.description = Some(format!("({subcommand}) {description}").trim().to_string());
+        }
+        push_option(&mut options, option);
+    }
+    (program, options)
+}
+
+fn fish_subcommand(condition: &str) -> Option<String> {
+    condition
+        .split_once("__fish_seen_subcommand_from")
+        .and_then(|(_, rest)| rest.split_whitespace().next())
+        .map(|subcommand| subcommand.trim_matches(['\'', '"', ';']).to_string())
+}
+
+/// zsh: `_arguments` specs such as `'(-f --file)'{-f+,--file=}'[use archive file]:archive:_files'`
+fn parse_zsh(script: &str) -> (Option<String>, Vec<OptionSpec>) {
+    let program = script
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("#compdef"))
+        .and_then(|names| names.split_whitespace().find(|name| !name.starts_with('-')))
+        .map(str::to_string);
+
+    let mut options = vec![];
+    for line in logical_lines(script) {
+        let words = shell_word_alternatives(&line);
+        let Some(start) = words.iter().position(|word| word == &["_arguments"]) else {
+            continue;
+        };
+        // Flags of `_arguments` itself come before the specs
+        let mut specs = words[start + 1..].iter();
+        let mut specs_start = specs.clone();
+        while let Some(word) = specs.next() {
+            match word.join(" ").as_str() {
+                "-A" | "-O" | "-M" => {
+                    specs.next();
+                }
+                "-s" | "-S" | "-C" | "-w" | "-W" | "-n" | "-0" | "-R" | "--" => {}
+                _ => break,
+            }
+            specs_start = specs.clone();
+        }
+        // The specs of a brace expansion are the names of the same option
+        for alternatives in specs_start {
+            let specs: Vec<OptionSpec> = alternatives
+                .iter()
+                .filter_map(|spec| parse_zsh_spec(spec))
+                .collect();
+            if !specs.is_empty() {
+                options.push(merge_aliases(specs));
+            }
+        }
+    }
+    let mut unique = vec![];
+    for option in options {
+        push_option(&mut unique, option);
+    }
+    (program, unique)
+}
+
+/// Parse a single `_arguments` option spec (`-f+[description]:message:action`)
+fn parse_zsh_spec(spec: &str) -> Option<OptionSpec> {
+    // Exclusion list, then `*` for repeatable options
+    let spec = match spec.strip_prefix('(') {
+        Some(rest) => rest.split_once(')')?.1,
+        None => spec,
+    };
+    let spec = spec.trim_start_matches('*');
+    if !spec.starts_with('-') || spec.len() < 2 {
+        return None;
+    }
+
+    let name_end = spec.find(['[', ':']).unwrap_or(spec.len());
+    let (name, rest) = spec.split_at(name_end);
+    let (description, arg_spec) = match rest.strip_prefix('[') {
+        Some(rest) => {
+            let (description, arg_spec) = rest.split_once(']').unwrap_or((rest, ""));
+            (Some(description.to_string()), arg_spec)
+        }
+        None => (None, rest),
+    };
+
+    // `=-` and `-` suffixes: the argument can only be attached to the option
+    let (name, attached_only) = match name.strip_suffix("=-") {
+        Some(name) => (name, true),
+        None => match name.strip_suffix('-').filter(|name| name.len() > 1) {
+            Some(name) if !name.ends_with('-') => (name, true),
+            _ => (name.trim_end_matches(['+', '=']), false),
+        },
+    };
+
+    let mut option = empty_option();
+    option.description = description;
+    if let Some(long) = name.strip_prefix("--") {
+        option.string_names.push(long.to_string());
+    } else {
+        option
+            .char_names
+            .push(single_char(name.strip_prefix('-')?)?);
+    }
+    // `:message:action` is a mandatory argument, `::message:action` an optional one
+    if let Some(arg) = arg_spec.strip_prefix(':') {
+        let is_file = arg.contains("_files") || arg.contains("_directories");
+        let name = if is_file { FILE_ARG } else { VALUE_ARG }.to_string();
+        option.arg = if arg.starts_with(':') || attached_only {
+            OptionArg::Optional(name)
+        } else {
+            OptionArg::Required(name)
+        };
+    }
+    option.synopsis = synopsis(&option);
+    Some(option)
+}
+
+/// bash: option words of `compgen -W` lists and `*opts=` assignments, and the `-f|--file)`
+/// branches of `case "$prev"`, which complete the argument of those options
+fn parse_bash(script: &str) -> (Option<String>, Vec<OptionSpec>) {
+    static COMPLETE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^\s*complete\b.*\s([\w.+-]+)\s*$").unwrap());
+    static WORD_LIST: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"(?s)(?:compgen\s+-W|\w*(?i:opts|options)\w*\+?=)\s*(?:"([^"]*)"|'([^']*)')"#)
+            .unwrap()
+    });
+    static CASE_BRANCH: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"(?m)^\s*((?:['"]?--?[\w-]+['"]?\|?)+)\)(.*)$"#).unwrap());
+    static OPTION_WORD: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^(--?[A-Za-z0-9][\w-]*)(=?)$").unwrap());
+
+    let program = script
+        .lines()
+        .filter_map(|line| COMPLETE.captures(line))
+        .map(|captures| captures[1].to_string())
+        .next_back();
+
+    let mut options: Vec<OptionSpec> = vec![];
+    for captures in WORD_LIST.captures_iter(script) {
+        let list = captures
+            .get(1)
+            .or(captures.get(2))
+            .map_or("", |m| m.as_str());
+        // Words computed by a command are not options of the program
+        if list.contains("$(") || list.contains('`') {
+            continue;
+        }
+        for word in list.split_whitespace() {
+            let Some(word) = OPTION_WORD.captures(word) else {
+                continue;
+            };
+            let mut option = name_option(&word[1]);
+            // `--file=` completes with the `=` for options taking an argument
+            if !word[2].is_empty() {
+                option.arg = OptionArg::Required(VALUE_ARG.to_string());
+            }
+            if !option.char_names.is_empty() || !option.string_names.is_empty() {
+                option.synopsis = synopsis(&option);
+                push_option(&mut options, option);
+            }
+        }
+    }
+
+    let lines: Vec<&str> = script.lines().collect();
+    for (idx, line) in lines.iter().enumerate() {
+        let Some(captures) = CASE_BRANCH.captures(line) else {
+            continue;
+        };
+        // Body of the branch, up to its `;;`
+        let mut body = vec![];
+        for line in std::iter::once(&captures[2]).chain(lines[idx + 1..].iter().copied()) {
+            match line.split_once(";;") {
+                Some((last, _)) => {
+                    body.push(last);
+                    break;
+                }
+                None => body.push(line),
+            }
+        }
+        // `-h|--help) return 0 ;;` completes nothing after the option, which takes no argument
+        let takes_arg = body
+            .iter()
+            .flat_map(|line| line.split(';'))
+            .map(str::trim)
+            .any(|statement| !matches!(statement, "" | "return" | "return 0"));
+        let body = body.join("\n");
+        let is_file = ["_filedir", "compgen -f", "compgen -d", "_cd"]
+            .iter()
+            .any(|marker| body.contains(marker));
+
+        let arg = takes_arg.then_some(if is_file { FILE_ARG } else { VALUE_ARG });
+
+        // `-o|--output)` names a single option, `-h|--help|-V|--version)` two of them: a short
+        // (or long) name following another one starts a new option
+        let mut aliases: Vec<OptionSpec> = vec![];
+        for name in captures[1].split('|') {
+            let option = name_option(name.trim_matches(['\'', '"']));
+            let starts_option = aliases.iter().any(|alias| {
+                (!alias.char_names.is_empty() && !option.char_names.is_empty())
+                    || (!alias.string_names.is_empty() && !option.string_names.is_empty())
+            });
+            if starts_option {
+                add_branch_option(&mut options, std::mem::take(&mut aliases), arg);
+            }
+            if !option.char_names.is_empty() || !option.string_names.is_empty() {
+                aliases.push(option);
+            }
+        }
+        add_branch_option(&mut options, aliases, arg);
+    }
+    (program, options)
+}
+
+/// Add the option named by `aliases` in a `case "$prev"` branch, merging the options already
+/// listed under any of these names into it. It takes `arg` if the branch completes one.
+fn add_branch_option(
+    options: &mut Vec<OptionSpec>,
+    mut aliases: Vec<OptionSpec>,
+    arg: Option<&str>,
+) {
+    if aliases.is_empty() {
+        return;
+    }
+    let shares_name = |known: &OptionSpec| {
+        aliases.iter().any(|alias| {
+            alias
+                .char_names
+                .iter()
+                .any(|name| known.char_names.contains(name))
+                || (alias.string_names.iter()).any(|name| known.string_names.contains(name))
+        })
+    };
+    let position = options
+        .iter()
+        .position(shares_name)
+        .unwrap_or(options.len());
+    let known: Vec<OptionSpec> = options.extract_if(.., |known| shares_name(known)).collect();
+    aliases.splice(0..0, known);
+    let mut option = merge_aliases(aliases);
+    dedup(&mut option.char_names);
+    dedup(&mut option.string_names);
+    if let Some(arg) = arg {
+        option.arg = OptionArg::Required(arg.to_string());
+    }
+    option.synopsis = synopsis(&option);
+    options.insert(position, option);
+}
+
+fn empty_option() -> OptionSpec {
+    OptionSpec {
+        char_names: vec![],
+        string_names: vec![],
+        arg: OptionArg::None,
+        synopsis: String::new(),
+        description: None,
+    }
+}
+
+/// Option named `-f` or `--file`
+fn name_option(name: &str) -> OptionSpec {
+    let mut option = empty_option();
+    if let Some(long) = name.strip_prefix("--") {
+        option.string_names.push(long.to_string());
+    } else if let Some(short) = name.strip_prefix('-').and_then(single_char) {
+        option.char_names.push(short);
+    }
+    option
+}
+
+/// Several names (`-f` and `--file`) of the same option
+fn merge_aliases(specs: Vec<OptionSpec>) -> OptionSpec {
+    let mut merged = empty_option();
+    for spec in specs {
+        merged.char_names.extend(spec.char_names);
+        merged.string_names.extend(spec.string_names);
+        if merged.arg == OptionArg::None {
+            merged.arg = spec.arg;
+        }
+        merged.description = merged.description.or(spec.description);
+    }
+    merged.synopsis = synopsis(&merged);
+    merged
+}
+
+/// Drop the repeated names of `names`, keeping the first occurrences
+fn dedup<T: PartialEq>(names: &mut Vec<T>) {
+    let mut idx = 0;
+    while idx < names.len() {
+        if names[..idx].contains(&names[idx]) {
+            names.remove(idx);
+        } else {
+            idx += 1;
+        }
+    }
+}
+
+/// Add `option` unless it has no name, dropping the names already known
+fn push_option(options: &mut Vec<OptionSpec>, mut option: OptionSpec) {
+    option
+        .char_names
+        .retain(|name| !options.iter().any(|other| other.char_names.contains(name)));
+    option.string_names.retain(|name| {
+        !options
+            .iter()
+            .any(|other| other.string_names.contains(name))
+    });
+    if option.char_names.is_empty() && option.string_names.is_empty() {
+        return;
+    }
+    option.synopsis = synopsis(&option);
+    options.push(option);
+}
+
+/// `-f, --file FILE`, as `--help` would document it
+fn synopsis(option: &OptionSpec) -> String {
+    let names: Vec<String> = option
+        .char_names
+        .iter()
+        .map(|name| format!("-{name}"))
+        .chain(option.string_names.iter().map(|name| format!("--{name}")))
+        .collect();
+    match &option.arg {
+        OptionArg::None => names.join(", "),
+        OptionArg::Required(arg) => format!("{} {}", names.join(", "), arg),
+        OptionArg::Optional(arg) => format!("{} [{}]", names.join(", "), arg),
+    }
+}
+
+fn single_char(name: &str) -> Option<char> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// Lines of `script`, joining those continued with a trailing backslash
+fn logical_lines(script: &str) -> Vec<String> {
+    let mut lines = vec![];
+    let mut current = String::new();
+    for line in script.lines() {
+        match line.strip_suffix('\\') {
+            Some(continued) => {
+                current.push_str(continued);
+                current.push(' ');
+            }
+            None => {
+                current.push_str(line);
+                lines.push(std::mem::take(&mut current));
+            }
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Split a shell command line into words: quotes are removed, unquoted `{a,b}` braces are
+/// expanded, and everything after an unquoted `#` is a comment
+pub(crate) fn shell_words(line: &str) -> Vec<String> {
+    shell_word_alternatives(line)
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Words of a shell command line, each made of the alternatives of its brace expansions
+fn shell_word_alternatives(line: &str) -> Vec<Vec<String>> {
+    // Each word is a list of alternatives, multiplied by every brace expansion
+    let mut words: Vec<Vec<String>> = vec![];
+    let mut current: Vec<String> = vec![String::new()];
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+
+    let push_str = |current: &mut Vec<String>, s: &str| {
+        for alternative in current.iter_mut() {
+            alternative.push_str(s);
+        }
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | ';' | '(' | ')' if !in_word || c == ' ' || c == '\t' => {
+                if in_word {
+                    words.push(std::mem::replace(&mut current, vec![String::new()]));
+                    in_word = false;
+                }
+                if matches!(c, '(' | ')') {
+                    // Subshells and arrays: `opts=( ... )`
+                    continue;
+                }
+            }
+            '#' if !in_word => break,
+            '\'' => {
+                let quoted: String = chars.by_ref().take_while(|c| *c != '\'').collect();
+                push_str(&mut current, &quoted);
+                in_word = true;
+            }
+            '"' => {
+                let mut quoted = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => quoted.extend(chars.next()),
+                        c => quoted.push(c),
+                    }
+                }
+                push_str(&mut current, &quoted);
+                in_word = true;
+            }
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    push_str(&mut current, &escaped.to_string());
+                }
+                in_word = true;
+            }
+            '{' => {
+                let mut group = String::new();
+                let mut depth = 1;
+                for c in chars.by_ref() {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                    group.push(c);
+                }
+                if group.contains(',') {
+                    current = current
+                        .iter()
+                        .flat_map(|prefix| {
+                            group
+                                .split(',')
+                                .map(move |alternative| format!("{prefix}{alternative}"))
+                        })
+                        .collect();
+                } else {
+                    push_str(&mut current, &format!("{{{group}}}"));
+                }
+                in_word = true;
+            }
+            c => {
+                push_str(&mut current, &c.to_string());
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(current);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// util-linux's completion of `blkid`
+    const BLKID: &str = include_str!("../tests/fixtures/completions/blkid.bash");
+
+    fn synopses(options: &[OptionSpec]) -> Vec<&str> {
+        options
+            .iter()
+            .map(|option| option.synopsis.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn bash_blkid() {
+        assert_eq!(Shell::detect(BLKID), Shell::Bash);
+        let (program, options) = import_completion(BLKID, Shell::Bash);
+        assert_eq!(program.as_deref(), Some("blkid"));
+        assert_eq!(
+            synopses(&options),
+            [
+                "-c, --cache-file FILE",
+                "--no-encoding",
+                "--garbage-collect",
+                "-o, --output VALUE",
+                "--list-filesystems",
+                "-s, --match-tag VALUE",
+                "-t, --match-token VALUE",
+                "--list-one",
+                "-L, --label VALUE",
+                "-U, --uuid VALUE",
+                "--probe",
+                "--info",
+                "-S, --size VALUE",
+                "-O, --offset VALUE",
+                "-u, --usages VALUE",
+                "-n, --match-types VALUE",
+                "--no-part-details",
+                "-h, --help",
+                "-V, --version",
+            ]
+        );
+    }
+
+    #[test]
+    fn bash_branches_returning_right_away_are_flags() {
+        let script = r#"
+_foo()
+{
+    case "$prev" in
+        -q|--quiet) return 0 ;;
+        -x) return ;;
+        -f|--file) _filedir ;;
+        --level) COMPREPLY=( $(compgen -W "1 2 3" -- "$cur") ) ;;
+    esac
+    COMPREPLY=( $(compgen -W '-q -x -f --level= --verbose' -- "$cur") )
+}
+complete -F _foo foo
+"#;
+        let (program, options) = import_completion(script, Shell::Bash);
+        assert_eq!(program.as_deref(), Some("foo"));
+        assert_eq!(
+            synopses(&options),
+            [
+                "-q, --quiet",
+                "-x",
+                "-f, --file FILE",
+                "--level VALUE",
+                "--verbose"
+            ]
+        );
+    }
+
+    #[test]
+    fn zsh_arguments() {
+        let script = r#"#compdef foo
+_arguments \
+  '(-f --file)'{-f+,--file=}'[archive file]:file:_files' \
+  '-v[verbose output]'
+"#;
+        assert_eq!(Shell::detect(script), Shell::Zsh);
+        let (_, options) = import_completion(script, Shell::Zsh);
+        assert_eq!(synopses(&options), ["-f, --file FILE", "-v"]);
+        assert_eq!(options[1].description.as_deref(), Some("verbose output"));
+    }
+
+    #[test]
+    fn fish_complete_lines() {
+        let script = "complete -c foo -s f -l file -r -F -d 'Archive file'\n\
+                      complete -c foo -s v -d 'Verbose'\n";
+        assert_eq!(Shell::detect(script), Shell::Fish);
+        let (program, options) = import_completion(script, Shell::Fish);
+        assert_eq!(program.as_deref(), Some("foo"));
+        assert_eq!(synopses(&options), ["-f, --file FILE", "-v"]);
+    }
+
+    #[test]
+    fn shell_words_unquote_and_expand_braces() {
+        assert_eq!(
+            shell_words(r#"ssh -o 'ProxyCommand nc %h' "$host" # comment"#),
+            ["ssh", "-o", "ProxyCommand nc %h", "$host"]
+        );
+        assert_eq!(shell_words("{-f,--file}"), ["-f", "--file"]);
+    }
+}
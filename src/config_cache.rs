@@ -1,63 +1,475 @@
+use crate::config_lint;
+use crate::config_store::ConfigStore;
 use crate::parser_config::ParserConfig;
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use crate::registry::ProgramRegistry;
+use crate::taxonomy::Taxonomy;
+use arc_swap::ArcSwap;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-// Cache type for storing ParserConfig instances (wrapped in Arc)
-pub type ConfigCache = Arc<RwLock<HashMap<String, Arc<ParserConfig>>>>;
+// Cache type for storing ParserConfig instances. Requests read a snapshot of it without locking,
+// loads replace the snapshot.
+type ConfigCache = Arc<ArcSwap<HashMap<Arc<str>, Arc<CacheEntry>>>>;
+
+/// A cached config, with the last time it was used, for eviction
+struct CacheEntry {
+    config: Arc<ParserConfig>,
+    last_used: AtomicU64,
+}
+
+/// Load state of a program config, as reported by the admin API
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigStatus {
+    pub program: String,
+    /// Hash of the contents of the config currently in use, including its bases and fragments
+    pub version: Option<String>,
+    /// Files the config currently in use was built from
+    pub sources: Vec<PathBuf>,
+    /// When the config currently in use was loaded (seconds since the Unix epoch)
+    pub loaded_at: Option<u64>,
+    /// Number of times the config was (re)loaded
+    pub loads: u64,
+    /// Error of the last load attempt, if it failed. The previous config, if any, stays in use.
+    pub last_error: Option<String>,
+}
+
+/// Maximum number of unknown programs remembered between two scans of the config directory
+const MAX_UNKNOWN_PROGRAMS: usize = 4096;
 
 // Caching interface for ParserConfig
 #[derive(Clone)]
 pub struct ParserConfigCache {
     cache: ConfigCache,
+    /// Maximum number of cached configs, the least recently used ones being evicted
+    capacity: Option<usize>,
+    /// Clock of `CacheEntry::last_used`
+    clock: Arc<AtomicU64>,
+    statuses: Arc<RwLock<HashMap<String, ConfigStatus>>>,
+    registry: Arc<ArcSwap<ProgramRegistry>>,
+    /// Programs that resolved to no config since the last scan
+    unknown_programs: Arc<RwLock<HashSet<String>>>,
+    store: Arc<ConfigStore>,
+    /// Taxonomy the behaviours of the configs belong to
+    taxonomy: &'static Taxonomy,
 }
 
 impl ParserConfigCache {
-    pub fn new() -> Self {
+    /// Create an empty cache loading configs from `store`
+    pub fn new(store: ConfigStore) -> Self {
         Self {
-            cache: Arc::new(RwLock::new(HashMap::new())),
+            cache: Default::default(),
+            capacity: None,
+            clock: Default::default(),
+            statuses: Default::default(),
+            registry: Arc::new(ArcSwap::from_pointee(ProgramRegistry::scan(&store))),
+            unknown_programs: Default::default(),
+            store: Arc::new(store),
+            taxonomy: Taxonomy::builtin(),
+        }
+    }
+
+    /// Resolve the behaviours of the configs in `taxonomy` instead of the built-in one. The
+    /// configs loaded so far are dropped.
+    pub fn with_taxonomy(mut self, taxonomy: &'static Taxonomy) -> Self {
+        self.cache = Default::default();
+        self.statuses = Default::default();
+        self.taxonomy = taxonomy;
+        self
+    }
+
+    /// Taxonomy the behaviours of the configs belong to
+    pub fn taxonomy(&self) -> &'static Taxonomy {
+        self.taxonomy
+    }
+
+    /// Keep at most `capacity` (at least one) configs in the cache, evicting the least recently
+    /// used ones. Evicted configs are loaded again when they are needed.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity.max(1));
+        self
+    }
+
+    /// Where the configs are loaded from
+    pub fn store(&self) -> &ConfigStore {
+        &self.store
+    }
+
+    /// Name of the config to use for `program` (see `ProgramRegistry::resolve`)
+    pub fn resolve(&self, program: &str) -> Option<String> {
+        if read(&self.unknown_programs).contains(program) {
+            metrics::counter!("ucp_unknown_programs_total").increment(1);
+            return None;
+        }
+        let config = self.registry.load().resolve(program).map(str::to_string);
+        if config.is_none() {
+            metrics::counter!("ucp_unknown_programs_total").increment(1);
+            let mut unknown_programs = write(&self.unknown_programs);
+            // Bounded, as request contents are arbitrary
+            if unknown_programs.len() < MAX_UNKNOWN_PROGRAMS {
+                unknown_programs.insert(program.to_string());
+            }
         }
+        config
+    }
+
+    /// The program registry of the config store
+    pub fn registry(&self) -> Arc<ProgramRegistry> {
+        self.registry.load_full()
+    }
+
+    /// Re-index the config store, to take added, removed or renamed configs into account
+    pub fn rescan(&self) {
+        let registry = ProgramRegistry::scan(&self.store);
+        tracing::debug!(
+            configs = registry.entries().len(),
+            "Config directory indexed"
+        );
+        self.registry.store(Arc::new(registry));
+        write(&self.unknown_programs).clear();
     }
 
-    /// Get a ParserConfig by program name.
-    /// If not in cache, load from filesystem and cache it.
+    /// Get a ParserConfig by config name (see `resolve`).
+    /// If not in cache, load it from the file indexed for it and cache it.
     pub fn get_config(&self, program: &str) -> Result<Arc<ParserConfig>, String> {
         // First, try to read from cache
-        {
-            let cache = self.cache.read().unwrap();
-            if let Some(config) = cache.get(program) {
-                tracing::debug!(program = %program, "Config loaded from cache");
-                return Ok(config.clone());
-            }
+        if let Some(config) = self.cached(program) {
+            return Ok(config);
         }
 
         // Not in cache, load from filesystem
         tracing::debug!(program = %program, "Config not in cache, loading from filesystem");
-        let config_path = format!("configs/{}.toml", program);
-        let config = ParserConfig::from_toml_file(&config_path)?;
+        metrics::counter!("ucp_config_cache_misses_total").increment(1);
+        self.load(program)
+    }
+
+    /// `get_config` for async code: configs that are not cached are loaded on the blocking
+    /// thread pool, so that file reads and parsing do not stall the runtime
+    #[cfg(feature = "server")]
+    pub async fn get_config_async(&self, program: &str) -> Result<Arc<ParserConfig>, String> {
+        if let Some(config) = self.cached(program) {
+            return Ok(config);
+        }
+        let cache = self.clone();
+        let program = program.to_string();
+        tokio::task::spawn_blocking(move || cache.get_config(&program))
+            .await
+            .map_err(|err| format!("Failed to load the config: {err}"))?
+    }
+
+    /// Load every config of the store, up to the capacity of the cache, returning the configs
+    /// that failed to load
+    pub fn preload(&self) -> Vec<(String, String)> {
+        let registry = self.registry();
+        let entries = registry.entries();
+        let count = self
+            .capacity
+            .map_or(entries.len(), |capacity| capacity.min(entries.len()));
+        if count < entries.len() {
+            tracing::warn!(
+                configs = entries.len(),
+                capacity = count,
+                "More configs than the cache can hold, only preloading some of them"
+            );
+        }
+        let errors: Vec<(String, String)> = entries[..count]
+            .iter()
+            .filter_map(|entry| {
+                self.load(&entry.config)
+                    .err()
+                    .map(|err| (entry.config.clone(), err))
+            })
+            .collect();
+        tracing::info!(
+            configs = count - errors.len(),
+            errors = errors.len(),
+            "Configs preloaded"
+        );
+        errors
+    }
+
+    /// Reload the config of `program` from the filesystem and atomically swap it in.
+    /// On failure, the previous config stays in use and the error is recorded in its status.
+    /// Configs added since the last scan are only found once the store is scanned again (by the
+    /// watcher, `reload_all` or `rescan`), so that unknown names cannot trigger scans.
+    pub fn reload(&self, program: &str) -> Result<Arc<ParserConfig>, String> {
+        tracing::info!(program = %program, "Reloading config");
+        self.load(program).inspect_err(|err| {
+            tracing::error!(program = %program, error = %err, "Failed to reload config, keeping the previous one");
+        })
+    }
+
+    /// Reload every cached config, returning the programs that failed to reload
+    pub fn reload_all(&self) -> Vec<(String, String)> {
+        self.rescan();
+        let programs: Vec<String> = self
+            .cache
+            .load()
+            .keys()
+            .map(|key| key.to_string())
+            .collect();
+        programs
+            .into_iter()
+            .filter_map(|program| {
+                self.reload(&program)
+                    .err()
+                    .map(|err| (program.clone(), err))
+            })
+            .collect()
+    }
 
-        let arc = Arc::new(config);
+    /// Load state of every config that was loaded or failed to load, sorted by program
+    pub fn statuses(&self) -> Vec<ConfigStatus> {
+        let mut statuses: Vec<ConfigStatus> = read(&self.statuses).values().cloned().collect();
+        statuses.sort_by(|a, b| a.program.cmp(&b.program));
+        statuses
+    }
+
+    fn load(&self, program: &str) -> Result<Arc<ParserConfig>, String> {
+        let config_path = self
+            .registry
+            .load()
+            .get(program)
+            .map(|entry| entry.file.clone());
+        let Some(config_file) = config_path else {
+            let err = format!("Unknown program \"{}\"", program);
+            // Only track errors of configs that exist or existed, not of every requested name
+            if read(&self.statuses).contains_key(program) {
+                self.update_status(program, |status| status.last_error = Some(err.clone()));
+            }
+            return Err(err);
+        };
+
+        let loaded = self
+            .taxonomy
+            .enter(|| ParserConfig::from_store(&self.store, &config_file));
+        let (config, sources) = match loaded {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                self.update_status(program, |status| status.last_error = Some(err.clone()));
+                return Err(err);
+            }
+        };
+        let version = self.sources_version(&sources);
+
+        // Nothing to do if none of the files changed since the config was last loaded
+        let unchanged = self.version(program).as_deref() == Some(version.as_str());
+        if unchanged && let Some(entry) = self.cache.load().get(program) {
+            self.update_status(program, |status| status.last_error = None);
+            return Ok(entry.config.clone());
+        }
+
+        // Configs evicted from the cache were already linted
+        if !unchanged {
+            let config_path = &sources[0];
+            let contents = self.store.contents(config_path).unwrap_or_default();
+            let issues = self
+                .taxonomy
+                .enter(|| config_lint::lint_source(config_path, &contents));
+            for issue in issues {
+                tracing::warn!(program = %program, "{}", issue);
+            }
+        }
+        let config = Arc::new(config);
+
+        self.insert(program, config.clone());
+        self.update_status(program, |status| {
+            status.version = Some(version);
+            status.sources = sources;
+            status.loaded_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|elapsed| elapsed.as_secs());
+            status.loads += 1;
+            status.last_error = None;
+        });
+
+        Ok(config)
+    }
+
+    /// Version of a config: a hash of the contents of the files it is built from
+    fn sources_version(&self, sources: &[PathBuf]) -> String {
+        let mut hasher = DefaultHasher::new();
+        for source in sources {
+            source.hash(&mut hasher);
+            self.store
+                .contents(source)
+                .unwrap_or_default()
+                .hash(&mut hasher);
+        }
+        format!("{:016x}", hasher.finish())
+    }
+
+    fn version(&self, program: &str) -> Option<String> {
+        let statuses = read(&self.statuses);
+        statuses.get(program)?.version.clone()
+    }
+
+    /// The cached config of `program`, marked as used
+    fn cached(&self, program: &str) -> Option<Arc<ParserConfig>> {
+        let cache = self.cache.load();
+        let entry = cache.get(program)?;
+        entry.last_used.store(self.tick(), Ordering::Relaxed);
+        tracing::debug!(program = %program, "Config loaded from cache");
+        metrics::counter!("ucp_config_cache_hits_total").increment(1);
+        Some(entry.config.clone())
+    }
 
-        // Store in cache
-        {
-            let mut cache = self.cache.write().unwrap();
-            cache.insert(program.to_string(), arc.clone());
-            tracing::debug!(program = %program, cache_size = cache.len(), "Config cached");
+    /// Swap in a new snapshot of the cache holding `config`, evicting the least recently used
+    /// configs beyond the capacity
+    fn insert(&self, program: &str, config: Arc<ParserConfig>) {
+        let program: Arc<str> = program.into();
+        let mut evicted = vec![];
+        self.cache.rcu(|cache| {
+            let mut cache = HashMap::clone(cache);
+            cache.insert(
+                program.clone(),
+                Arc::new(CacheEntry {
+                    config: config.clone(),
+                    last_used: AtomicU64::new(self.tick()),
+                }),
+            );
+            evicted.clear();
+            while self.capacity.is_some_and(|capacity| cache.len() > capacity) {
+                let Some(lru) = cache
+                    .iter()
+                    .filter(|(name, _)| **name != program)
+                    .min_by_key(|(_, entry)| entry.last_used.load(Ordering::Relaxed))
+                    .map(|(name, _)| name.clone())
+                else {
+                    break;
+                };
+                cache.remove(&lru);
+                evicted.push(lru);
+            }
+            cache
+        });
+        let cache_size = self.cache.load().len();
+        for lru in &evicted {
+            tracing::debug!(program = %lru, "Config evicted from the cache");
         }
+        metrics::counter!("ucp_config_cache_evictions_total").increment(evicted.len() as u64);
+        tracing::debug!(program = %program, cache_size, "Config cached");
+        metrics::gauge!("ucp_config_cache_size").set(cache_size as f64);
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn update_status(&self, program: &str, update: impl FnOnce(&mut ConfigStatus)) {
+        let mut statuses = write(&self.statuses);
+        let status = statuses
+            .entry(program.to_string())
+            .or_insert_with(|| ConfigStatus {
+                program: program.to_string(),
+                version: None,
+                sources: vec![],
+                loaded_at: None,
+                loads: 0,
+                last_error: None,
+            });
+        update(status);
+    }
 
-        Ok(arc)
+    /// Cached programs whose config is built from one of `files` (relative to their layer)
+    #[cfg(feature = "hot-reload")]
+    fn programs_using(&self, files: &[PathBuf]) -> Vec<String> {
+        let cache = self.cache.load();
+        let statuses = read(&self.statuses);
+        cache
+            .keys()
+            .filter(|program| {
+                statuses.get::<str>(program).is_some_and(|status| {
+                    status.sources.iter().any(|source| {
+                        self.store
+                            .relative_name(source)
+                            .is_some_and(|source| files.contains(&source))
+                    })
+                })
+            })
+            .map(|program| program.to_string())
+            .collect()
+    }
+
+    /// Watch the config directory and reload cached configs when their file changes.
+    /// Watching stops when the returned watcher is dropped.
+    #[cfg(feature = "hot-reload")]
+    pub fn watch(&self) -> Result<notify::RecommendedWatcher, String> {
+        use crate::config_format::{ConfigFormat, format_variants};
+        use notify::{EventKind, RecursiveMode, Watcher};
+
+        let cache = self.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(err) => {
+                        tracing::error!(error = %err, "Config directory watch error");
+                        return;
+                    }
+                };
+                if !matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                ) {
+                    return;
+                }
+                // Config files by path relative to their overlay, so that a file shadowing
+                // another one (e.g. a built-in fragment, possibly in another format) counts as a
+                // change of the latter
+                let changed: Vec<PathBuf> = event
+                    .paths
+                    .iter()
+                    .filter(|path| ConfigFormat::from_path(path).is_some())
+                    .filter_map(|path| cache.store.relative_name(path))
+                    .flat_map(|name| format_variants(&name))
+                    .collect();
+                if changed.is_empty() {
+                    return;
+                }
+                // Aliases, dialects or whole configs may have changed
+                cache.rescan();
+                // Reload the cached configs built from a changed file (themselves, a base or a
+                // fragment). Configs that were never requested will be loaded on demand anyway.
+                for program in cache.programs_using(&changed) {
+                    let _ = cache.reload(&program);
+                }
+            })
+            .map_err(|e| e.to_string())?;
+        for overlay in self.store.overlays() {
+            watcher
+                .watch(overlay, RecursiveMode::Recursive)
+                .map_err(|e| format!("{}: {}", overlay.display(), e))?;
+            tracing::info!(config_dir = %overlay.display(), "Watching config directory");
+        }
+        Ok(watcher)
     }
 
     /// Clear the cache (useful for testing or cache invalidation)
     #[allow(dead_code)]
     pub fn clear(&self) {
-        let mut cache = self.cache.write().unwrap();
-        cache.clear();
+        self.cache.store(Default::default());
+        metrics::gauge!("ucp_config_cache_size").set(0.0);
     }
 
     /// Get cache size (useful for monitoring)
     #[allow(dead_code)]
     pub fn size(&self) -> usize {
-        let cache = self.cache.read().unwrap();
-        cache.len()
+        self.cache.load().len()
     }
 }
+
+// The data behind the locks is left consistent by every holder, a panic while holding one must
+// not take every later request down with it
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,121 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Formats config files can be written in. The format of a file is given by its extension, and
+//! every format describes the same `ConfigFile`: only the syntax differs.
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+    Yaml,
+}
+
+/// Every format, by order of precedence when a program has configs in several formats
+pub const CONFIG_FORMATS: [ConfigFormat; 3] =
+    [ConfigFormat::Toml, ConfigFormat::Json, ConfigFormat::Yaml];
+
+impl ConfigFormat {
+    /// The format of a file with this extension
+    pub fn from_extension(extension: &str) -> Option<ConfigFormat> {
+        match extension {
+            "toml" => Some(ConfigFormat::Toml),
+            "json" => Some(ConfigFormat::Json),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    /// The format of the config file at `path`, if it is a config file
+    pub fn from_path(path: &Path) -> Option<ConfigFormat> {
+        ConfigFormat::from_extension(path.extension()?.to_str()?)
+    }
+
+    /// Extensions of the files of this format, the preferred one first
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            ConfigFormat::Toml => &["toml"],
+            ConfigFormat::Json => &["json"],
+            ConfigFormat::Yaml => &["yaml", "yml"],
+        }
+    }
+
+    pub fn deserialize<T: DeserializeOwned>(self, contents: &str) -> Result<T, String> {
+        match self {
+            ConfigFormat::Toml => toml::from_str(contents).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::from_str(contents).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml::from_str(contents).map_err(|e| e.to_string()),
+        }
+    }
+
+    pub fn serialize<T: Serialize>(self, value: &T) -> Result<String, String> {
+        match self {
+            ConfigFormat::Toml => toml::to_string_pretty(value).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::to_string_pretty(value)
+                .map(|json| json + "\n")
+                .map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Rewrite `contents`, a config of the `from` format, in this format. Comments are lost.
+    pub fn convert(self, contents: &str, from: ConfigFormat) -> Result<String, String> {
+        if from == self {
+            return Ok(contents.to_string());
+        }
+        let value: serde_json::Value = from.deserialize(contents)?;
+        self.serialize(&value)
+    }
+}
+
+impl fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigFormat::Toml => write!(f, "TOML"),
+            ConfigFormat::Json => write!(f, "JSON"),
+            ConfigFormat::Yaml => write!(f, "YAML"),
+        }
+    }
+}
+
+/// Name of a config or fragment file without its extension (`tar@bsd` for `tar@bsd.yaml`), if
+/// it is in one of the config formats
+pub fn config_stem(file_name: &str) -> Option<&str> {
+    let (stem, extension) = file_name.rsplit_once('.')?;
+    ConfigFormat::from_extension(extension).map(|_| stem)
+}
+
+/// The paths a config named like `name` may have: `name` itself, then the same file in the other
+/// formats, by order of precedence. Other files only have `name`.
+pub fn format_variants(name: &Path) -> Vec<PathBuf> {
+    let mut variants = vec![name.to_path_buf()];
+    if ConfigFormat::from_path(name).is_some() {
+        let extensions = CONFIG_FORMATS.iter().flat_map(|format| format.extensions());
+        for extension in extensions {
+            let variant = name.with_extension(extension);
+            if !variants.contains(&variant) {
+                variants.push(variant);
+            }
+        }
+    }
+    variants
+}
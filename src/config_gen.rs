@@ -0,0 +1,770 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Generation of config skeletons from the documentation of a program: the output of its
+//! `--help` option or the source of its man page (man or mdoc macros).
+
+use std::fmt::Write;
+
+/// Behaviour given to every generated option, for analysts to replace
+pub const PLACEHOLDER_BEHAVIOUR: &str = "NEUTRAL";
+
+/// Lines of a man page paragraph searched for the description of an option
+const MAX_DESCRIPTION_LINES: usize = 3;
+
+/// Argument of an option, named as in the documentation (`ARCHIVE`, `<file>`...)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionArg {
+    None,
+    /// `--file=ARCHIVE`, `-f ARCHIVE`
+    Required(String),
+    /// `--color[=WHEN]`, which can only be given with `=` and therefore counts as no argument
+    /// for the parser
+    Optional(String),
+}
+
+/// An option found in the documentation, with all its names
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptionSpec {
+    /// Single-dash names, without their dash
+    pub char_names: Vec<char>,
+    /// Double-dash names, without their dashes
+    pub string_names: Vec<String>,
+    pub arg: OptionArg,
+    /// The option as documented (`-f, --file=ARCHIVE`)
+    pub synopsis: String,
+    pub description: Option<String>,
+}
+
+impl OptionSpec {
+    /// Whether the parser should consume the next word as the argument of the option
+    pub fn has_arg(&self) -> bool {
+        matches!(self.arg, OptionArg::Required(_))
+    }
+}
+
+/// Documentation formats `gen-config` understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocFormat {
+    /// Output of `program --help`
+    Help,
+    /// Man page source, using the man or mdoc macros
+    Man,
+}
+
+impl DocFormat {
+    /// Guess the format of `text`: man page sources start with roff requests or comments
+    pub fn detect(text: &str) -> DocFormat {
+        let is_roff = text
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .is_some_and(|line| line.starts_with('.') || line.starts_with("'\\\""));
+        if is_roff {
+            DocFormat::Man
+        } else {
+            DocFormat::Help
+        }
+    }
+}
+
+/// Extract the options documented in `text`
+pub fn extract_options(text: &str, format: DocFormat) -> Vec<OptionSpec> {
+    match format {
+        DocFormat::Help => parse_help_text(text),
+        DocFormat::Man => parse_man_page(text),
+    }
+}
+
+/// Extract the options of `--help` output: lines starting with a dash, the option names being
+/// separated from their description by two spaces or more
+pub fn parse_help_text(text: &str) -> Vec<OptionSpec> {
+    let mut options: Vec<OptionSpec> = vec![];
+    let lines: Vec<&str> = text.lines().collect();
+    for (idx, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        if !trimmed.starts_with('-') {
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+        let (synopsis, mut description) = split_synopsis(trimmed);
+        // Descriptions too long for the option column start on the next, more indented, line
+        if description.is_none()
+            && let Some(next) = lines.get(idx + 1)
+        {
+            let next_trimmed = next.trim_start();
+            if next.len() - next_trimmed.len() > indent
+                && !next_trimmed.is_empty()
+                && !next_trimmed.starts_with('-')
+            {
+                description = Some(next_trimmed.trim_end().to_string());
+            }
+        }
+        if let Some(option) = parse_synopsis(synopsis, description) {
+            add_option(&mut options, option);
+        }
+    }
+    options
+}
+
+/// Extract the options of a man page source: the tags of its `.TP`, `.IP` (man) and `.It`
+/// (mdoc) paragraphs that start with a dash
+pub fn parse_man_page(source: &str) -> Vec<OptionSpec> {
+    let mut options: Vec<OptionSpec> = vec![];
+    let lines: Vec<&str> = source.lines().collect();
+    let mut idx = 0;
+    while idx < lines.len() {
+        let line = lines[idx];
+        idx += 1;
+        let (request, args) = split_request(line);
+        let tag = match request {
+            // The tag is the next line
+            Some("TP") => match lines.get(idx) {
+                Some(tag_line) => {
+                    idx += 1;
+                    man_line_text(tag_line)
+                }
+                None => continue,
+            },
+            Some("IP") => match split_args(args).first() {
+                Some(tag) => unescape(tag),
+                None => continue,
+            },
+            Some("It") => mdoc_text(args),
+            _ => continue,
+        };
+        let tag = tag.trim();
+        if !tag.starts_with('-') {
+            continue;
+        }
+
+        // The description is the first sentence of the paragraph, within its first lines of text
+        let mut description_lines: Vec<String> = vec![];
+        for line in lines[idx..]
+            .iter()
+            .take_while(|line| !matches!(split_request(line).0, Some("TP" | "IP" | "It" | "SH")))
+        {
+            let text = man_line_text(line).trim().to_string();
+            if text.is_empty() {
+                continue;
+            }
+            let sentence_end = text.ends_with('.');
+            description_lines.push(text);
+            if sentence_end || description_lines.len() == MAX_DESCRIPTION_LINES {
+                break;
+            }
+        }
+        let description = (!description_lines.is_empty()).then(|| {
+            let description = description_lines.join(" ");
+            match description.find(". ") {
+                Some(end) => description[..=end].to_string(),
+                None => description,
+            }
+        });
+        if let Some(option) = parse_synopsis(tag, description) {
+            add_option(&mut options, option);
+        }
+    }
+    options
+}
+
+/// Render a config skeleton for `program`: the options in the order of the documentation, each
+/// preceded by its synopsis and description as a comment, with placeholder behaviours
+pub fn render_config(program: &str, options: &[OptionSpec]) -> String {
+    let mut config = String::new();
+    let _ = writeln!(config, "name = {}", toml_string(program));
+    let _ = writeln!(config, "has_separatorless_args_for_char_options = false");
+    let _ = writeln!(config, "string_separators = [\"=\", \" \"]");
+    let _ = writeln!(config, "handle_quotes = false");
+    let _ = writeln!(config, "behaviours = [\"{}\"]", PLACEHOLDER_BEHAVIOUR);
+    render_options(&mut config, options);
+    config
+}
+
+/// Add the options of `options` missing from the `existing` config, as `render_config` would.
+/// The existing text, and thus its behaviours and comments, is kept as is. Returns the merged
+/// config and the options that were added.
+pub fn merge_config(
+    existing: &str,
+    options: &[OptionSpec],
+) -> Result<(String, Vec<OptionSpec>), String> {
+    #[derive(serde::Deserialize)]
+    struct ExistingOption {
+        option_name: String,
+    }
+    #[derive(serde::Deserialize)]
+    struct ExistingConfig {
+        #[serde(default)]
+        string_options: Vec<ExistingOption>,
+        #[serde(default)]
+        char_options: Vec<ExistingOption>,
+    }
+    let existing_config: ExistingConfig = toml::from_str(existing).map_err(|e| e.to_string())?;
+    let known = |options: &[ExistingOption], name: &str| {
+        options.iter().any(|option| option.option_name == name)
+    };
+
+    let mut added: Vec<OptionSpec> = vec![];
+    for option in options {
+        let mut option = option.clone();
+        option
+            .char_names
+            .retain(|name| !known(&existing_config.char_options, &name.to_string()));
+        option
+            .string_names
+            .retain(|name| !known(&existing_config.string_options, name));
+        if !option.char_names.is_empty() || !option.string_names.is_empty() {
+            added.push(option);
+        }
+    }
+
+    let mut config = existing.to_string();
+    if !added.is_empty() {
+        if !config.ends_with('\n') {
+            config.push('\n');
+        }
+        render_options(&mut config, &added);
+    }
+    Ok((config, added))
+}
+
+fn render_options(config: &mut String, options: &[OptionSpec]) {
+    for option in options {
+        let _ = writeln!(config);
+        match &option.description {
+            Some(description) => {
+                let _ = writeln!(config, "# {}  {}", option.synopsis, description);
+            }
+            None => {
+                let _ = writeln!(config, "# {}", option.synopsis);
+            }
+        }
+        let tables = option
+            .char_names
+            .iter()
+            .map(|name| ("char_options", name.to_string()))
+            .chain(
+                option
+                    .string_names
+                    .iter()
+                    .map(|name| ("string_options", name.clone())),
+            );
+        for (idx, (table, name)) in tables.enumerate() {
+            if idx > 0 {
+                let _ = writeln!(config);
+            }
+            render_option(config, table, &name, option.has_arg());
+        }
+    }
+}
+
+fn render_option(config: &mut String, table: &str, name: &str, has_arg: bool) {
+    let _ = writeln!(config, "[[{}]]", table);
+    let _ = writeln!(config, "option_name = {}", toml_string(name));
+    let _ = writeln!(config, "has_arg = {}", has_arg);
+    let _ = writeln!(config, "behaviours = [\"{}\"]", PLACEHOLDER_BEHAVIOUR);
+}
+
+fn toml_string(value: &str) -> String {
+    toml::Value::String(value.to_string()).to_string()
+}
+
+/// Add `option`, dropping the names already documented by a previous option
+fn add_option(options: &mut Vec<OptionSpec>, mut option: OptionSpec) {
+    option
+        .char_names
+        .retain(|name| !options.iter().any(|other| other.char_names.contains(name)));
+    option.string_names.retain(|name| {
+        !options
+            .iter()
+            .any(|other| other.string_names.contains(name))
+    });
+    if !option.char_names.is_empty() || !option.string_names.is_empty() {
+        options.push(option);
+    }
+}
+
+/// Split a `--help` line into its option names and their description
+fn split_synopsis(line: &str) -> (&str, Option<String>) {
+    let line = line.trim_end();
+    let separator = line.find("  ").into_iter().chain(line.find('\t')).min();
+    match separator {
+        Some(idx) => {
+            let description = line[idx..].trim();
+            (
+                &line[..idx],
+                (!description.is_empty()).then(|| description.to_string()),
+            )
+        }
+        None => (line, None),
+    }
+}
+
+/// Parse the names and argument of an option, as in `-f, --file=ARCHIVE` or `-C DIR`
+fn parse_synopsis(synopsis: &str, description: Option<String>) -> Option<OptionSpec> {
+    let mut option = OptionSpec {
+        char_names: vec![],
+        string_names: vec![],
+        arg: OptionArg::None,
+        synopsis: synopsis.trim().to_string(),
+        description,
+    };
+    for word in synopsis
+        .split([',', ' ', '|'])
+        .filter(|word| !word.is_empty())
+    {
+        if let Some(long) = word.strip_prefix("--") {
+            let (name, arg) = split_attached_arg(long);
+            if name.is_empty() {
+                continue;
+            }
+            // `--[no-]progress` documents both `--progress` and `--no-progress`
+            if let Some((prefix, rest)) =
+                name.strip_prefix('[').and_then(|name| name.split_once(']'))
+            {
+                option.string_names.push(rest.to_string());
+                option.string_names.push(format!("{prefix}{rest}"));
+            } else {
+                option.string_names.push(name.to_string());
+            }
+            if arg != OptionArg::None {
+                option.arg = arg;
+            }
+        } else if let Some(short) = word.strip_prefix('-') {
+            let (name, arg) = split_attached_arg(short);
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(name), None) => option.char_names.push(name),
+                // Single-dash long options (`-name`) can't be described by a config
+                _ => continue,
+            }
+            if arg != OptionArg::None {
+                option.arg = arg;
+            }
+        } else if is_arg_name(word) && option.arg == OptionArg::None {
+            option.arg = match word.strip_prefix('[') {
+                Some(optional) => OptionArg::Optional(optional.trim_end_matches(']').to_string()),
+                None => OptionArg::Required(word.to_string()),
+            };
+        } else if !is_arg_name(word) {
+            // The description started after a single space
+            break;
+        }
+    }
+    (!option.char_names.is_empty() || !option.string_names.is_empty()).then_some(option)
+}
+
+/// Split `file=ARCHIVE`, `color[=WHEN]` or `file<archive>` into the option name and its argument
+fn split_attached_arg(word: &str) -> (&str, OptionArg) {
+    if let Some(idx) = word.find("[=") {
+        let arg = word[idx + 2..].trim_end_matches(']');
+        return (&word[..idx], OptionArg::Optional(arg.to_string()));
+    }
+    if let Some((name, arg)) = word.split_once('=') {
+        return (name, OptionArg::Required(arg.to_string()));
+    }
+    // `-n[NUM]` or `-j<N>`, written without separator
+    if let Some(idx) = word.find(['[', '<']).filter(|idx| *idx > 0) {
+        let arg = &word[idx..];
+        return match arg.strip_prefix('[') {
+            Some(optional) => (
+                &word[..idx],
+                OptionArg::Optional(optional.trim_end_matches(']').to_string()),
+            ),
+            None => (&word[..idx], OptionArg::Required(arg.to_string())),
+        };
+    }
+    (word, OptionArg::None)
+}
+
+/// Whether `word` looks like the name of an argument: `FILE`, `<file>`, `[FILE]`, `{a,b}`
+fn is_arg_name(word: &str) -> bool {
+    word.starts_with(['<', '[', '{'])
+        || (word.chars().any(|c| c.is_ascii_uppercase())
+            && word
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || "_-.:".contains(c)))
+}
+
+/// Split a roff line into its request name (`TP` for `.TP`) and arguments
+fn split_request(line: &str) -> (Option<&str>, &str) {
+    match line.strip_prefix('.').or_else(|| line.strip_prefix('\'')) {
+        Some(request) => {
+            let request = request.trim_start();
+            match request.split_once([' ', '\t']) {
+                Some((name, args)) => (Some(name), args.trim()),
+                None => (Some(request.trim_end()), ""),
+            }
+        }
+        None => (None, line),
+    }
+}
+
+/// Split the arguments of a roff request, honouring double quotes
+fn split_args(args: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut in_word = false;
+    for c in args.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            ' ' | '\t' if !quoted => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Text of a line of a man page: plain text, or the arguments of a font request (`.B`, `.BI`...)
+fn man_line_text(line: &str) -> String {
+    match split_request(line) {
+        (None, text) => unescape(text),
+        // Alternating font requests join their arguments without spaces
+        (Some("BI" | "BR" | "IB" | "IR" | "RB" | "RI"), args) => {
+            split_args(args).iter().map(|arg| unescape(arg)).collect()
+        }
+        (Some("B" | "I" | "SM" | "SB"), args) => split_args(args)
+            .iter()
+            .map(|arg| unescape(arg))
+            .collect::<Vec<_>>()
+            .join(" "),
+        (Some(_), _) => String::new(),
+    }
+}
+
+/// Text of the arguments of an mdoc request such as `.It Fl f , Fl \-file Ns = Ns Ar archive` or
+/// `.It Fl v , Fl Fl verbose`
+fn mdoc_text(args: &str) -> String {
+    let mut text = String::new();
+    let mut no_space = false;
+    let mut open_brackets = 0;
+    let mut words = split_args(args).into_iter().peekable();
+    while let Some(word) = words.next() {
+        let piece = match word.as_str() {
+            // `Fl Fl file` is `--file`
+            "Fl" if words.peek().is_some_and(|next| next == "Fl") => {
+                if !text.is_empty() && !no_space {
+                    text.push(' ');
+                }
+                text.push('-');
+                no_space = true;
+                continue;
+            }
+            "Fl" => {
+                // `Fl` alone is a lone dash, otherwise it prefixes its argument
+                let flag = words.next().unwrap_or_default();
+                format!("-{}", unescape(&flag))
+            }
+            "Ar" => format!("<{}>", unescape(&words.next().unwrap_or_default())),
+            "Ns" => {
+                no_space = true;
+                continue;
+            }
+            "Op" | "Oo" => {
+                open_brackets += 1;
+                if !text.is_empty() && !no_space {
+                    text.push(' ');
+                }
+                text.push('[');
+                no_space = true;
+                continue;
+            }
+            "Oc" => {
+                if open_brackets > 0 {
+                    open_brackets -= 1;
+                    text.push(']');
+                }
+                continue;
+            }
+            // Other macros only change the font
+            macro_name
+                if macro_name.len() == 2
+                    && macro_name.starts_with(|c: char| c.is_ascii_uppercase()) =>
+            {
+                continue;
+            }
+            "," => {
+                text.push(',');
+                continue;
+            }
+            word => unescape(word),
+        };
+        if !text.is_empty() && !no_space {
+            text.push(' ');
+        }
+        text.push_str(&piece);
+        no_space = false;
+    }
+    text.push_str(&"]".repeat(open_brackets));
+    text
+}
+
+/// Remove the roff escapes of `text`: font changes, `\-`, `\&`, `\e`, special characters
+fn unescape(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('-') => result.push('-'),
+            Some('e') | Some('\\') => result.push('\\'),
+            Some('&') | Some('|') | Some('^') | Some(',') | Some('/') => {}
+            Some(' ') | Some('~') => result.push(' '),
+            // Font changes: `\fB`, `\f(CW`, `\f[B]`
+            Some('f') => match chars.next() {
+                Some('(') => {
+                    chars.next();
+                    chars.next();
+                }
+                Some('[') => while chars.next().is_some_and(|c| c != ']') {},
+                _ => {}
+            },
+            // Special characters: `\(em`, `\[em]`
+            Some('(') => {
+                let name: String = chars.by_ref().take(2).collect();
+                result.push_str(special_char(&name));
+            }
+            Some('[') => {
+                let name: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                result.push_str(special_char(&name));
+            }
+            // Comments
+            Some('"') => break,
+            Some(other) => result.push(other),
+            None => {}
+        }
+    }
+    result
+}
+
+fn special_char(name: &str) -> &'static str {
+    match name {
+        "em" | "en" | "mi" | "hy" => "-",
+        "aq" | "cq" | "oq" => "'",
+        "dq" | "lq" | "rq" => "\"",
+        "ba" | "or" => "|",
+        "lB" => "[",
+        "rB" => "]",
+        "la" => "<",
+        "ra" => ">",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser_config::ParserConfig;
+
+    /// `--help` output in the style of GNU tools
+    const GNU_HELP: &str = include_str!("../tests/fixtures/docs/gnu.help");
+    /// Man page written with the man macros
+    const TAR_MAN: &str = include_str!("../tests/fixtures/docs/tar.1");
+    /// Man page written with the mdoc macros
+    const BSDTAR_MDOC: &str = include_str!("../tests/fixtures/docs/bsdtar.1");
+
+    fn option<'o>(options: &'o [OptionSpec], name: &str) -> &'o OptionSpec {
+        options
+            .iter()
+            .find(|option| {
+                option.string_names.iter().any(|known| known == name)
+                    || option.char_names.iter().any(|c| c.to_string() == name)
+            })
+            .unwrap_or_else(|| panic!("{name} not found"))
+    }
+
+    fn synopses(options: &[OptionSpec]) -> Vec<&str> {
+        options
+            .iter()
+            .map(|option| option.synopsis.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn help_text() {
+        assert_eq!(DocFormat::detect(GNU_HELP), DocFormat::Help);
+        let options = parse_help_text(GNU_HELP);
+        assert_eq!(
+            synopses(&options),
+            [
+                "-c, --create",
+                "-x, --extract, --get",
+                "-f, --file=ARCHIVE",
+                "--force-local",
+                "-C, --directory=DIR",
+                "--exclude=PATTERN",
+                "--[no-]recursion",
+                "--checkpoint-action=ACTION",
+                "--color[=WHEN]",
+                "-v, --verbose",
+                "-?, --help",
+            ]
+        );
+
+        let file = option(&options, "file");
+        assert_eq!(file.char_names, ['f']);
+        assert_eq!(file.string_names, ["file"]);
+        assert_eq!(file.arg, OptionArg::Required("ARCHIVE".to_string()));
+        assert_eq!(
+            file.description.as_deref(),
+            Some("use archive file or device ARCHIVE")
+        );
+        assert_eq!(option(&options, "x").string_names, ["extract", "get"]);
+        // Only given with `=`, an optional argument is not consumed by the parser
+        let color = option(&options, "color");
+        assert_eq!(color.arg, OptionArg::Optional("WHEN".to_string()));
+        assert!(!color.has_arg());
+        assert_eq!(
+            option(&options, "recursion").string_names,
+            ["recursion", "no-recursion"]
+        );
+        // Descriptions on the next line
+        assert_eq!(
+            option(&options, "checkpoint-action").description.as_deref(),
+            Some("execute ACTION on each checkpoint")
+        );
+    }
+
+    #[test]
+    fn man_page() {
+        assert_eq!(DocFormat::detect(TAR_MAN), DocFormat::Man);
+        let options = parse_man_page(TAR_MAN);
+        assert_eq!(
+            synopses(&options),
+            [
+                "-f, --file=ARCHIVE",
+                "-v",
+                "-C, --directory=DIR",
+                "--one-file-system",
+                "-z, --gzip",
+            ]
+        );
+        let file = option(&options, "f");
+        assert!(file.has_arg());
+        assert_eq!(
+            file.description.as_deref(),
+            Some("Use archive file or device ARCHIVE.")
+        );
+        assert_eq!(
+            option(&options, "v").description.as_deref(),
+            Some("Verbosely list files processed.")
+        );
+        assert_eq!(
+            option(&options, "directory").arg,
+            OptionArg::Required("DIR".to_string())
+        );
+        assert!(!option(&options, "gzip").has_arg());
+    }
+
+    #[test]
+    fn mdoc_page() {
+        assert_eq!(DocFormat::detect(BSDTAR_MDOC), DocFormat::Man);
+        let options = parse_man_page(BSDTAR_MDOC);
+        assert_eq!(
+            synopses(&options),
+            [
+                "-f <file>, --file <file>",
+                "-v, --verbose",
+                "--exclude <pattern>",
+                "-s <pattern>",
+                "--options=<options>",
+            ]
+        );
+        let file = option(&options, "file");
+        assert_eq!(file.char_names, ['f']);
+        assert_eq!(file.arg, OptionArg::Required("<file>".to_string()));
+        assert_eq!(option(&options, "verbose").char_names, ['v']);
+        assert!(!option(&options, "verbose").has_arg());
+        assert!(option(&options, "options").has_arg());
+    }
+
+    #[test]
+    fn rendered_configs_load() {
+        let options = parse_help_text(GNU_HELP);
+        let config = render_config("tar", &options);
+        assert!(config.contains("\n# -f, --file=ARCHIVE  use archive file or device ARCHIVE\n"));
+
+        let config = ParserConfig::from_toml_str(&config).unwrap();
+        assert_eq!(config.name, "tar");
+        assert_eq!(config.does_char_option_have_arg(&'f'), Ok(true));
+        assert_eq!(
+            config.does_string_option_have_arg(&"file".to_string()),
+            Ok(true)
+        );
+        assert_eq!(
+            config.does_string_option_have_arg(&"color".to_string()),
+            Ok(false)
+        );
+        assert_eq!(config.does_char_option_have_arg(&'?'), Ok(false));
+        assert_eq!(
+            config.get_behaviours("no-recursion").unwrap()[0].name(),
+            PLACEHOLDER_BEHAVIOUR
+        );
+
+        // Merging adds the options the config lacks only
+        let (merged, added) = merge_config(
+            &render_config("tar", &options[..2]),
+            &parse_man_page(TAR_MAN),
+        )
+        .unwrap();
+        assert_eq!(
+            synopses(&added),
+            [
+                "-f, --file=ARCHIVE",
+                "-v",
+                "-C, --directory=DIR",
+                "--one-file-system",
+                "-z, --gzip",
+            ]
+        );
+        assert!(ParserConfig::from_toml_str(&merged).is_ok());
+        let (_, added) = merge_config(&merged, &options).unwrap();
+        assert_eq!(
+            synopses(&added),
+            [
+                "--force-local",
+                "--exclude=PATTERN",
+                "--[no-]recursion",
+                "--checkpoint-action=ACTION",
+                "--color[=WHEN]",
+                "-v, --verbose",
+                "-?, --help",
+            ]
+        );
+        // `-v` is already there
+        let verbose = option(&added, "verbose");
+        assert!(verbose.char_names.is_empty());
+        assert_eq!(verbose.string_names, ["verbose"]);
+    }
+}
@@ -0,0 +1,663 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use serde::Deserialize;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use toml::Spanned;
+
+use crate::config_format::ConfigFormat;
+use crate::env_vars;
+use crate::parser_config::ParserConfig;
+use crate::taxonomy::Behaviour;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The config cannot be loaded, or would be misinterpreted
+    Error,
+    /// The config loads, but probably does not say what its author meant
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem found in a config file
+#[derive(Debug, Clone)]
+pub struct LintIssue {
+    pub file: PathBuf,
+    /// None for issues of the whole file, or whose line in the config as written is unknown
+    pub line: Option<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, "{line}:")?;
+        }
+        write!(f, " {}: {}", self.severity, self.message)
+    }
+}
+
+// Mirrors `ConfigFile`, keeping the position of every value
+#[derive(Deserialize)]
+struct RawConfigFile {
+    // All optional, as configs using `extends` or `include` may get them from other files
+    #[serde(default)]
+    string_separators: Vec<Spanned<String>>,
+    #[serde(default)]
+    string_options: Vec<Spanned<RawOption>>,
+    #[serde(default)]
+    char_options: Vec<Spanned<RawOption>>,
+    behaviours: Option<Spanned<Vec<Spanned<String>>>>,
+    #[serde(default)]
+    name_patterns: Vec<Spanned<String>>,
+    #[serde(default)]
+    env_vars: Vec<Spanned<RawEnvVar>>,
+}
+
+#[derive(Deserialize)]
+struct RawOption {
+    // Not a `char`, so that multi-character char options are reported instead of failing
+    option_name: Spanned<String>,
+    has_arg: Option<bool>,
+    arg_type: Option<Spanned<String>>,
+    behaviours: Spanned<Vec<Spanned<String>>>,
+}
+
+#[derive(Deserialize)]
+struct RawEnvVar {
+    name: Spanned<String>,
+    behaviours: Spanned<Vec<Spanned<String>>>,
+}
+
+/// Lint the config file at `path`
+pub fn lint_file(path: &Path) -> Vec<LintIssue> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => lint_str(path, &contents),
+        Err(err) => vec![LintIssue {
+            file: path.to_path_buf(),
+            line: None,
+            severity: Severity::Error,
+            message: err.to_string(),
+        }],
+    }
+}
+
+/// Lint the contents of a config file, written in the format of the extension of `file` (TOML when
+/// it has none), which is otherwise only used in the reports
+pub fn lint_str(file: &Path, contents: &str) -> Vec<LintIssue> {
+    lint(file, contents, true)
+}
+
+/// Lint the contents of a config file that was already loaded, without trying to load it again
+pub fn lint_source(file: &Path, contents: &str) -> Vec<LintIssue> {
+    lint(file, contents, false)
+}
+
+fn lint(file: &Path, contents: &str, check_load: bool) -> Vec<LintIssue> {
+    // JSON and YAML configs are checked as their TOML equivalent, whose issues are reported at the
+    // line of the same value in the config as written
+    let format = ConfigFormat::from_path(file).unwrap_or(ConfigFormat::Toml);
+    let toml_contents = match to_toml(contents, format) {
+        Ok(toml_contents) => toml_contents,
+        Err((line, message)) => {
+            return vec![LintIssue {
+                file: file.to_path_buf(),
+                line,
+                severity: Severity::Error,
+                message,
+            }];
+        }
+    };
+    let mut linter = Linter {
+        file,
+        source: contents,
+        format,
+        contents: &toml_contents,
+        check_load,
+        issues: vec![],
+    };
+    linter.lint();
+    linter.issues.sort_by_key(|issue| issue.line);
+    linter.issues
+}
+
+/// `contents`, a config of the `format` format, in TOML, or the line and message of its syntax
+/// error
+fn to_toml(contents: &str, format: ConfigFormat) -> Result<String, (Option<usize>, String)> {
+    let value: serde_json::Value = match format {
+        ConfigFormat::Toml => return Ok(contents.to_string()),
+        ConfigFormat::Json => serde_json::from_str(contents)
+            .map_err(|err| (Some(err.line()).filter(|&line| line > 0), err.to_string()))?,
+        ConfigFormat::Yaml => serde_yaml::from_str(contents).map_err(|err| {
+            (
+                err.location().map(|location| location.line()),
+                err.to_string(),
+            )
+        })?,
+    };
+    ConfigFormat::Toml
+        .serialize(&value)
+        .map_err(|err| (None, err))
+}
+
+struct Linter<'a> {
+    file: &'a Path,
+    /// The config as written, in `format`
+    source: &'a str,
+    format: ConfigFormat,
+    /// The config in TOML
+    contents: &'a str,
+    check_load: bool,
+    issues: Vec<LintIssue>,
+}
+
+impl Linter<'_> {
+    fn lint(&mut self) {
+        let config_file: RawConfigFile = match toml::from_str(self.contents) {
+            Ok(config_file) => config_file,
+            Err(err) => {
+                let line = err.span().and_then(|span| self.line_of(span.start));
+                self.push(line, Severity::Error, err.message().to_string());
+                return;
+            }
+        };
+
+        for separator in &config_file.string_separators {
+            if separator.get_ref().chars().count() != 1 {
+                self.report(
+                    separator.span().start,
+                    Severity::Error,
+                    format!(
+                        "String separator \"{}\" must be exactly one character",
+                        separator.get_ref()
+                    ),
+                );
+            }
+        }
+
+        for pattern in &config_file.name_patterns {
+            if let Err(err) = regex::Regex::new(pattern.get_ref()) {
+                self.report(
+                    pattern.span().start,
+                    Severity::Error,
+                    format!("Invalid name pattern \"{}\": {err}", pattern.get_ref()),
+                );
+            }
+        }
+
+        if let Some(behaviours) = &config_file.behaviours {
+            self.lint_behaviours(behaviours, "The program");
+        }
+        self.lint_options(&config_file.char_options, "char");
+        self.lint_options(&config_file.string_options, "string");
+        self.lint_env_vars(&config_file.env_vars);
+
+        // Anything the checks above did not catch but the real loader rejects, including
+        // problems with the configs and fragments it extends or includes
+        if self.check_load && !self.has_errors() {
+            let loaded = if self.file.is_file() {
+                ParserConfig::from_file_with_sources(self.file).map(|_| ())
+            } else {
+                ParserConfig::from_str(self.source, self.format).map(|_| ())
+            };
+            if let Err(err) = loaded {
+                self.push(None, Severity::Error, err);
+            }
+        }
+    }
+
+    fn lint_options(&mut self, options: &[Spanned<RawOption>], kind: &str) {
+        let mut first_definitions: HashMap<&str, &RawOption> = HashMap::new();
+        for option in options {
+            let option = option.get_ref();
+            let name = option.option_name.get_ref().as_str();
+            let offset = option.option_name.span().start;
+
+            if kind == "char" && name.chars().count() != 1 {
+                self.report(
+                    offset,
+                    Severity::Error,
+                    format!("Char option \"{name}\" must be exactly one character"),
+                );
+            } else if name.is_empty() {
+                self.report(
+                    offset,
+                    Severity::Error,
+                    "Empty string option name".to_string(),
+                );
+            }
+
+            if let Some(arg_type) = &option.arg_type
+                && !option.has_arg.unwrap_or(false)
+            {
+                self.report(
+                    arg_type.span().start,
+                    Severity::Warning,
+                    format!("The \"{name}\" {kind} option has an argument type but no argument"),
+                );
+            }
+
+            if option.behaviours.get_ref().is_empty() {
+                self.report(
+                    option.behaviours.span().start,
+                    Severity::Warning,
+                    format!("The \"{name}\" {kind} option has no behaviours"),
+                );
+            }
+            self.lint_behaviours(&option.behaviours, &format!("The \"{name}\" {kind} option"));
+
+            match first_definitions.get(name) {
+                None => {
+                    first_definitions.insert(name, option);
+                }
+                Some(first) => {
+                    let first_line = match self.line_of(first.option_name.span().start) {
+                        None => String::new(),
+                        Some(line) => format!(" at line {line}"),
+                    };
+                    let first_behaviours: Vec<&String> = first
+                        .behaviours
+                        .get_ref()
+                        .iter()
+                        .map(|b| b.get_ref())
+                        .collect();
+                    let behaviours: Vec<&String> = option
+                        .behaviours
+                        .get_ref()
+                        .iter()
+                        .map(|b| b.get_ref())
+                        .collect();
+                    if first.has_arg.unwrap_or(false) != option.has_arg.unwrap_or(false)
+                        || first_behaviours != behaviours
+                    {
+                        self.report(
+                            offset,
+                            Severity::Warning,
+                            format!("The \"{name}\" {kind} option conflicts with its definition{first_line}, which takes precedence"),
+                        );
+                    } else {
+                        self.report(
+                            offset,
+                            Severity::Warning,
+                            format!("The \"{name}\" {kind} option is already defined{first_line}"),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn lint_env_vars(&mut self, env_vars: &[Spanned<RawEnvVar>]) {
+        let mut names: Vec<&str> = vec![];
+        for env_var in env_vars {
+            let env_var = env_var.get_ref();
+            let name = env_var.name.get_ref().as_str();
+            let offset = env_var.name.span().start;
+            if !env_vars::is_name(name) {
+                self.report(
+                    offset,
+                    Severity::Error,
+                    format!("\"{name}\" is not an environment variable name"),
+                );
+            }
+            if names.contains(&name) {
+                self.report(
+                    offset,
+                    Severity::Warning,
+                    format!("The \"{name}\" environment variable is already defined"),
+                );
+            }
+            names.push(name);
+            self.lint_behaviours(
+                &env_var.behaviours,
+                &format!("The \"{name}\" environment variable"),
+            );
+        }
+    }
+
+    fn lint_behaviours(&mut self, behaviours: &Spanned<Vec<Spanned<String>>>, owner: &str) {
+        for behaviour in behaviours.get_ref() {
+            if Behaviour::from_name(behaviour.get_ref()).is_none() {
+                self.report(
+                    behaviour.span().start,
+                    Severity::Error,
+                    format!(
+                        "{owner} has behaviour \"{}\", which is not part of the taxonomy",
+                        behaviour.get_ref()
+                    ),
+                );
+            }
+        }
+    }
+
+    fn has_errors(&self) -> bool {
+        self.issues
+            .iter()
+            .any(|issue| issue.severity == Severity::Error)
+    }
+
+    /// Line of `offset` of the TOML contents in the config as written
+    fn line_of(&self, offset: usize) -> Option<usize> {
+        if self.format != ConfigFormat::Toml {
+            let path = toml_path(self.contents, offset)?;
+            return source_line(self.source, self.format, &path);
+        }
+        let line = self.contents[..offset.min(self.contents.len())]
+            .matches('\n')
+            .count();
+        Some(line + 1)
+    }
+
+    fn report(&mut self, offset: usize, severity: Severity, message: String) {
+        self.push(self.line_of(offset), severity, message);
+    }
+
+    fn push(&mut self, line: Option<usize>, severity: Severity, message: String) {
+        self.issues.push(LintIssue {
+            file: self.file.to_path_buf(),
+            line,
+            severity,
+            message,
+        });
+    }
+}
+
+/// A step from a value to one of its children
+#[derive(Debug, Clone, PartialEq)]
+enum PathStep {
+    Key(String),
+    Index(usize),
+}
+
+/// Path to the innermost value of `contents`, a TOML document, whose span holds `offset`
+fn toml_path(contents: &str, offset: usize) -> Option<Vec<PathStep>> {
+    let document = toml_edit::ImDocument::parse(contents).ok()?;
+    let mut path = vec![];
+    item_path(document.as_item(), offset, &mut path).then_some(path)
+}
+
+fn item_path(item: &toml_edit::Item, offset: usize, path: &mut Vec<PathStep>) -> bool {
+    match item {
+        toml_edit::Item::None => false,
+        toml_edit::Item::Value(value) => value_path(value, offset, path),
+        toml_edit::Item::Table(table) => table_path(table, offset, path),
+        toml_edit::Item::ArrayOfTables(tables) => tables.iter().enumerate().any(|(idx, table)| {
+            child_path(path, PathStep::Index(idx), |path| {
+                table_path(table, offset, path)
+            })
+        }),
+    }
+}
+
+fn table_path(table: &toml_edit::Table, offset: usize, path: &mut Vec<PathStep>) -> bool {
+    table.iter().any(|(key, item)| {
+        child_path(path, PathStep::Key(key.to_string()), |path| {
+            item_path(item, offset, path)
+        })
+    })
+}
+
+fn value_path(value: &toml_edit::Value, offset: usize, path: &mut Vec<PathStep>) -> bool {
+    let in_child = match value {
+        toml_edit::Value::Array(array) => array.iter().enumerate().any(|(idx, value)| {
+            child_path(path, PathStep::Index(idx), |path| {
+                value_path(value, offset, path)
+            })
+        }),
+        toml_edit::Value::InlineTable(table) => table.iter().any(|(key, value)| {
+            child_path(path, PathStep::Key(key.to_string()), |path| {
+                value_path(value, offset, path)
+            })
+        }),
+        _ => false,
+    };
+    in_child || value.span().is_some_and(|span| span.contains(&offset))
+}
+
+/// Whether `find` finds the offset under `step`, which is kept in `path` if so
+fn child_path(
+    path: &mut Vec<PathStep>,
+    step: PathStep,
+    find: impl FnOnce(&mut Vec<PathStep>) -> bool,
+) -> bool {
+    path.push(step);
+    let found = find(path);
+    if !found {
+        path.pop();
+    }
+    found
+}
+
+/// Message of the error a `Locator` stops at the value it looks for with
+const LOCATED: &str = "ucp-lint: located";
+
+/// Line of the value at `path` in `source`, a JSON or YAML document. Neither deserializer gives
+/// the position of values, but both give that of errors: the document is deserialized up to the
+/// value, which fails.
+fn source_line(source: &str, format: ConfigFormat, path: &[PathStep]) -> Option<usize> {
+    let locator = Locator(path);
+    match format {
+        ConfigFormat::Toml => None,
+        ConfigFormat::Json => {
+            let mut deserializer = serde_json::Deserializer::from_str(source);
+            match locator.deserialize(&mut deserializer) {
+                Err(err) if err.to_string().contains(LOCATED) => Some(err.line()),
+                _ => None,
+            }
+        }
+        ConfigFormat::Yaml => {
+            let deserializer = serde_yaml::Deserializer::from_str(source);
+            match locator.deserialize(deserializer) {
+                Err(err) if err.to_string().contains(LOCATED) => {
+                    err.location().map(|location| location.line())
+                }
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Deserializes a document down `.0`, to fail at the value it leads to
+#[derive(Clone, Copy)]
+struct Locator<'p>(&'p [PathStep]);
+
+impl Locator<'_> {
+    fn scalar<E: de::Error>(self) -> Result<(), E> {
+        match self.0 {
+            [] => Err(E::custom(LOCATED)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Locator<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Locator<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "any value")
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let (step, rest) = match self.0.split_first() {
+            None => return Err(de::Error::custom(LOCATED)),
+            Some((PathStep::Index(idx), rest)) => (*idx, rest),
+            Some((PathStep::Key(_), _)) => return Ok(()),
+        };
+        for _ in 0..step {
+            if seq.next_element::<IgnoredAny>()?.is_none() {
+                return Ok(());
+            }
+        }
+        seq.next_element_seed(Locator(rest))?;
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let (step, rest) = match self.0.split_first() {
+            None => return Err(de::Error::custom(LOCATED)),
+            Some((PathStep::Key(key), rest)) => (key, rest),
+            Some((PathStep::Index(_), _)) => return Ok(()),
+        };
+        while let Some(key) = map.next_key::<String>()? {
+            if key == *step {
+                return map.next_value_seed(Locator(rest));
+            }
+            map.next_value::<IgnoredAny>()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(file: &str, contents: &str) -> Vec<(Option<usize>, String)> {
+        lint_source(Path::new(file), contents)
+            .iter()
+            .map(|issue| (issue.line, issue.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn toml_issues_have_their_line() {
+        let issues = lines(
+            "foo.toml",
+            "name = \"foo\"\nbehaviours = [\"NEUTRAL\"]\n\n[[char_options]]\noption_name = \"a\"\nbehaviours = [\"BOGUS\"]\n",
+        );
+        assert_eq!(
+            issues,
+            [(
+                Some(6),
+                "foo.toml:6: error: The \"a\" char option has behaviour \"BOGUS\", which is not part of the taxonomy".to_string()
+            )]
+        );
+        let issues = lines("foo.toml", "name = \"foo\"\nbehaviours = [\n");
+        assert!(matches!(issues.as_slice(), [(Some(3), _)]), "{issues:?}");
+    }
+
+    #[test]
+    fn json_and_yaml_issues_have_their_line_as_written() {
+        // Keys in another order than in their TOML equivalent, and the same behaviour twice
+        let json = r#"{
+  "string_options": [{"option_name": "long", "behaviours": ["BOGUS"]}],
+  "name": "foo",
+  "char_options": [
+    {"option_name": "a", "behaviours": ["NEUTRAL"]},
+    {
+      "behaviours": ["BOGUS"],
+      "option_name": "a"
+    }
+  ]
+}"#;
+        let yaml = "string_options:\n  - option_name: long\n    behaviours: [BOGUS]\nname: foo\nchar_options:\n  - option_name: a\n    behaviours: [NEUTRAL]\n  -\n    behaviours:\n      - BOGUS\n    option_name: a\n";
+        for (file, contents, lines_as_written) in [
+            ("foo.json", json, [(2, 5), (7, 8)]),
+            ("foo.yaml", yaml, [(3, 6), (10, 11)]),
+        ] {
+            let [(long_behaviour, first_a), (a_behaviour, second_a)] = lines_as_written;
+            let issues = lines(file, contents);
+            assert_eq!(
+                issues,
+                [
+                    (
+                        Some(long_behaviour),
+                        format!(
+                            "{file}:{long_behaviour}: error: The \"long\" string option has behaviour \"BOGUS\", which is not part of the taxonomy"
+                        )
+                    ),
+                    (
+                        Some(a_behaviour),
+                        format!(
+                            "{file}:{a_behaviour}: error: The \"a\" char option has behaviour \"BOGUS\", which is not part of the taxonomy"
+                        )
+                    ),
+                    (
+                        Some(second_a),
+                        format!(
+                            "{file}:{second_a}: warning: The \"a\" char option conflicts with its definition at line {first_a}, which takes precedence"
+                        )
+                    ),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn json_and_yaml_syntax_errors_have_their_line() {
+        let issues = lines(
+            "foo.json",
+            "{\n  \"name\": \"foo\",\n  \"behaviours\": [\n}\n",
+        );
+        assert!(matches!(issues.as_slice(), [(Some(4), _)]), "{issues:?}");
+        let issues = lines(
+            "foo.yaml",
+            "name: foo\nbehaviours: [NEUTRAL\nchar_options: []\n",
+        );
+        assert!(matches!(issues.as_slice(), [(Some(3), _)]), "{issues:?}");
+        assert!(issues[0].1.starts_with("foo.yaml:"), "{issues:?}");
+    }
+}
@@ -0,0 +1,327 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use serde::Serialize;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config_format::{CONFIG_FORMATS, ConfigFormat, format_variants};
+
+/// The configs of the `configs/` directory of the repository, compiled into the binary, by path
+/// relative to it (`tar.toml`, `common/help.toml`...)
+pub static BUILTIN_CONFIGS: &[(&str, &str)] =
+    include!(concat!(env!("OUT_DIR"), "/builtin_configs.rs"));
+
+/// Prefix of the paths reported for built-in config files
+pub const BUILTIN_PREFIX: &str = "<builtin>";
+
+/// Where a config file comes from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigOrigin {
+    /// Compiled into the binary
+    Builtin,
+    /// An overlay directory
+    Overlay(PathBuf),
+}
+
+/// The config files available to an analyzer: the built-in ones (unless disabled), overlaid by
+/// the files of zero or more directories, a file of a later directory replacing the file with the
+/// same relative path in the previous layers
+#[derive(Debug, Clone)]
+pub struct ConfigStore {
+    builtin: bool,
+    overlays: Vec<PathBuf>,
+}
+
+impl ConfigStore {
+    /// Only the built-in configs
+    pub fn builtin() -> ConfigStore {
+        ConfigStore {
+            builtin: true,
+            overlays: vec![],
+        }
+    }
+
+    /// No configs at all, until overlays are added
+    pub fn empty() -> ConfigStore {
+        ConfigStore {
+            builtin: false,
+            overlays: vec![],
+        }
+    }
+
+    /// Only the configs of `dir`
+    pub fn dir(dir: impl Into<PathBuf>) -> ConfigStore {
+        ConfigStore {
+            builtin: false,
+            overlays: vec![dir.into()],
+        }
+    }
+
+    /// Overlay the configs of `dir` on top of the current ones
+    pub fn with_overlay(mut self, dir: impl Into<PathBuf>) -> ConfigStore {
+        self.overlays.push(dir.into());
+        self
+    }
+
+    /// Whether the built-in configs are used
+    pub fn has_builtin(&self) -> bool {
+        self.builtin
+    }
+
+    /// Overlay directories, from the lowest to the highest priority
+    pub fn overlays(&self) -> &[PathBuf] {
+        &self.overlays
+    }
+
+    /// Read the config file at `name`, a relative path such as `tar.toml`, from the layer with the
+    /// highest priority that has it in any config format (`tar.yaml`...). Also returns the path
+    /// of the file actually read, which is under `BUILTIN_PREFIX` for built-in files.
+    pub fn read(&self, name: &Path) -> Result<(String, PathBuf), String> {
+        if !is_plain_relative(name) {
+            return Err(format!(
+                "{}: config paths must be relative and stay within the config directories",
+                name.display()
+            ));
+        }
+        let variants = format_variants(name);
+        for overlay in self.overlays.iter().rev() {
+            for variant in &variants {
+                let path = overlay.join(variant);
+                if path.is_file() {
+                    return std::fs::read_to_string(&path)
+                        .map(|contents| (contents, path.clone()))
+                        .map_err(|e| format!("{}: {}", path.display(), e));
+                }
+            }
+        }
+        if self.builtin {
+            for variant in &variants {
+                if let Some(contents) = builtin_config(variant) {
+                    return Ok((
+                        contents.to_string(),
+                        Path::new(BUILTIN_PREFIX).join(variant),
+                    ));
+                }
+            }
+        }
+        Err(format!("{}: no such config file", name.display()))
+    }
+
+    /// Contents of a file returned by `read`, as currently available
+    pub fn contents(&self, source: &Path) -> Option<String> {
+        match source.strip_prefix(BUILTIN_PREFIX) {
+            Ok(name) => builtin_config(name).map(str::to_string),
+            Err(_) => std::fs::read_to_string(source).ok(),
+        }
+    }
+
+    /// Path of a file of the store relative to its layer (`common/help.toml`), for files returned
+    /// by `read` as well as for any file under an overlay directory
+    pub fn relative_name(&self, path: &Path) -> Option<PathBuf> {
+        if let Ok(name) = path.strip_prefix(BUILTIN_PREFIX) {
+            return Some(name.to_path_buf());
+        }
+        let path = absolute(path)?;
+        self.overlays
+            .iter()
+            .filter_map(|overlay| path.strip_prefix(absolute(overlay)?).ok())
+            .map(Path::to_path_buf)
+            .next()
+    }
+
+    /// The program configs (the TOML, JSON or YAML files at the top of each layer) by name, with
+    /// the file and origin of the one in use, sorted
+    pub fn configs(&self) -> Vec<(String, PathBuf, ConfigOrigin)> {
+        let mut configs: Vec<(String, PathBuf, ConfigOrigin)> = vec![];
+        let mut add = |name: String, path: PathBuf, origin: ConfigOrigin| match configs
+            .iter_mut()
+            .find(|(config, _, _)| *config == name)
+        {
+            Some(config) => *config = (name, path, origin),
+            None => configs.push((name, path, origin)),
+        };
+
+        if self.builtin {
+            let files = BUILTIN_CONFIGS
+                .iter()
+                .filter(|(file, _)| !file.contains('/'))
+                .map(|(file, _)| Path::new(BUILTIN_PREFIX).join(file));
+            for (name, file) in layer_configs(files) {
+                add(name, file, ConfigOrigin::Builtin);
+            }
+        }
+        for overlay in &self.overlays {
+            let Ok(entries) = std::fs::read_dir(overlay) else {
+                tracing::warn!(overlay = %overlay.display(), "Unreadable config directory");
+                continue;
+            };
+            let files = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| file.is_file());
+            for (name, file) in layer_configs(files) {
+                add(name, file, ConfigOrigin::Overlay(overlay.clone()));
+            }
+        }
+        configs.sort_by(|a, b| a.0.cmp(&b.0));
+        configs
+    }
+}
+
+/// The configs among the files at the top of a layer, by name. A config written in several formats
+/// is taken from the file of the format with the highest precedence.
+fn layer_configs(files: impl Iterator<Item = PathBuf>) -> Vec<(String, PathBuf)> {
+    let mut configs: Vec<(String, PathBuf, usize)> = vec![];
+    for file in files {
+        let Some(format) = ConfigFormat::from_path(&file) else {
+            continue;
+        };
+        let Some(name) = file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+        else {
+            continue;
+        };
+        let precedence = CONFIG_FORMATS
+            .iter()
+            .flat_map(|format| format.extensions())
+            .position(|extension| file.extension().is_some_and(|ext| ext == *extension))
+            .unwrap_or(usize::MAX);
+        match configs.iter_mut().find(|(config, _, _)| *config == name) {
+            Some(config) => {
+                tracing::warn!(
+                    config = %name,
+                    file = %file.display(),
+                    other = %config.1.display(),
+                    format = %format,
+                    "Config written in several formats, only one is used"
+                );
+                if precedence < config.2 {
+                    *config = (name, file, precedence);
+                }
+            }
+            None => configs.push((name, file, precedence)),
+        }
+    }
+    configs.sort_by(|a, b| a.0.cmp(&b.0));
+    configs
+        .into_iter()
+        .map(|(name, file, _)| (name, file))
+        .collect()
+}
+
+fn builtin_config(name: &Path) -> Option<&'static str> {
+    let name = name
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    BUILTIN_CONFIGS
+        .iter()
+        .find(|(file, _)| *file == name)
+        .map(|(_, contents)| *contents)
+}
+
+/// `path` made absolute, resolving symbolic links of its directory only, as the file may be gone
+fn absolute(path: &Path) -> Option<PathBuf> {
+    match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            Some(std::fs::canonicalize(dir).ok()?.join(name))
+        }
+        _ => std::fs::canonicalize(path).ok(),
+    }
+}
+
+/// Whether `path` is relative and made of plain file names only (no `..`)
+pub fn is_plain_relative(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// A directory of config files for tests, removed when dropped
+#[cfg(test)]
+pub(crate) struct ConfigDir(PathBuf);
+
+#[cfg(test)]
+impl ConfigDir {
+    /// A directory of its own for `test` (`<module>-<test>`), holding `files` by relative path
+    pub(crate) fn new(test: &str, files: &[(&str, &str)]) -> ConfigDir {
+        let dir = std::env::temp_dir().join(format!("ucp-{test}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        ConfigDir(dir)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// A store of the files of the directory only
+    pub(crate) fn store(&self) -> ConfigStore {
+        ConfigStore::dir(&self.0)
+    }
+}
+
+#[cfg(test)]
+impl Drop for ConfigDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_lint::{self, Severity};
+    use crate::parser_config::ParserConfig;
+
+    #[test]
+    fn builtin_configs_load() {
+        let store = ConfigStore::builtin();
+        let configs = store.configs();
+        for program in ["cpio", "curl", "tar", "zip"] {
+            assert!(
+                configs.iter().any(|(name, _, _)| name == program),
+                "{program}"
+            );
+        }
+        for (name, file, origin) in configs {
+            assert_eq!(origin, ConfigOrigin::Builtin);
+            let contents = store.contents(&file).unwrap();
+            let errors: Vec<String> = config_lint::lint_source(&file, &contents)
+                .iter()
+                .filter(|issue| issue.severity == Severity::Error)
+                .map(|issue| issue.to_string())
+                .collect();
+            assert!(errors.is_empty(), "{errors:#?}");
+            let relative_name = store.relative_name(&file).unwrap();
+            if let Err(err) = ParserConfig::from_store(&store, &relative_name) {
+                panic!("{name}: {err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,761 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Synchronisation of the configs with the behaviour labels of the DID U Misbehave dataset.
+//!
+//! The dataset is read from CSV files (`,`, `;` or tab separated) whose header names the
+//! columns, in any order: `program` (optional, the file name without extension by default),
+//! `option` (`-x`, `--long`, or empty for the behaviours of the program itself), `has_arg`
+//! (optional, `true` or `false`) and `behaviours` (names of the taxonomy, separated by `|` or
+//! spaces). Rows of the same option are merged.
+
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::config_cache::ParserConfigCache;
+use crate::config_gen;
+use crate::parser_config::ParserConfig;
+use crate::taxonomy::Behaviour;
+
+/// Columns of the files written by `Dataset::to_csv`
+pub const DATASET_COLUMNS: [&str; 4] = ["program", "option", "has_arg", "behaviours"];
+
+/// Name of a char (`-x`) or string (`--long`) option
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptionName {
+    Char(char),
+    String(String),
+}
+
+impl OptionName {
+    /// `-x` or `--long`; without dashes, single characters are char options. `--` is the `-`
+    /// char option.
+    pub fn parse(name: &str) -> Option<OptionName> {
+        let bare = match name.strip_prefix("--") {
+            Some("") => return Some(OptionName::Char('-')),
+            Some(long) => return Some(OptionName::String(long.to_string())),
+            None => name.strip_prefix('-').unwrap_or(name),
+        };
+        let mut chars = bare.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(OptionName::Char(c)),
+            (Some(_), Some(_)) if name == bare => Some(OptionName::String(bare.to_string())),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for OptionName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptionName::Char(c) => write!(f, "-{c}"),
+            OptionName::String(s) => write!(f, "--{s}"),
+        }
+    }
+}
+
+impl Serialize for OptionName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatasetOption {
+    pub name: OptionName,
+    pub has_arg: Option<bool>,
+    pub behaviours: Vec<Behaviour>,
+}
+
+/// The labels of a program in the dataset
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatasetProgram {
+    pub program: String,
+    /// Behaviours of the program itself, if the dataset gives them
+    pub behaviours: Option<Vec<Behaviour>>,
+    pub options: Vec<DatasetOption>,
+}
+
+/// Programs of the dataset, sorted by name
+#[derive(Debug, Clone, Default)]
+pub struct Dataset {
+    pub programs: Vec<DatasetProgram>,
+}
+
+impl Dataset {
+    /// Read a dataset file, or every `.csv`/`.tsv` file of a directory
+    pub fn read(path: &Path) -> Result<Dataset, String> {
+        let mut files = vec![];
+        if path.is_dir() {
+            let entries =
+                std::fs::read_dir(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            files = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| {
+                    file.is_file()
+                        && file
+                            .extension()
+                            .is_some_and(|ext| ext == "csv" || ext == "tsv")
+                })
+                .collect();
+            files.sort();
+        } else {
+            files.push(path.to_path_buf());
+        }
+
+        let mut programs: BTreeMap<String, DatasetProgram> = BTreeMap::new();
+        for file in files {
+            let contents =
+                std::fs::read_to_string(&file).map_err(|e| format!("{}: {}", file.display(), e))?;
+            let default_program = file
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned());
+            read_csv(&contents, default_program.as_deref(), &mut programs)
+                .map_err(|e| format!("{}:{}", file.display(), e))?;
+        }
+        Ok(Dataset {
+            programs: programs.into_values().collect(),
+        })
+    }
+
+    /// The labels of `program`
+    pub fn get(&self, program: &str) -> Option<&DatasetProgram> {
+        self.programs.iter().find(|entry| entry.program == program)
+    }
+
+    /// The dataset as a single CSV file with the `DATASET_COLUMNS`
+    pub fn to_csv(&self) -> String {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        let mut write_row = |row: [&str; 4]| {
+            writer
+                .write_record(row)
+                .expect("Writing to memory does not fail");
+        };
+        write_row(DATASET_COLUMNS);
+        for program in &self.programs {
+            if let Some(behaviours) = &program.behaviours {
+                write_row([&program.program, "", "", &join_behaviours(behaviours)]);
+            }
+            for option in &program.options {
+                let has_arg = option
+                    .has_arg
+                    .map(|has_arg| has_arg.to_string())
+                    .unwrap_or_default();
+                write_row([
+                    &program.program,
+                    &option.name.to_string(),
+                    &has_arg,
+                    &join_behaviours(&option.behaviours),
+                ]);
+            }
+        }
+        let csv = writer
+            .into_inner()
+            .expect("Writing to memory does not fail");
+        String::from_utf8(csv).expect("The fields are UTF-8")
+    }
+}
+
+impl DatasetProgram {
+    /// The labels of a loaded config, `program` being the name of the config
+    pub fn from_config(program: &str, config: &ParserConfig) -> DatasetProgram {
+        let char_options = config
+            .char_options
+            .iter()
+            .map(|(name, has_arg)| DatasetOption {
+                name: OptionName::Char(*name),
+                has_arg: Some(*has_arg),
+                behaviours: config
+                    .get_behaviours_for_char_option(name)
+                    .unwrap_or_default(),
+            });
+        let string_options = config
+            .string_options
+            .iter()
+            .map(|(name, has_arg)| DatasetOption {
+                name: OptionName::String(name.clone()),
+                has_arg: Some(*has_arg),
+                behaviours: config
+                    .get_behaviours_for_string_option(name)
+                    .unwrap_or_default(),
+            });
+        DatasetProgram {
+            program: program.to_string(),
+            behaviours: Some(config.config_file.behaviours.clone()),
+            options: char_options.chain(string_options).collect(),
+        }
+    }
+
+    fn option(&self, name: &OptionName) -> Option<&DatasetOption> {
+        self.options.iter().find(|option| option.name == *name)
+    }
+}
+
+/// Differences between the dataset and the configs
+#[derive(Debug, Default, Serialize)]
+pub struct DatasetDiff {
+    /// Programs of the dataset without a config
+    pub uncovered_programs: Vec<String>,
+    /// Configs of programs the dataset does not label
+    pub unlabelled_configs: Vec<String>,
+    /// Options labelled by the dataset but missing from the config of their program
+    pub missing_options: Vec<ProgramOption>,
+    /// Options of the configs the dataset does not label
+    pub unlabelled_options: Vec<ProgramOption>,
+    /// Programs and options labelled differently by the dataset and the configs
+    pub label_disagreements: Vec<LabelDisagreement>,
+    /// Configs that failed to load, with the error
+    pub errors: Vec<(String, String)>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProgramOption {
+    pub program: String,
+    pub option: OptionName,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LabelDisagreement {
+    pub program: String,
+    /// None for the behaviours of the program itself
+    pub option: Option<OptionName>,
+    pub dataset: Vec<Behaviour>,
+    pub config: Vec<Behaviour>,
+}
+
+impl DatasetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.uncovered_programs.is_empty()
+            && self.unlabelled_configs.is_empty()
+            && self.missing_options.is_empty()
+            && self.unlabelled_options.is_empty()
+            && self.label_disagreements.is_empty()
+            && self.errors.is_empty()
+    }
+}
+
+/// Compare the labels of `dataset` with the configs of `cache`, resolving the programs of the
+/// dataset like requests are
+pub fn diff(dataset: &Dataset, cache: &ParserConfigCache) -> DatasetDiff {
+    let mut diff = DatasetDiff::default();
+    let mut used_configs = vec![];
+    for program in &dataset.programs {
+        let Some(config_name) = cache.resolve(&program.program) else {
+            diff.uncovered_programs.push(program.program.clone());
+            continue;
+        };
+        let config = match cache.get_config(&config_name) {
+            Ok(config) => config,
+            Err(err) => {
+                diff.errors.push((config_name, err));
+                continue;
+            }
+        };
+        used_configs.push(config_name.clone());
+        let configured = DatasetProgram::from_config(&config_name, &config);
+
+        if let (Some(dataset_behaviours), Some(config_behaviours)) =
+            (&program.behaviours, &configured.behaviours)
+            && !same_labels(dataset_behaviours, config_behaviours)
+        {
+            diff.label_disagreements.push(LabelDisagreement {
+                program: program.program.clone(),
+                option: None,
+                dataset: dataset_behaviours.clone(),
+                config: config_behaviours.clone(),
+            });
+        }
+        for option in &program.options {
+            match configured.option(&option.name) {
+                Some(configured) if !same_labels(&option.behaviours, &configured.behaviours) => {
+                    diff.label_disagreements.push(LabelDisagreement {
+                        program: program.program.clone(),
+                        option: Some(option.name.clone()),
+                        dataset: option.behaviours.clone(),
+                        config: configured.behaviours.clone(),
+                    })
+                }
+                Some(_) => {}
+                None => diff.missing_options.push(ProgramOption {
+                    program: program.program.clone(),
+                    option: option.name.clone(),
+                }),
+            }
+        }
+        for option in &configured.options {
+            if program.option(&option.name).is_none() {
+                diff.unlabelled_options.push(ProgramOption {
+                    program: program.program.clone(),
+                    option: option.name.clone(),
+                });
+            }
+        }
+    }
+    diff.unlabelled_configs = cache
+        .registry()
+        .entries()
+        .iter()
+        .map(|entry| entry.config.clone())
+        .filter(|config| !used_configs.contains(config))
+        .collect();
+    diff
+}
+
+/// What importing the labels of a program changed in its config
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// Options added to the config
+    pub added: usize,
+    /// Options (or the program itself) whose behaviours or argument were changed
+    pub updated: usize,
+}
+
+/// Apply the labels of `program` to the `existing` config (a new config if None): behaviours and
+/// arguments of the options it has are replaced by the dataset's, and the other options are
+/// added. Options `merged`, the existing config with its bases and fragments, already labels the
+/// same way are not repeated. The rest of the config, comments included, is kept as is.
+pub fn import_program(
+    existing: Option<&str>,
+    merged: Option<&ParserConfig>,
+    program: &DatasetProgram,
+) -> Result<(String, ImportSummary), String> {
+    let existing = match existing {
+        Some(existing) => existing.to_string(),
+        None => config_gen::render_config(&program.program, &[]),
+    };
+    let mut config: toml_edit::DocumentMut = existing.parse().map_err(|e| format!("{e}"))?;
+    let mut summary = ImportSummary::default();
+
+    if let Some(behaviours) = &program.behaviours
+        && !has_labels(config.get("behaviours"), behaviours)
+    {
+        config["behaviours"] = toml_edit::value(behaviour_array(behaviours));
+        summary.updated += 1;
+    }
+    for option in &program.options {
+        let (table_name, option_name) = match &option.name {
+            OptionName::Char(c) => ("char_options", c.to_string()),
+            OptionName::String(s) => ("string_options", s.clone()),
+        };
+        if config.get(table_name).is_none() {
+            config[table_name] = toml_edit::Item::ArrayOfTables(toml_edit::ArrayOfTables::new());
+        }
+        let tables = config[table_name]
+            .as_array_of_tables_mut()
+            .ok_or_else(|| format!("{table_name} is not an array of tables"))?;
+
+        let existing = tables.iter_mut().find(|table| {
+            table.get("option_name").and_then(|name| name.as_str()) == Some(option_name.as_str())
+        });
+        match existing {
+            Some(table) => {
+                let mut updated = false;
+                if !has_labels(table.get("behaviours"), &option.behaviours) {
+                    table["behaviours"] = toml_edit::value(behaviour_array(&option.behaviours));
+                    updated = true;
+                }
+                if let Some(has_arg) = option.has_arg
+                    && table
+                        .get("has_arg")
+                        .and_then(|item| item.as_bool())
+                        .unwrap_or(false)
+                        != has_arg
+                {
+                    table["has_arg"] = toml_edit::value(has_arg);
+                    updated = true;
+                }
+                summary.updated += usize::from(updated);
+            }
+            // Inherited through `extends` or `include` with the same labels
+            None if merged.is_some_and(|merged| labels_option(merged, option)) => {}
+            None => {
+                let mut table = toml_edit::Table::new();
+                table.decor_mut().set_prefix("\n");
+                table["option_name"] = toml_edit::value(option_name);
+                if let Some(has_arg) = option.has_arg {
+                    table["has_arg"] = toml_edit::value(has_arg);
+                }
+                table["behaviours"] = toml_edit::value(behaviour_array(&option.behaviours));
+                tables.push(table);
+                summary.added += 1;
+            }
+        }
+    }
+    Ok((config.to_string(), summary))
+}
+
+/// Parse the rows of a dataset file into `programs`. Errors start with their line number.
+fn read_csv(
+    contents: &str,
+    default_program: Option<&str>,
+    programs: &mut BTreeMap<String, DatasetProgram>,
+) -> Result<(), String> {
+    let header = contents
+        .lines()
+        .find(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .ok_or("1: missing header")?;
+    let delimiter = [b',', b';', b'\t']
+        .into_iter()
+        .max_by_key(|delimiter| header.matches(char::from(*delimiter)).count())
+        .unwrap_or(b',');
+    let mut records = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .comment(Some(b'#'))
+        .trim(csv::Trim::All)
+        .from_reader(contents.as_bytes())
+        .into_records();
+
+    let header = records
+        .next()
+        .ok_or("1: missing header")?
+        .map_err(|err| csv_error(contents, err))?;
+    let header_line = line_number(contents, header.position());
+    let columns: Vec<String> = header.iter().map(str::to_lowercase).collect();
+    let column = |name: &str| columns.iter().position(|column| column == name);
+    let (program_col, option_col, has_arg_col) =
+        (column("program"), column("option"), column("has_arg"));
+    let behaviours_col = column("behaviours")
+        .or_else(|| column("behaviors"))
+        .ok_or(format!("{header_line}: missing \"behaviours\" column"))?;
+    let option_col = option_col.ok_or(format!("{header_line}: missing \"option\" column"))?;
+    if program_col.is_none() && default_program.is_none() {
+        return Err(format!("{header_line}: missing \"program\" column"));
+    }
+
+    for record in records {
+        let record = record.map_err(|err| csv_error(contents, err))?;
+        let line_number = line_number(contents, record.position());
+        let field = |col: usize| record.get(col).unwrap_or_default();
+
+        let program = match program_col.map(field) {
+            Some(program) if !program.is_empty() => program,
+            _ => default_program.ok_or(format!("{line_number}: missing program"))?,
+        };
+        let behaviours = field(behaviours_col)
+            .split(|c: char| c == '|' || c.is_whitespace())
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                Behaviour::from_name(name)
+                    .ok_or_else(|| format!("{line_number}: unknown behaviour \"{name}\""))
+            })
+            .collect::<Result<Vec<Behaviour>, String>>()?;
+        let has_arg = match has_arg_col.map(field).unwrap_or_default() {
+            "" => None,
+            "true" | "1" | "yes" => Some(true),
+            "false" | "0" | "no" => Some(false),
+            other => return Err(format!("{line_number}: invalid has_arg \"{other}\"")),
+        };
+
+        let entry = programs
+            .entry(program.to_string())
+            .or_insert_with(|| DatasetProgram {
+                program: program.to_string(),
+                behaviours: None,
+                options: vec![],
+            });
+        let option = field(option_col);
+        if option.is_empty() {
+            add_labels(entry.behaviours.get_or_insert_with(Vec::new), behaviours);
+            continue;
+        }
+        let name = OptionName::parse(option)
+            .ok_or_else(|| format!("{line_number}: invalid option \"{option}\""))?;
+        match entry.options.iter_mut().find(|known| known.name == name) {
+            Some(known) => {
+                add_labels(&mut known.behaviours, behaviours);
+                known.has_arg = known.has_arg.or(has_arg);
+            }
+            None => entry.options.push(DatasetOption {
+                name,
+                has_arg,
+                behaviours,
+            }),
+        }
+    }
+    Ok(())
+}
+
+/// Whether `config` has `option` with its behaviours and argument
+fn labels_option(config: &ParserConfig, option: &DatasetOption) -> bool {
+    let (has_arg, behaviours) = match &option.name {
+        OptionName::Char(name) => (
+            config
+                .char_options
+                .iter()
+                .find(|(known, _)| known == name)
+                .map(|(_, has_arg)| *has_arg),
+            config.get_behaviours_for_char_option(name),
+        ),
+        OptionName::String(name) => (
+            config
+                .string_options
+                .iter()
+                .find(|(known, _)| known == name)
+                .map(|(_, has_arg)| *has_arg),
+            config.get_behaviours_for_string_option(name),
+        ),
+    };
+    has_arg.is_some_and(|has_arg| option.has_arg.unwrap_or(has_arg) == has_arg)
+        && behaviours.is_ok_and(|behaviours| same_labels(&behaviours, &option.behaviours))
+}
+
+/// Line of `contents` a record starts at. The position of a record is where the reader started
+/// reading it, before the comment and empty lines it skipped.
+fn line_number(contents: &str, position: Option<&csv::Position>) -> usize {
+    let offset = position.map_or(0, |position| position.byte() as usize);
+    let (before, after) = contents.split_at(offset.min(contents.len()));
+    let skipped = after
+        .lines()
+        .take_while(|line| line.is_empty() || line.starts_with('#'))
+        .count();
+    before.matches('\n').count() + skipped + 1
+}
+
+fn csv_error(contents: &str, err: csv::Error) -> String {
+    format!("{}: {err}", line_number(contents, err.position()))
+}
+
+fn join_behaviours(behaviours: &[Behaviour]) -> String {
+    behaviours
+        .iter()
+        .map(|behaviour| behaviour.name())
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+fn add_labels(labels: &mut Vec<Behaviour>, behaviours: Vec<Behaviour>) {
+    for behaviour in behaviours {
+        if !labels.contains(&behaviour) {
+            labels.push(behaviour);
+        }
+    }
+}
+
+/// Whether two lists of behaviours hold the same behaviours, in any order
+fn same_labels(a: &[Behaviour], b: &[Behaviour]) -> bool {
+    a.iter().all(|behaviour| b.contains(behaviour))
+        && b.iter().all(|behaviour| a.contains(behaviour))
+}
+
+/// Whether a `behaviours` item of a config holds `behaviours`
+fn has_labels(item: Option<&toml_edit::Item>, behaviours: &[Behaviour]) -> bool {
+    let Some(array) = item.and_then(|item| item.as_array()) else {
+        return false;
+    };
+    let labels: Option<Vec<Behaviour>> = array
+        .iter()
+        .map(|value| value.as_str().and_then(Behaviour::from_name))
+        .collect();
+    labels.is_some_and(|labels| same_labels(&labels, behaviours))
+}
+
+fn behaviour_array(behaviours: &[Behaviour]) -> toml_edit::Array {
+    behaviours
+        .iter()
+        .map(|behaviour| behaviour.name())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_store::ConfigDir;
+
+    const FOO: &str = r#"
+name = "foo"
+has_separatorless_args_for_char_options = false
+string_separators = ["="]
+handle_quotes = false
+behaviours = ["NEUTRAL"]
+
+[[char_options]]
+option_name = "a"
+behaviours = ["FILE_READ"]
+
+[[string_options]]
+option_name = "long"
+has_arg = true
+behaviours = ["NEUTRAL"]
+"#;
+
+    fn behaviours(names: &[&str]) -> Vec<Behaviour> {
+        names
+            .iter()
+            .map(|name| Behaviour::from_name(name).unwrap())
+            .collect()
+    }
+
+    fn read(contents: &str) -> Result<Dataset, String> {
+        let mut programs = BTreeMap::new();
+        read_csv(contents, None, &mut programs)?;
+        Ok(Dataset {
+            programs: programs.into_values().collect(),
+        })
+    }
+
+    #[test]
+    fn diff_with_the_configs() {
+        let dir = ConfigDir::new(
+            "dataset-diff",
+            &[("foo.toml", FOO), ("baz.toml", &FOO.replace("foo", "baz"))],
+        );
+        let dataset = read(
+            "program,option,has_arg,behaviours
+foo,,,NEUTRAL
+foo,-a,,FILE_WRITE
+foo,-b,false,NEUTRAL
+bar,-x,,NEUTRAL
+",
+        )
+        .unwrap();
+        let diff = diff(&dataset, &ParserConfigCache::new(dir.store()));
+
+        assert_eq!(diff.uncovered_programs, ["bar"]);
+        assert_eq!(diff.unlabelled_configs, ["baz"]);
+        let options = |options: &[ProgramOption]| -> Vec<String> {
+            options
+                .iter()
+                .map(|option| format!("{} {}", option.program, option.option))
+                .collect()
+        };
+        assert_eq!(options(&diff.missing_options), ["foo -b"]);
+        assert_eq!(options(&diff.unlabelled_options), ["foo --long"]);
+        let [disagreement] = diff.label_disagreements.as_slice() else {
+            panic!("{:?}", diff.label_disagreements);
+        };
+        assert_eq!(disagreement.program, "foo");
+        assert_eq!(disagreement.option, Some(OptionName::Char('a')));
+        assert_eq!(disagreement.dataset, behaviours(&["FILE_WRITE"]));
+        assert_eq!(disagreement.config, behaviours(&["FILE_READ"]));
+        assert!(diff.errors.is_empty());
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn import_into_a_config() {
+        let dataset = read(
+            "behaviours;option;program;has_arg
+NET_COMS;;foo;
+FILE_WRITE;-a;foo;
+NEUTRAL;--long;foo;true
+CMD_EXEC FILE_READ;--new;foo;false
+",
+        )
+        .unwrap();
+        let program = dataset.get("foo").unwrap();
+
+        let (config, summary) = import_program(Some(FOO), None, program).unwrap();
+        // The program and -a are relabelled, --long already agrees, --new is added
+        assert_eq!((summary.updated, summary.added), (2, 1));
+        assert!(config.contains("\n[[char_options]]\noption_name = \"a\"\n"));
+        let config = ParserConfig::from_toml_str(&config).unwrap();
+        assert_eq!(config.config_file.behaviours, behaviours(&["NET_COMS"]));
+        assert_eq!(
+            config.get_behaviours_for_char_option(&'a'),
+            Ok(behaviours(&["FILE_WRITE"]))
+        );
+        assert_eq!(
+            config.get_behaviours_for_string_option("new"),
+            Ok(behaviours(&["CMD_EXEC", "FILE_READ"]))
+        );
+        assert_eq!(
+            config.does_string_option_have_arg(&"new".to_string()),
+            Ok(false)
+        );
+
+        // Options the config inherits with the same labels are not repeated
+        let merged = ParserConfig::from_toml_str(&format!(
+            "{FOO}
+[[string_options]]
+option_name = \"new\"
+behaviours = [\"FILE_READ\", \"CMD_EXEC\"]
+"
+        ))
+        .unwrap();
+        let (_, summary) = import_program(Some(FOO), Some(&merged), program).unwrap();
+        assert_eq!((summary.updated, summary.added), (2, 0));
+    }
+
+    #[test]
+    fn quoted_fields_round_trip() {
+        let dataset = Dataset {
+            programs: vec![
+                DatasetProgram {
+                    program: "a,b".to_string(),
+                    behaviours: Some(behaviours(&["NEUTRAL"])),
+                    options: vec![
+                        DatasetOption {
+                            name: OptionName::String("with,comma".to_string()),
+                            has_arg: Some(true),
+                            behaviours: behaviours(&["FILE_READ", "CMD_EXEC"]),
+                        },
+                        DatasetOption {
+                            name: OptionName::Char('"'),
+                            has_arg: None,
+                            behaviours: behaviours(&["NEUTRAL"]),
+                        },
+                    ],
+                },
+                DatasetProgram {
+                    program: "line\nbreak".to_string(),
+                    behaviours: None,
+                    options: vec![DatasetOption {
+                        name: OptionName::String("x;y\tz".to_string()),
+                        has_arg: Some(false),
+                        behaviours: behaviours(&["NET_COMS"]),
+                    }],
+                },
+            ],
+        };
+        let csv = dataset.to_csv();
+        assert!(csv.contains("\"a,b\",\"--with,comma\",true,FILE_READ|CMD_EXEC\n"));
+        assert_eq!(read(&csv).unwrap().programs, dataset.programs);
+
+        // Into a config, as `ucp dataset import` does
+        let (config, _) = import_program(None, None, &dataset.programs[0]).unwrap();
+        let config = ParserConfig::from_toml_str(&config).unwrap();
+        assert_eq!(config.name, "a,b");
+        assert_eq!(
+            config.get_behaviours_for_string_option("with,comma"),
+            Ok(behaviours(&["FILE_READ", "CMD_EXEC"]))
+        );
+    }
+
+    #[test]
+    fn errors_give_their_line() {
+        assert_eq!(
+            read("# comment\n\nprogram,option,behaviours\n# c\nfoo,-a,\"BOGUS\"\n")
+                .err()
+                .as_deref(),
+            Some("5: unknown behaviour \"BOGUS\"")
+        );
+        assert_eq!(
+            read("program,option,behaviours\n\"foo\nbar\",-a,NEUTRAL\nfoo,-c,BOGUS\n")
+                .err()
+                .as_deref(),
+            Some("4: unknown behaviour \"BOGUS\"")
+        );
+        assert_eq!(
+            read("program,behaviours\n").err().as_deref(),
+            Some("1: missing \"option\" column")
+        );
+    }
+}
@@ -0,0 +1,153 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Environment variables set for a program, inline (`LD_PRELOAD=/tmp/x.so ls`) or in the
+//! environment of the request, and the behaviours they bring. Configs may describe the variables
+//! their program reads; the global rules below cover those that change any program's behaviour.
+
+use serde::Serialize;
+use std::borrow::Cow;
+
+use crate::parser_structs::{CLArgument, CLElement};
+use crate::taxonomy::{self, Behaviour, BehaviourSet};
+
+/// What setting a variable does to a program
+#[derive(Debug, Clone, Copy)]
+pub struct EnvVarRule {
+    pub behaviours: BehaviourSet,
+    /// Whether the value is a command-line the program runs (`GIT_SSH_COMMAND`), to be parsed
+    pub command: bool,
+}
+
+/// Variables changing the behaviour of any program: its name, its behaviours and whether its
+/// value is a command-line
+const ENV_RULES: &[(&str, &[&str], bool)] = &[
+    // Code loaded into every program
+    ("LD_PRELOAD", &["CMD_EXEC"], false),
+    ("LD_LIBRARY_PATH", &["CMD_EXEC"], false),
+    ("LD_AUDIT", &["CMD_EXEC"], false),
+    ("DYLD_INSERT_LIBRARIES", &["CMD_EXEC"], false),
+    ("DYLD_LIBRARY_PATH", &["CMD_EXEC"], false),
+    // Code run by shells and interpreters
+    ("BASH_ENV", &["CMD_EXEC"], false),
+    ("ENV", &["CMD_EXEC"], false),
+    ("PROMPT_COMMAND", &["CMD_EXEC"], true),
+    ("PYTHONSTARTUP", &["CMD_EXEC"], false),
+    ("PYTHONPATH", &["CMD_EXEC"], false),
+    ("PERL5OPT", &["CMD_EXEC"], false),
+    ("PERL5LIB", &["CMD_EXEC"], false),
+    ("RUBYOPT", &["CMD_EXEC"], false),
+    ("NODE_OPTIONS", &["CMD_EXEC"], false),
+    // Commands run on behalf of the program
+    ("GIT_SSH_COMMAND", &["CMD_EXEC"], true),
+    ("GIT_SSH", &["CMD_EXEC"], true),
+    ("GIT_EDITOR", &["CMD_EXEC"], true),
+    ("GIT_PAGER", &["CMD_EXEC"], true),
+    ("GIT_ASKPASS", &["CMD_EXEC"], true),
+    ("SSH_ASKPASS", &["CMD_EXEC"], true),
+    ("SUDO_ASKPASS", &["CMD_EXEC"], true),
+    ("EDITOR", &["CMD_EXEC"], true),
+    ("VISUAL", &["CMD_EXEC"], true),
+    ("PAGER", &["CMD_EXEC"], true),
+    ("MANPAGER", &["CMD_EXEC"], true),
+    ("BROWSER", &["CMD_EXEC"], true),
+    // Where connections go, and which certificates are trusted
+    ("GIT_PROXY_COMMAND", &["NET_CFG", "CMD_EXEC"], true),
+    ("http_proxy", &["NET_CFG"], false),
+    ("https_proxy", &["NET_CFG"], false),
+    ("ftp_proxy", &["NET_CFG"], false),
+    ("all_proxy", &["NET_CFG"], false),
+    ("no_proxy", &["NET_CFG"], false),
+    ("HTTP_PROXY", &["NET_CFG"], false),
+    ("HTTPS_PROXY", &["NET_CFG"], false),
+    ("FTP_PROXY", &["NET_CFG"], false),
+    ("ALL_PROXY", &["NET_CFG"], false),
+    ("NO_PROXY", &["NET_CFG"], false),
+    ("SSL_CERT_FILE", &["NET_CFG"], false),
+    ("SSL_CERT_DIR", &["NET_CFG"], false),
+    ("CURL_CA_BUNDLE", &["NET_CFG"], false),
+    ("REQUESTS_CA_BUNDLE", &["NET_CFG"], false),
+    ("GIT_SSL_NO_VERIFY", &["NET_CFG"], false),
+    ("NODE_TLS_REJECT_UNAUTHORIZED", &["NET_CFG"], false),
+];
+
+/// The global rule of the variable `name`, if it has one
+pub fn global_rule(name: &str) -> Option<EnvVarRule> {
+    let (_, behaviours, command) = ENV_RULES.iter().find(|(rule, _, _)| *rule == name)?;
+    // A custom taxonomy may not have the behaviours
+    let behaviours: Vec<Behaviour> = behaviours
+        .iter()
+        .filter_map(|name| Behaviour::from_name(name))
+        .collect();
+    Some(EnvVarRule {
+        behaviours: taxonomy::intern_behaviours(&behaviours),
+        command: *command,
+    })
+}
+
+/// The name and value of `word` if it is an assignment (`NAME=value`), as written before a
+/// command
+pub fn split_assignment(word: &str) -> Option<(&str, &str)> {
+    let (name, value) = word.split_once('=')?;
+    is_name(name).then_some((name, value))
+}
+
+/// Whether `name` may be the name of a variable: letters, digits and underscores, not starting
+/// with a digit
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A variable set for the program, with the behaviours it brings
+#[derive(Debug, Serialize, Clone)]
+pub struct EnvAssignment<'a> {
+    pub name: Cow<'a, str>,
+    pub value: CLArgument<'a>,
+    pub behaviours: BehaviourSet,
+    /// Whether the assignment is written before the command, rather than in the environment of
+    /// the request
+    pub inline: bool,
+    /// Behaviour tree of the command-line held by the variable, for command-valued ones
+    pub command: Option<Vec<CLElement<'static>>>,
+}
+
+impl<'a> EnvAssignment<'a> {
+    /// The assignment of `value` to `name`, without behaviours
+    pub fn new(name: Cow<'a, str>, value: Cow<'a, str>, inline: bool) -> EnvAssignment<'a> {
+        EnvAssignment {
+            name,
+            value: CLArgument::identified(value),
+            behaviours: BehaviourSet::EMPTY,
+            inline,
+            command: None,
+        }
+    }
+
+    pub fn into_owned(self) -> EnvAssignment<'static> {
+        EnvAssignment {
+            name: Cow::Owned(self.name.into_owned()),
+            value: self.value.into_owned(),
+            behaviours: self.behaviours,
+            inline: self.inline,
+            command: self.command,
+        }
+    }
+}
@@ -0,0 +1,435 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::time::Instant;
+
+use axum::http::StatusCode;
+use hyper_util::rt::TokioIo;
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::{Channel, Endpoint, Server, Uri};
+use tonic::{Request, Response, Status, Streaming};
+use tower::service_fn;
+
+use crate::analyzer::{Analyzer, AnalyzerError, roll_up_behaviour_tree};
+use crate::parser_structs::{CLArgument, CLElement, Host};
+use crate::path_classifier::PathContext;
+use crate::server::{error_message, error_status, record_request};
+
+/// Code generated from `proto/ucp.proto`
+pub mod proto {
+    tonic::include_proto!("ucp.v1");
+}
+
+use proto::analyzer_client::AnalyzerClient;
+use proto::analyzer_server::{Analyzer as AnalyzerRpc, AnalyzerServer};
+use proto::{
+    AnalyzeRequest, AnalyzeResponse, ListProgramsRequest, ListProgramsResponse, analyze_error,
+};
+
+/// gRPC front-end of an `Analyzer`, sharing its config cache with the HTTP API
+#[derive(Clone)]
+pub struct AnalyzerService {
+    analyzer: Analyzer,
+}
+
+impl AnalyzerService {
+    pub fn new(analyzer: Analyzer) -> AnalyzerService {
+        AnalyzerService { analyzer }
+    }
+
+    /// Wrap the service into the tonic server type
+    pub fn into_server(self) -> AnalyzerServer<AnalyzerService> {
+        AnalyzerServer::new(self)
+    }
+
+    async fn analyze_request(
+        &self,
+        route: &'static str,
+        request: AnalyzeRequest,
+    ) -> Result<AnalyzeResponse, AnalyzerError> {
+        let start_time = Instant::now();
+        let context = PathContext {
+            cwd: request.cwd,
+            user: request.user,
+            home: request.home,
+            env: request.env,
+        };
+        let result = self
+            .analyzer
+            .analyze_in_async(&request.program, &request.args, &context)
+            .await
+            .map(|behaviour_tree| match request.depth {
+                Some(depth) => roll_up_behaviour_tree(behaviour_tree, depth as usize),
+                None => behaviour_tree,
+            });
+        // Same labels as the HTTP API
+        let status = match &result {
+            Ok(_) => StatusCode::OK,
+            Err(err) => error_status(err),
+        };
+        let config = self.analyzer.config_name(&request.program, &request.args);
+        record_request(route, config.as_deref(), status, start_time);
+
+        let behaviour_tree = result?;
+        Ok(AnalyzeResponse {
+            program: request.program,
+            elements: behaviour_tree.iter().map(proto::Element::from).collect(),
+            error: None,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl AnalyzerRpc for AnalyzerService {
+    async fn analyze(
+        &self,
+        request: Request<AnalyzeRequest>,
+    ) -> Result<Response<AnalyzeResponse>, Status> {
+        self.analyze_request("/ucp.v1.Analyzer/Analyze", request.into_inner())
+            .await
+            .map(Response::new)
+            .map_err(|err| match err {
+                AnalyzerError::UnknownOption { .. } => {
+                    Status::invalid_argument(error_message(&err))
+                }
+                _ => Status::not_found(error_message(&err)),
+            })
+    }
+
+    type AnalyzeBatchStream =
+        Pin<Box<dyn Stream<Item = Result<AnalyzeResponse, Status>> + Send + 'static>>;
+
+    async fn analyze_batch(
+        &self,
+        request: Request<Streaming<AnalyzeRequest>>,
+    ) -> Result<Response<Self::AnalyzeBatchStream>, Status> {
+        let service = self.clone();
+        let responses = request.into_inner().then(move |request| {
+            let service = service.clone();
+            async move {
+                let request = request?;
+                let program = request.program.clone();
+                Ok(service
+                    .analyze_request("/ucp.v1.Analyzer/AnalyzeBatch", request)
+                    .await
+                    .unwrap_or_else(|err| AnalyzeResponse {
+                        program,
+                        elements: vec![],
+                        error: Some(proto::AnalyzeError::from(&err)),
+                    }))
+            }
+        });
+        Ok(Response::new(Box::pin(responses)))
+    }
+
+    async fn list_programs(
+        &self,
+        _request: Request<ListProgramsRequest>,
+    ) -> Result<Response<ListProgramsResponse>, Status> {
+        let programs = self.analyzer.programs();
+        Ok(Response::new(ListProgramsResponse { programs }))
+    }
+}
+
+/// Serve the gRPC service on `address` until `shutdown` resolves
+pub async fn serve(
+    address: SocketAddr,
+    analyzer: Analyzer,
+    shutdown: impl Future<Output = ()>,
+) -> std::io::Result<()> {
+    println!("Starting gRPC server on {address}");
+    Server::builder()
+        .add_service(AnalyzerService::new(analyzer).into_server())
+        .serve_with_shutdown(address, shutdown)
+        .await
+        .map_err(std::io::Error::other)
+}
+
+/// Run the gRPC service in-process and return a client connected to it, without any socket.
+/// Useful to try or test the service locally.
+pub async fn in_process_client(
+    analyzer: Analyzer,
+) -> Result<AnalyzerClient<Channel>, tonic::transport::Error> {
+    let (client_io, server_io) = tokio::io::duplex(1024 * 1024);
+
+    tokio::spawn(async move {
+        Server::builder()
+            .add_service(AnalyzerService::new(analyzer).into_server())
+            .serve_with_incoming(tokio_stream::once(Ok::<_, std::io::Error>(server_io)))
+            .await
+    });
+
+    // The URI is never resolved, the connector hands over the in-memory stream instead
+    let mut client_io = Some(client_io);
+    let channel = Endpoint::from_static("http://in-process")
+        .connect_with_connector(service_fn(move |_: Uri| {
+            let client_io = client_io.take();
+            async move {
+                client_io
+                    .map(TokioIo::new)
+                    .ok_or_else(|| std::io::Error::other("The in-process stream was already used"))
+            }
+        }))
+        .await?;
+    Ok(AnalyzerClient::new(channel))
+}
+
+impl From<&AnalyzerError> for proto::AnalyzeError {
+    fn from(err: &AnalyzerError) -> Self {
+        let kind = match err {
+            AnalyzerError::UnknownOption { .. } => analyze_error::Kind::UnknownOption,
+            AnalyzerError::UnknownProgram { .. } => analyze_error::Kind::UnknownProgram,
+            _ => analyze_error::Kind::ConfigNotFound,
+        };
+        proto::AnalyzeError {
+            kind: kind.into(),
+            message: error_message(err),
+        }
+    }
+}
+
+impl From<&CLElement<'_>> for proto::Element {
+    fn from(elem: &CLElement<'_>) -> Self {
+        use proto::element::Kind;
+        let kind = match elem {
+            CLElement::CLOption((name, argument)) => Kind::Option(proto::Option {
+                name: name.to_string(),
+                argument: argument.as_ref().map(proto::Argument::from),
+            }),
+            CLElement::CLBehaviouredOption((name, behaviours, argument)) => {
+                Kind::BehaviouredOption(proto::BehaviouredOption {
+                    name: name.to_string(),
+                    behaviours: behaviours.iter().map(|b| b.name().to_string()).collect(),
+                    argument: argument.as_ref().map(proto::Argument::from),
+                })
+            }
+            CLElement::CLInherentBehaviour(behaviours) => {
+                Kind::InherentBehaviour(proto::InherentBehaviour {
+                    behaviours: behaviours.iter().map(|b| b.name().to_string()).collect(),
+                })
+            }
+            CLElement::CLArgument(argument) => Kind::Argument(proto::Argument::from(argument)),
+            CLElement::CLSep(separator) => Kind::Separator(separator.to_string()),
+            CLElement::ParsingError(message) => Kind::ParsingError(proto::ParsingError {
+                message: message.clone(),
+            }),
+            CLElement::CLDoubleDash => Kind::DoubleDash(proto::DoubleDash {}),
+            CLElement::CLBehaviouredArgument((argument, behaviours)) => {
+                Kind::BehaviouredArgument(proto::BehaviouredArgument {
+                    argument: Some(proto::Argument::from(argument)),
+                    behaviours: behaviours.iter().map(|b| b.name().to_string()).collect(),
+                })
+            }
+            CLElement::CLHeuristic(confidence) => Kind::Heuristic(proto::Heuristic {
+                confidence: *confidence,
+            }),
+            CLElement::CLEnvAssignment(assignment) => Kind::EnvAssignment(proto::EnvAssignment {
+                name: assignment.name.to_string(),
+                value: Some(proto::Argument::from(&assignment.value)),
+                behaviours: assignment
+                    .behaviours
+                    .iter()
+                    .map(|b| b.name().to_string())
+                    .collect(),
+                inline: assignment.inline,
+                command: assignment.command.as_ref().map(|command| proto::Command {
+                    elements: command.iter().map(proto::Element::from).collect(),
+                }),
+            }),
+        };
+        proto::Element { kind: Some(kind) }
+    }
+}
+
+impl From<&CLArgument<'_>> for proto::Argument {
+    fn from(argument: &CLArgument<'_>) -> Self {
+        use proto::argument::Value;
+        let value = match argument {
+            CLArgument::String(value) => Value::String(value.to_string()),
+            CLArgument::Integer(value) => Value::Integer(*value),
+            CLArgument::Float(value) => Value::Float(*value),
+            CLArgument::Boolean(value) => Value::Boolean(*value),
+            CLArgument::IPv4Address(address) => Value::Ipv4Address(address.to_string()),
+            CLArgument::IPv6Address(address) => Value::Ipv6Address(address.to_string()),
+            CLArgument::Hostname(name) => Value::Hostname(name.to_string()),
+            CLArgument::HostPort { host, port } => Value::HostPort(proto::HostPort {
+                host: Some(proto::Host::from(host)),
+                port: u32::from(*port),
+            }),
+            CLArgument::Cidr {
+                address,
+                prefix_length,
+            } => Value::Cidr(proto::Cidr {
+                address: address.to_string(),
+                prefix_length: u32::from(*prefix_length),
+            }),
+            CLArgument::Port(port) => Value::Port(u32::from(*port)),
+            CLArgument::Email { user, domain } => Value::Email(proto::Email {
+                user: user.to_string(),
+                domain: domain.to_string(),
+            }),
+            CLArgument::UserAtHost { user, host } => Value::UserAtHost(proto::UserAtHost {
+                user: user.to_string(),
+                host: Some(proto::Host::from(host)),
+            }),
+            CLArgument::RemotePath(value) => Value::RemotePath(value.to_string()),
+            CLArgument::LocalPath(path) => Value::LocalPath(proto::LocalPath {
+                path: path.path.to_string(),
+                resolved: path.resolved.clone(),
+                categories: path
+                    .categories
+                    .iter()
+                    .map(|category| category.name().to_string())
+                    .collect(),
+                glob: path.glob,
+            }),
+            CLArgument::URL(url) => Value::Url(proto::Url {
+                url: url.url.to_string(),
+                scheme: url.scheme.to_string(),
+                user: url.user.as_deref().map(str::to_string),
+                password: url.password.as_deref().map(str::to_string),
+                host: url.host.as_ref().map(proto::Host::from),
+                port: url.port.map(u32::from),
+                path: url.path.to_string(),
+                query: url.query.as_deref().map(str::to_string),
+                fragment: url.fragment.as_deref().map(str::to_string),
+                has_credentials: url.has_credentials,
+                dangerous_scheme: url.dangerous_scheme,
+            }),
+        };
+        proto::Argument { value: Some(value) }
+    }
+}
+
+impl From<&Host<'_>> for proto::Host {
+    fn from(host: &Host<'_>) -> Self {
+        use proto::host::Kind;
+        let kind = match host {
+            Host::IPv4(address) => Kind::Ipv4Address(address.to_string()),
+            Host::IPv6(address) => Kind::Ipv6Address(address.to_string()),
+            Host::Name(name) => Kind::Name(name.to_string()),
+        };
+        proto::Host { kind: Some(kind) }
+    }
+}
+
+#[cfg(all(test, feature = "grpc"))]
+mod tests {
+    use super::*;
+    use proto::element::Kind;
+
+    fn request(program: &str, args: &[&str]) -> AnalyzeRequest {
+        AnalyzeRequest {
+            program: program.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn analyze() {
+        let mut client = in_process_client(Analyzer::builtin()).await.unwrap();
+        let response = client
+            .analyze(AnalyzeRequest {
+                cwd: Some("/tmp".to_string()),
+                ..request("tar", &["-xvf", "archive.tar"])
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.program, "tar");
+        assert_eq!(response.error, None);
+        let options: Vec<&str> = response
+            .elements
+            .iter()
+            .filter_map(|element| match &element.kind {
+                Some(Kind::BehaviouredOption(option)) => Some(option.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(options, ["x", "v", "f"]);
+        let resolved = response
+            .elements
+            .iter()
+            .find_map(|element| match &element.kind {
+                Some(Kind::BehaviouredOption(option)) => match &option.argument {
+                    Some(proto::Argument {
+                        value: Some(proto::argument::Value::LocalPath(path)),
+                    }) => path.resolved.clone(),
+                    _ => None,
+                },
+                _ => None,
+            });
+        assert_eq!(resolved.as_deref(), Some("/tmp/archive.tar"));
+
+        let status = client
+            .analyze(request("unknown-program", &[]))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+        let status = client
+            .analyze(request("tar", &["--no-such-option"]))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn analyze_batch_reports_failures_in_their_response() {
+        let mut client = in_process_client(Analyzer::builtin()).await.unwrap();
+        let requests = tokio_stream::iter(vec![
+            request("curl", &["-o", "out.html"]),
+            request("unknown-program", &[]),
+            request("ls", &["-l"]),
+        ]);
+        let responses: Vec<AnalyzeResponse> = client
+            .analyze_batch(requests)
+            .await
+            .unwrap()
+            .into_inner()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        let programs: Vec<&str> = responses
+            .iter()
+            .map(|response| response.program.as_str())
+            .collect();
+        assert_eq!(programs, ["curl", "unknown-program", "ls"]);
+        assert!(responses[0].error.is_none() && !responses[0].elements.is_empty());
+        let error = responses[1].error.as_ref().unwrap();
+        assert_eq!(error.kind(), analyze_error::Kind::UnknownProgram);
+        assert!(responses[1].elements.is_empty());
+        assert!(responses[2].error.is_none() && !responses[2].elements.is_empty());
+    }
+
+    #[tokio::test]
+    async fn list_programs() {
+        let mut client = in_process_client(Analyzer::builtin()).await.unwrap();
+        let programs = client
+            .list_programs(ListProgramsRequest {})
+            .await
+            .unwrap()
+            .into_inner()
+            .programs;
+        assert_eq!(programs, Analyzer::builtin().programs());
+        assert!(programs.iter().any(|program| program == "tar"));
+        assert!(programs.iter().any(|program| program == "tar@bsd"));
+    }
+}
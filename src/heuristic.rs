@@ -0,0 +1,310 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Fallback parser for programs without a config. Options are tokenized GNU-style, an option
+//! takes the next token as its argument when that token looks like a value (a number, an address,
+//! a URL...), and behaviours are only inferred from the arguments. The result starts with a
+//! `CLHeuristic` element giving its (low) confidence.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::analyzer::BehaviourTree;
+use crate::parser_structs::{CLArgument, CLElement};
+use crate::taxonomy::{self, Behaviour, BehaviourSet};
+
+/// Confidence of a heuristic parse, for lack of a config
+pub const HEURISTIC_CONFIDENCE: f32 = 0.2;
+
+/// Parse `args` without knowing the options of the program
+pub fn parse_heuristic<'a, S: AsRef<str>>(args: &'a [S]) -> Vec<CLElement<'a>> {
+    static ATTACHED_NUMBER: Lazy<Regex> = Lazy::new(|| Regex::new(r"^-([A-Za-z]+)(\d+)$").unwrap());
+
+    metrics::counter!("ucp_heuristic_parses_total").increment(1);
+    let mut parsed_cmdline = vec![CLElement::CLHeuristic(HEURISTIC_CONFIDENCE)];
+    let mut idx = 0;
+    while idx < args.len() {
+        let arg: &'a str = args[idx].as_ref();
+        idx += 1;
+
+        if arg == "--" {
+            parsed_cmdline.push(CLElement::CLDoubleDash);
+            // Everything after `--` is an argument
+            parsed_cmdline.extend(args[idx..].iter().map(|arg| argument_element(arg.as_ref())));
+            break;
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, argument) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(CLArgument::identified(value))),
+                None => (long, take_value(args, &mut idx)),
+            };
+            parsed_cmdline.push(CLElement::CLOption((name.into(), argument)));
+            continue;
+        }
+        // `-` is the standard input, `-5` a number
+        let is_option =
+            arg.len() > 1 && arg.starts_with('-') && is_string_like(&CLArgument::identified(arg));
+        if !is_option {
+            parsed_cmdline.push(argument_element(arg));
+            continue;
+        }
+
+        // `-n5` is `-n 5`, and the last option of `-xvf` may take the next token
+        let (names, attached) = match ATTACHED_NUMBER.captures(arg) {
+            Some(captures) => (
+                captures.get(1).unwrap().as_str(),
+                Some(CLArgument::identified(captures.get(2).unwrap().as_str())),
+            ),
+            None => (&arg[1..], None),
+        };
+        for (char_pos, name) in names.char_indices() {
+            let end = char_pos + name.len_utf8();
+            let argument = if end < names.len() {
+                None
+            } else if attached.is_some() {
+                attached.clone()
+            } else {
+                take_value(args, &mut idx)
+            };
+            parsed_cmdline.push(CLElement::CLOption((names[char_pos..end].into(), argument)));
+        }
+    }
+    parsed_cmdline
+}
+
+/// Attach behaviours to a heuristic parse, those suggested by the arguments
+pub fn enrich_heuristic(parsed_cmdline: Vec<CLElement<'_>>) -> BehaviourTree<'_> {
+    parsed_cmdline.into_iter().map(enrich_element).collect()
+}
+
+/// Infer the behaviours of a heuristic behaviour tree again, after its arguments changed (see
+/// `analyzer::resolve_behaviour_tree_paths`)
+pub fn reenrich_heuristic(behaviour_tree: BehaviourTree<'_>) -> BehaviourTree<'_> {
+    behaviour_tree
+        .into_iter()
+        .map(|elem| match elem {
+            CLElement::CLBehaviouredOption((name, _, argument)) => {
+                enrich_element(CLElement::CLOption((name, argument)))
+            }
+            CLElement::CLBehaviouredArgument((argument, _)) => {
+                enrich_element(CLElement::CLArgument(argument))
+            }
+            elem => enrich_element(elem),
+        })
+        .collect()
+}
+
+/// Whether a parsed command-line or behaviour tree is the result of a heuristic parse
+pub fn is_heuristic(elements: &[CLElement]) -> bool {
+    matches!(elements.first(), Some(CLElement::CLHeuristic(_)))
+}
+
+/// Coarse behaviours suggested by an argument: network communication for URLs, hosts and
+/// addresses, file reads for sensitive paths and those of dangerous URL schemes (`file:`...)
+pub fn argument_behaviours(argument: &CLArgument) -> BehaviourSet {
+    let name = match argument {
+        CLArgument::URL(url) if url.dangerous_scheme => return url.scheme_behaviours(),
+        CLArgument::URL(_)
+        | CLArgument::RemotePath(_)
+        | CLArgument::IPv4Address(_)
+        | CLArgument::IPv6Address(_)
+        | CLArgument::Hostname(_)
+        | CLArgument::HostPort { .. }
+        | CLArgument::UserAtHost { .. } => "NET_COMS",
+        CLArgument::LocalPath(path) if path.is_sensitive() => "FILE_READ",
+        _ => return BehaviourSet::EMPTY,
+    };
+    // A custom taxonomy may not have the behaviour
+    match Behaviour::from_name(name) {
+        Some(behaviour) => taxonomy::intern_behaviours(&[behaviour]),
+        None => BehaviourSet::EMPTY,
+    }
+}
+
+fn enrich_element(elem: CLElement<'_>) -> CLElement<'_> {
+    match elem {
+        CLElement::CLOption((name, argument)) => CLElement::CLBehaviouredOption((
+            name,
+            argument
+                .as_ref()
+                .map(argument_behaviours)
+                .unwrap_or_default(),
+            argument,
+        )),
+        CLElement::CLArgument(argument) => {
+            let behaviours = argument_behaviours(&argument);
+            if behaviours.is_empty() {
+                CLElement::CLArgument(argument)
+            } else {
+                CLElement::CLBehaviouredArgument((argument, behaviours))
+            }
+        }
+        elem => elem,
+    }
+}
+
+/// The next token as the argument of an option, if it looks like a value rather than an
+/// operand of the program
+fn take_value<'a, S: AsRef<str>>(args: &'a [S], idx: &mut usize) -> Option<CLArgument<'a>> {
+    // Options (`-v`) and operands are strings
+    let argument = CLArgument::identified(args.get(*idx)?.as_ref());
+    if is_string_like(&argument) {
+        return None;
+    }
+    *idx += 1;
+    Some(argument)
+}
+
+fn argument_element(arg: &str) -> CLElement<'_> {
+    CLElement::CLArgument(CLArgument::identified(arg))
+}
+
+/// Strings, local paths, hosts and accounts, which may as well be operands as values of options
+fn is_string_like(argument: &CLArgument) -> bool {
+    matches!(
+        argument,
+        CLArgument::String(_)
+            | CLArgument::LocalPath(_)
+            | CLArgument::Hostname(_)
+            | CLArgument::UserAtHost { .. }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The elements of a heuristic parse, after its `CLHeuristic` one, as debug strings
+    fn parse(args: &[&str]) -> Vec<String> {
+        let parsed_cmdline = parse_heuristic(args);
+        assert!(matches!(
+            parsed_cmdline[0],
+            CLElement::CLHeuristic(HEURISTIC_CONFIDENCE)
+        ));
+        parsed_cmdline[1..]
+            .iter()
+            .map(|elem| format!("{elem:?}"))
+            .collect()
+    }
+
+    #[test]
+    fn attached_numbers() {
+        assert_eq!(parse(&["-n5"]), [r#"CLOption(("n", Some(Integer(5))))"#]);
+        assert_eq!(
+            parse(&["-vn5"]),
+            [
+                r#"CLOption(("v", None))"#,
+                r#"CLOption(("n", Some(Integer(5))))"#
+            ]
+        );
+        // A negative number is not an option
+        assert_eq!(parse(&["-5"]), ["CLArgument(Integer(-5))"]);
+    }
+
+    #[test]
+    fn bundled_options() {
+        // Operands are not taken for the argument of the last option...
+        assert_eq!(
+            parse(&["-xvf", "file"]),
+            [
+                r#"CLOption(("x", None))"#,
+                r#"CLOption(("v", None))"#,
+                r#"CLOption(("f", None))"#,
+                r#"CLArgument(String("file"))"#
+            ]
+        );
+        // ...values are
+        assert_eq!(
+            parse(&["-vp", "8080", "file"]),
+            [
+                r#"CLOption(("v", None))"#,
+                r#"CLOption(("p", Some(Integer(8080))))"#,
+                r#"CLArgument(String("file"))"#
+            ]
+        );
+    }
+
+    #[test]
+    fn long_options() {
+        assert_eq!(
+            parse(&["--opt=val"]),
+            [r#"CLOption(("opt", Some(String("val"))))"#]
+        );
+        assert_eq!(
+            parse(&["--port", "8080", "--verbose", "file"]),
+            [
+                r#"CLOption(("port", Some(Integer(8080))))"#,
+                r#"CLOption(("verbose", None))"#,
+                r#"CLArgument(String("file"))"#
+            ]
+        );
+    }
+
+    #[test]
+    fn end_of_options() {
+        assert_eq!(
+            parse(&["-v", "--", "-x", "--long=1"]),
+            [
+                r#"CLOption(("v", None))"#,
+                "CLDoubleDash",
+                r#"CLArgument(String("-x"))"#,
+                r#"CLArgument(String("--long=1"))"#
+            ]
+        );
+    }
+
+    #[test]
+    fn url_operands_communicate_over_the_network() {
+        let behaviour_tree = enrich_heuristic(parse_heuristic(&["https://example.com/x"]));
+        assert!(is_heuristic(&behaviour_tree));
+        assert!(matches!(
+            behaviour_tree[0],
+            CLElement::CLHeuristic(HEURISTIC_CONFIDENCE)
+        ));
+        let CLElement::CLBehaviouredArgument((CLArgument::URL(url), behaviours)) =
+            &behaviour_tree[1]
+        else {
+            panic!("{:?}", behaviour_tree[1]);
+        };
+        assert_eq!(url.url, "https://example.com/x");
+        assert_eq!(behaviours.as_slice()[0].name(), "NET_COMS");
+        assert_eq!(behaviours.len(), 1);
+
+        // The URL is the value of the option before it, which gets its behaviours
+        let behaviour_tree = enrich_heuristic(parse_heuristic(&["-q", "https://example.com/x"]));
+        assert!(matches!(
+            &behaviour_tree[1],
+            CLElement::CLBehaviouredOption((name, behaviours, Some(CLArgument::URL(_))))
+                if name == "q" && behaviours.as_slice()[0].name() == "NET_COMS"
+        ));
+    }
+
+    #[test]
+    fn file_operands_do_not_communicate_over_the_network() {
+        for operand in ["README.org", "foo.info"] {
+            let args = [operand];
+            let behaviour_tree = enrich_heuristic(parse_heuristic(&args));
+            assert!(
+                matches!(
+                    &behaviour_tree[1..],
+                    [CLElement::CLArgument(CLArgument::LocalPath(_))]
+                ),
+                "{behaviour_tree:?}"
+            );
+        }
+    }
+}
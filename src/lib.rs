@@ -0,0 +1,60 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Universal CLI Parser (UCP): parses command-lines similarly to what bash does and interprets
+//! them from a behavioural standpoint, yielding behaviour trees.
+//!
+//! ```
+//! use universal_cli_parser::Analyzer;
+//!
+//! let analyzer = Analyzer::builtin();
+//! let behaviour_tree = analyzer.analyze("tar", &["-xvf", "archive.tar"]).unwrap();
+//! ```
+//!
+//! The configs of the repository's `configs/` directory are built in; `config_store::ConfigStore`
+//! overlays directories of configs on top of them.
+//!
+//! The HTTP server is available behind the `server` feature, and the gRPC service behind the
+//! `grpc` feature.
+
+pub mod analyzer;
+pub mod completion_import;
+pub mod config_cache;
+pub mod config_format;
+pub mod config_gen;
+pub mod config_lint;
+pub mod config_store;
+pub mod dataset;
+pub mod env_vars;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod heuristic;
+pub mod parser;
+pub mod parser_config;
+pub mod parser_structs;
+pub mod path_classifier;
+pub mod registry;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod taxonomy;
+
+pub use analyzer::{Analyzer, AnalyzerError, BehaviourTree};
+pub use parser_structs::{CLArgument, CLElement};
+pub use taxonomy::{Behaviour, Taxonomy};
+
+pub const APP_NAME: &str = "universal-cli-parser";
+pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
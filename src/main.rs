@@ -15,434 +15,937 @@
    along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-mod config_cache;
-mod parser_config;
-mod parser_structs;
-use config_cache::ParserConfigCache;
-use std::ops::Deref;
-
-use parser_config::ParserConfig;
-use parser_structs::CLElement;
-
-use crate::parser_structs::CLArgument;
-
-use axum::{
-    Json, Router,
-    extract::State,
-    http::StatusCode,
-    routing::{get, post},
-};
-use serde::{Deserialize, Serialize};
-
-const APP_NAME: &str = "universal-cli-parser";
-const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
-const PORT_NUMBER: u16 = 6880;
-
-// ... cache moved to `src/config_cache.rs`
-
-#[tokio::main]
-async fn main() {
-    // initialize tracing
-    tracing_subscriber::fmt::init();
-
-    println!("{APP_NAME} version: {APP_VERSION}");
-
-    // Create the parser config cache
-    let config_cache = ParserConfigCache::new();
-
-    // build our application with a route
-    let app = Router::new()
-        // `GET /` goes to `root`
-        .route("/", get(root))
-        .route("/parse", post(parse_request))
-        .route("/behaviours", post(behaviours_request))
-        .with_state(config_cache);
-
-    // run our app with hyper, listening globally on port 6880
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{PORT_NUMBER}"))
-        .await
-        .unwrap();
-    println!("Starting server on port {PORT_NUMBER}");
-    axum::serve(listener, app).await.unwrap();
-    println!("Bye !");
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use universal_cli_parser::analyzer::roll_up_behaviour_tree;
+use universal_cli_parser::completion_import::{self, Shell};
+use universal_cli_parser::config_format::ConfigFormat;
+use universal_cli_parser::config_gen::{self, DocFormat};
+use universal_cli_parser::config_lint::{self, LintIssue, Severity};
+use universal_cli_parser::config_store::{self, ConfigStore};
+use universal_cli_parser::dataset::{self, Dataset, DatasetProgram};
+use universal_cli_parser::parser_config::{self, ParserConfig};
+use universal_cli_parser::path_classifier::PathContext;
+use universal_cli_parser::registry::ProgramRegistry;
+#[cfg(feature = "server")]
+use universal_cli_parser::server::ListenConfig;
+use universal_cli_parser::{Analyzer, AnalyzerError, Taxonomy};
+
+#[cfg(feature = "server")]
+const DEFAULT_BIND_ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+#[cfg(feature = "server")]
+const DEFAULT_PORT_NUMBER: u16 = 6880;
+
+/// Universal CLI Parser: interprets command-lines from a behavioural standpoint
+#[derive(Parser)]
+#[command(name = "ucp", version, about)]
+struct Cli {
+    /// Behaviour taxonomy to use instead of the built-in one
+    #[arg(long, global = true)]
+    taxonomy: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-// basic handler that responds with a static string
-async fn root() -> &'static str {
-    "Hello, World!"
+#[derive(Subcommand)]
+enum Command {
+    /// Start the HTTP server (default when no subcommand is given)
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to bind the HTTP server to
+        #[arg(long, default_value_t = DEFAULT_BIND_ADDRESS)]
+        bind: IpAddr,
+        /// Port to listen on
+        #[arg(short, long, default_value_t = DEFAULT_PORT_NUMBER)]
+        port: u16,
+        /// Also listen on this Unix domain socket
+        #[arg(long)]
+        unix_socket: Option<PathBuf>,
+        /// Octal permissions of the Unix domain socket (e.g. 660)
+        #[arg(long, value_parser = parse_octal_mode, requires = "unix_socket")]
+        socket_mode: Option<u32>,
+        /// Only listen on the Unix domain socket, not over TCP
+        #[arg(long, requires = "unix_socket")]
+        no_tcp: bool,
+        /// Also serve the gRPC service on this port of the bind address
+        #[cfg(feature = "grpc")]
+        #[arg(long)]
+        grpc_port: Option<u16>,
+        /// Load every config at startup instead of on first use
+        #[arg(long)]
+        preload: bool,
+        /// Keep at most this many configs in memory, evicting the least recently used ones
+        #[arg(long)]
+        cache_capacity: Option<usize>,
+        #[command(flatten)]
+        configs: ConfigArgs,
+    },
+    /// Parse a command-line locally and print its behaviour tree
+    Parse {
+        /// Program whose config should be used (e.g. `tar`)
+        program: String,
+        /// Arguments of the program, usually given after `--`
+        #[arg(allow_hyphen_values = true, trailing_var_arg = true)]
+        args: Vec<String>,
+        /// Print the behaviour tree as JSON, as `/behaviours` would
+        #[arg(long)]
+        json: bool,
+        /// Roll behaviours up to this depth of the taxonomy (0 being the most general level)
+        #[arg(long)]
+        depth: Option<usize>,
+        #[command(flatten)]
+        configs: ConfigArgs,
+        #[command(flatten)]
+        context: ContextArgs,
+    },
+    /// Generate a config skeleton from the `--help` output or the man page of a program
+    GenConfig {
+        /// File containing the `--help` output or the man page source (uncompressed) of the program
+        input: PathBuf,
+        /// Name of the program (by default, the name of the input file up to its first dot)
+        #[arg(long)]
+        name: Option<String>,
+        /// Format of the input, `help` or `man` (detected by default)
+        #[arg(long, value_parser = parse_doc_format)]
+        format: Option<DocFormat>,
+        /// Write the config to this (new) file instead of the standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Generate a config skeleton from a bash, zsh or fish completion script, or add the options
+    /// it knows of to an existing config
+    ImportCompletion {
+        /// Completion script of the program
+        input: PathBuf,
+        /// Shell of the script, `bash`, `zsh` or `fish` (detected by default)
+        #[arg(long, value_parser = parse_shell)]
+        shell: Option<Shell>,
+        /// Name of the program (by default, the one completed by the script)
+        #[arg(long)]
+        name: Option<String>,
+        /// Existing config to add the missing options to, in place, keeping its options as they are
+        #[arg(long, conflicts_with = "output")]
+        merge: Option<PathBuf>,
+        /// Write the config to this (new) file instead of the standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Compare the configs with the labels of the DID U Misbehave dataset, or sync them with it
+    Dataset {
+        #[command(subcommand)]
+        command: DatasetCommand,
+    },
+    /// Check that config files (or directories of config files) load correctly and lint them
+    Validate {
+        /// Config files or directories to check (the built-in configs by default)
+        paths: Vec<PathBuf>,
+        /// Fail on warnings too
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Print the JSON Schema of config files (whatever their format), for editors to validate
+    /// and complete them
+    ConfigSchema {
+        /// Write the schema to this file instead of the standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
 }
 
-async fn parse_request(Json(payload): Json<ParseRequest>) -> Result<String, StatusCode> {
-    let program = payload.program;
-    let args = payload.args;
+#[derive(Subcommand)]
+enum DatasetCommand {
+    /// Report the differences between the dataset and the configs (and fail if there are any)
+    Diff {
+        /// Dataset file, or directory of dataset files
+        dataset: PathBuf,
+        /// Print the differences as JSON
+        #[arg(long)]
+        json: bool,
+        #[command(flatten)]
+        configs: ConfigArgs,
+    },
+    /// Write the labels of the dataset into the configs of a directory, adding the missing options
+    /// and configs
+    Import {
+        /// Dataset file, or directory of dataset files
+        dataset: PathBuf,
+        /// Config directory to update (e.g. `configs`)
+        #[arg(long)]
+        into: PathBuf,
+        /// Only import the labels of these programs (repeatable)
+        #[arg(long = "program")]
+        programs: Vec<String>,
+    },
+    /// Write the labels of the configs as a dataset file
+    Export {
+        /// Write the dataset to this (new) file instead of the standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        configs: ConfigArgs,
+    },
+}
 
-    // Perform parsing logic here
-    let parser_config = ParserConfig::from_toml_file(format!("configs/{}.toml", program).as_str())
-        .expect(format!("Failed to load config for program {}", program).as_str());
+#[derive(clap::Args)]
+struct ConfigArgs {
+    /// Directory of configs adding to or overriding the built-in ones (repeatable, the last one
+    /// having the highest priority)
+    #[arg(short, long = "config-dir")]
+    config_dirs: Vec<PathBuf>,
+    /// Do not use the built-in configs, only those of the config directories
+    #[arg(long)]
+    no_builtin_configs: bool,
+    /// Parse the command-lines of programs without a config heuristically, instead of rejecting
+    /// them
+    #[arg(long)]
+    heuristic_fallback: bool,
+}
 
-    let parsed_cmdline = parse_the_split(args, &parser_config);
+impl ConfigArgs {
+    fn analyzer(&self) -> Result<Analyzer, AnalyzerError> {
+        let store = if self.no_builtin_configs {
+            ConfigStore::empty()
+        } else {
+            ConfigStore::builtin()
+        };
+        let store = self
+            .config_dirs
+            .iter()
+            .fold(store, |store, dir| store.with_overlay(dir));
+        Analyzer::from_store(store).map(|analyzer| {
+            analyzer
+                .with_taxonomy(Taxonomy::current())
+                .with_heuristic_fallback(self.heuristic_fallback)
+        })
+    }
+}
 
-    Ok(format!("{:?}", parsed_cmdline))
+#[derive(clap::Args)]
+struct ContextArgs {
+    /// Working directory to resolve relative paths in
+    #[arg(long)]
+    cwd: Option<String>,
+    /// User running the command-line, to resolve `~` paths in
+    #[arg(long)]
+    user: Option<String>,
+    /// Home directory of the user (by default, `/root` or `/home/<user>`)
+    #[arg(long)]
+    home: Option<String>,
+    /// Environment variable of the command-line, `NAME=VALUE` (repeatable)
+    #[arg(long = "env", value_parser = parse_env_var)]
+    env: Vec<(String, String)>,
 }
 
-async fn behaviours_request(
-    State(cache): State<ParserConfigCache>,
-    Json(payload): Json<ParseRequest>,
-) -> Result<Json<Vec<CLElement>>, StatusCode> {
-    let start_time = std::time::Instant::now();
-    let program = payload.program;
-    let args = payload.args;
-
-    // Get parser config from cache or load from filesystem (Arc<ParserConfig>)
-    let parser_config_arc = match cache.get_config(&program) {
-        Ok(arc) => arc,
-        Err(_err) => {
-            let elapsed = start_time.elapsed();
-            tracing::warn!(
-                duration_us = elapsed.as_micros(),
-                program = %program,
-                "/behaviours : Failed to load config for program"
-            );
-            return Err(StatusCode::NOT_FOUND);
+impl ContextArgs {
+    fn path_context(&self) -> PathContext {
+        PathContext {
+            cwd: self.cwd.clone(),
+            user: self.user.clone(),
+            home: self.home.clone(),
+            env: self.env.iter().cloned().collect(),
         }
+    }
+}
+
+fn main() -> ExitCode {
+    // initialize tracing, on stderr so that it does not mix with the output of `parse`
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    let cli = Cli::parse();
+    let taxonomy = match &cli.taxonomy {
+        Some(taxonomy_path) => match Taxonomy::from_toml_file(&taxonomy_path.to_string_lossy()) {
+            Ok(taxonomy) => taxonomy.leak(),
+            Err(err) => {
+                eprintln!("Failed to load taxonomy {}: {err}", taxonomy_path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Taxonomy::builtin(),
     };
+    let Some(command) = cli.command.or_else(default_command) else {
+        eprintln!("No subcommand given and ucp was built without the `server` feature");
+        return ExitCode::FAILURE;
+    };
+    // Configs and datasets are read, and analyzers built, with the taxonomy of the command-line
+    taxonomy.enter(|| run_command(command))
+}
 
-    //Debug display of the request contents
-    tracing::debug!(program = %program, args = ?args, "/behaviours : Received request");
-    // Use a reference to the ParserConfig inside the Arc
-    let parser_config_ref: &ParserConfig = parser_config_arc.as_ref();
-    let parsed_cmdline = parse_the_split(args, parser_config_ref);
-    let mut enriched_parsed_cmdline: Vec<CLElement> = Vec::new();
+fn run_command(command: Command) -> ExitCode {
+    match command {
+        #[cfg(feature = "server")]
+        Command::Serve {
+            bind,
+            port,
+            unix_socket,
+            socket_mode,
+            no_tcp,
+            #[cfg(feature = "grpc")]
+            grpc_port,
+            preload,
+            cache_capacity,
+            configs,
+        } => {
+            let listen = ListenConfig {
+                tcp: (!no_tcp).then(|| SocketAddr::from((bind, port))),
+                unix_socket,
+                unix_socket_mode: socket_mode,
+                #[cfg(feature = "grpc")]
+                grpc: grpc_port.map(|grpc_port| SocketAddr::from((bind, grpc_port))),
+            };
+            serve_command(&listen, preload, cache_capacity, &configs)
+        }
+        Command::Parse {
+            program,
+            args,
+            json,
+            depth,
+            configs,
+            context,
+        } => parse_command(&program, args, json, depth, &configs, &context),
+        Command::GenConfig {
+            input,
+            name,
+            format,
+            output,
+        } => gen_config_command(&input, name, format, output.as_deref()),
+        Command::ImportCompletion {
+            input,
+            shell,
+            name,
+            merge,
+            output,
+        } => import_completion_command(&input, shell, name, merge.as_deref(), output.as_deref()),
+        Command::Dataset { command } => match command {
+            DatasetCommand::Diff {
+                dataset,
+                json,
+                configs,
+            } => dataset_diff_command(&dataset, json, &configs),
+            DatasetCommand::Import {
+                dataset,
+                into,
+                programs,
+            } => dataset_import_command(&dataset, &into, &programs),
+            DatasetCommand::Export { output, configs } => {
+                dataset_export_command(output.as_deref(), &configs)
+            }
+        },
+        Command::Validate { paths, strict } => validate_command(&paths, strict),
+        Command::ConfigSchema { output } => config_schema_command(output.as_deref()),
+    }
+}
 
-    //Add the inherent behaviours of the program
-    let mut inherent_behaviours: Vec<String> = vec![];
+#[cfg(feature = "server")]
+fn default_command() -> Option<Command> {
+    Some(Command::Serve {
+        bind: DEFAULT_BIND_ADDRESS,
+        port: DEFAULT_PORT_NUMBER,
+        unix_socket: None,
+        socket_mode: None,
+        no_tcp: false,
+        #[cfg(feature = "grpc")]
+        grpc_port: None,
+        preload: false,
+        cache_capacity: None,
+        configs: ConfigArgs {
+            config_dirs: vec![],
+            no_builtin_configs: false,
+            heuristic_fallback: false,
+        },
+    })
+}
 
-    if parser_config_ref.config_file.behaviours.len() > 0 {
-        for behaviour in &parser_config_ref.config_file.behaviours {
-            inherent_behaviours.push(behaviour.clone());
+#[cfg(not(feature = "server"))]
+fn default_command() -> Option<Command> {
+    None
+}
+
+#[cfg(feature = "server")]
+fn serve_command(
+    listen: &ListenConfig,
+    preload: bool,
+    cache_capacity: Option<usize>,
+    configs: &ConfigArgs,
+) -> ExitCode {
+    let mut analyzer = match configs.analyzer() {
+        Ok(analyzer) => analyzer,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Some(capacity) = cache_capacity {
+        analyzer = analyzer.with_cache_capacity(capacity);
+    }
+    if preload {
+        // Configs that fail to load are reported, and retried when they are requested
+        for (config, err) in analyzer.cache().preload() {
+            tracing::warn!(config = %config, error = %err, "Failed to preload config");
+        }
+    }
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start the tokio runtime");
+    match runtime.block_on(universal_cli_parser::server::serve(listen, analyzer)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Server error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+fn parse_octal_mode(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .map_err(|_| format!("\"{mode}\" is not an octal permission mode"))
+}
+
+fn parse_command(
+    program: &str,
+    args: Vec<String>,
+    json: bool,
+    depth: Option<usize>,
+    configs: &ConfigArgs,
+    context: &ContextArgs,
+) -> ExitCode {
+    let analyzer = match configs.analyzer() {
+        Ok(analyzer) => analyzer,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
         }
+    };
+
+    let displayed_cmdline = args.join(" ");
+    let behaviour_tree = match analyzer.analyze_in(program, &args, &context.path_context()) {
+        Ok(behaviour_tree) => behaviour_tree,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let behaviour_tree = match depth {
+        Some(depth) => roll_up_behaviour_tree(behaviour_tree, depth),
+        None => behaviour_tree,
+    };
 
-        let new_element = CLElement::CLInherentBehaviour(inherent_behaviours);
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&behaviour_tree).expect("CLElement is serializable")
+        );
+    } else {
+        println!("{program} {displayed_cmdline} =>");
+        for elem in &behaviour_tree {
+            println!("\t{:?}", elem);
+        }
+    }
+    ExitCode::SUCCESS
+}
 
-        enriched_parsed_cmdline.push(new_element);
+fn parse_doc_format(format: &str) -> Result<DocFormat, String> {
+    match format {
+        "help" => Ok(DocFormat::Help),
+        "man" => Ok(DocFormat::Man),
+        _ => Err(format!(
+            "\"{format}\" is not a documentation format (help or man)"
+        )),
     }
+}
 
-    for elem in &parsed_cmdline {
-        let new_element: CLElement;
-        //If elem is not a CLOption, just copy it into the new vector
-        if let CLElement::CLOption(opt) = elem {
-            //Let's get the behaviour of the option
-            let behaviours = match parser_config_ref.get_behaviours(&opt.0) {
-                Ok(behaviours) => behaviours,
-                Err(err) => {
-                    let elapsed = start_time.elapsed();
-                    tracing::error!(
-                        duration_us = elapsed.as_micros(),
-                        program = %program,
-                        option = %opt.0,
-                        error = %err,
-                        "/behaviours : Error getting behaviour for option"
-                    );
-                    return Err(StatusCode::IM_A_TEAPOT);
-                }
-            };
-            new_element =
-                CLElement::CLBehaviouredOption((opt.0.clone(), behaviours, opt.1.clone()));
-        } else {
-            new_element = elem.clone();
+fn gen_config_command(
+    input: &Path,
+    name: Option<String>,
+    format: Option<DocFormat>,
+    output: Option<&Path>,
+) -> ExitCode {
+    let text = match std::fs::read_to_string(input) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("Failed to read {}: {err}", input.display());
+            return ExitCode::FAILURE;
         }
-        enriched_parsed_cmdline.push(new_element);
+    };
+    let format = format.unwrap_or_else(|| DocFormat::detect(&text));
+    let options = config_gen::extract_options(&text, format);
+    if options.is_empty() {
+        eprintln!("No option found in {}", input.display());
+        return ExitCode::FAILURE;
     }
+    let name = name.unwrap_or_else(|| {
+        let file_name = input.file_name().unwrap_or_default().to_string_lossy();
+        file_name.split('.').next().unwrap_or_default().to_string()
+    });
+    let config = config_gen::render_config(&name, &options);
 
-    let elapsed = start_time.elapsed();
-    tracing::info!(
-        duration_us = elapsed.as_micros(),
-        program = %program,
-        "/behaviours : SUCCESS"
-    );
-    // println!("Dealt with a behaviour parsing request");
+    write_config(&config, options.len(), output)
+}
 
-    Ok(Json(enriched_parsed_cmdline))
+fn parse_env_var(assignment: &str) -> Result<(String, String), String> {
+    match assignment.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("\"{assignment}\" is not a NAME=VALUE assignment")),
+    }
 }
 
-// the input to our `create_user` handler
-#[derive(Deserialize)]
-struct ParseRequest {
-    program: String,
-    args: Vec<String>,
+fn parse_shell(shell: &str) -> Result<Shell, String> {
+    match shell {
+        "bash" => Ok(Shell::Bash),
+        "zsh" => Ok(Shell::Zsh),
+        "fish" => Ok(Shell::Fish),
+        _ => Err(format!(
+            "\"{shell}\" is not a supported shell (bash, zsh or fish)"
+        )),
+    }
 }
 
-fn old_main() {
-    let cmd_line = "-xvf remotehost:test.tar.gz --rsh-command=/bin/ssh";
+fn import_completion_command(
+    input: &Path,
+    shell: Option<Shell>,
+    name: Option<String>,
+    merge: Option<&Path>,
+    output: Option<&Path>,
+) -> ExitCode {
+    let script = match std::fs::read_to_string(input) {
+        Ok(script) => script,
+        Err(err) => {
+            eprintln!("Failed to read {}: {err}", input.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    let shell = shell.unwrap_or_else(|| Shell::detect(&script));
+    let (program, options) = completion_import::import_completion(&script, shell);
+    if options.is_empty() {
+        eprintln!("No option found in {}", input.display());
+        return ExitCode::FAILURE;
+    }
 
-    let parser_config =
-        ParserConfig::from_toml_file("configs/tar.toml").expect("Failed to load config");
+    let Some(merge) = merge else {
+        let name = name.or(program).unwrap_or_else(|| {
+            let file_name = input.file_name().unwrap_or_default().to_string_lossy();
+            file_name
+                .trim_start_matches('_')
+                .split('.')
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        });
+        let config = config_gen::render_config(&name, &options);
+        return write_config(&config, options.len(), output);
+    };
 
-    let split_vec = get_split_vec(cmd_line, &parser_config);
+    let merged = std::fs::read_to_string(merge)
+        .map_err(|err| err.to_string())
+        .and_then(|existing| {
+            edit_as_toml(merge, &existing, |existing| {
+                config_gen::merge_config(existing, &options)
+            })
+        });
+    let (config, added) = match merged {
+        Ok(merged) => merged,
+        Err(err) => {
+            eprintln!("Failed to merge into {}: {err}", merge.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    if added.is_empty() {
+        eprintln!("{} already has every option", merge.display());
+        return ExitCode::SUCCESS;
+    }
+    if let Err(err) = std::fs::write(merge, config) {
+        eprintln!("Failed to write {}: {err}", merge.display());
+        return ExitCode::FAILURE;
+    }
+    eprintln!("{} option(s) added to {}", added.len(), merge.display());
+    ExitCode::SUCCESS
+}
 
-    let parsed_cmdline = parse_the_split(split_vec, &parser_config);
-    println!("{} =>", cmd_line);
-    for elem in &parsed_cmdline {
-        println!("\t\"{:?}\"", elem);
+fn read_dataset(path: &Path) -> Option<Dataset> {
+    match Dataset::read(path) {
+        Ok(dataset) => Some(dataset),
+        Err(err) => {
+            eprintln!("Failed to read the dataset: {err}");
+            None
+        }
     }
 }
 
-pub fn parse_the_split(split_vec: Vec<String>, parser_config: &ParserConfig) -> Vec<CLElement> {
-    let mut idx = 0; //Index into the split
-    let mut parsed_cmdline: Vec<CLElement> = vec![];
-    loop {
-        //println!("Current index is {}/{}", idx, split_vec.len());
-        if idx >= split_vec.len() {
-            break;
-        }
-        let pointed_str: &String = split_vec
-            .get(idx)
-            .expect("split_vec.len() must have returned a wrong value");
-        //If there's a single dash
-        //TODO Watch out for anomic CLI programs (e.g., "-cache" instead of "--cache")
-        let first_char_is_dash: bool = pointed_str.starts_with("-");
-        let two_first_char_are_dashes: bool = pointed_str.starts_with("--");
-
-        //Is it an option?
-        if first_char_is_dash {
-            if !two_first_char_are_dashes {
-                //It's a char option block
-                //Let's get the individual chars
-                let mut option_names_vec = Vec::new();
-                option_names_vec.extend(pointed_str.clone().split_off(1).chars());
-                let mut opt_idx: usize = 0;
-                let mut idx_replacement = None; //Can be assigned something when a char option has an argument => We should consume it
-                loop {
-                    if opt_idx >= option_names_vec.len() {
-                        break;
-                    }
-                    let option_name: &char = option_names_vec.get(opt_idx).unwrap();
-                    let does_opt_have_arg = parser_config.does_char_option_have_arg(option_name);
-                    match does_opt_have_arg {
-                        Ok(has_arg) => {
-                            if has_arg {
-                                if opt_idx + 1 < option_names_vec.len() {
-                                    if parser_config.has_separatorless_args_for_char_options {
-                                        todo!("Handle stuff like \"-n5\"")
-                                    } else {
-                                        parsed_cmdline.push(CLElement::ParsingError(Some(format!("The following char option ('{}') was supposed to take an argument but was not the last char option of its option list (\"{}\"", option_name, pointed_str))));
-                                        parsed_cmdline.push(CLElement::CLOption((
-                                            option_name.to_string(),
-                                            None,
-                                        )));
-                                    }
-                                } else {
-                                    //Get the argument that comes after it like we would for a string option
-                                    let arg_str_res =
-                                        get_argument_string(&parser_config, &split_vec, idx + 1);
-                                    match arg_str_res {
-                                        Ok((argument_string, new_idx)) => {
-                                            idx_replacement = Some(new_idx);
-                                            let mut argument = CLArgument::String(argument_string);
-                                            argument.identify_type();
-                                            parsed_cmdline.push(CLElement::CLOption((
-                                                option_name.to_string(),
-                                                Some(argument),
-                                            )));
-                                        }
-                                        Err(err_msg) => {
-                                            parsed_cmdline
-                                                .push(CLElement::ParsingError(Some(err_msg)));
-                                            parsed_cmdline.push(CLElement::CLOption((
-                                                option_name.to_string(),
-                                                None,
-                                            )));
-                                            idx_replacement = Some(idx + 3);
-                                        }
-                                    }
-                                }
-                            } else {
-                                parsed_cmdline
-                                    .push(CLElement::CLOption((option_name.to_string(), None)))
-                            }
-                        }
-                        Err(err_msg) => {
-                            parsed_cmdline.push(CLElement::ParsingError(Some(err_msg)));
-                            parsed_cmdline
-                                .push(CLElement::CLOption((option_name.to_string(), None)));
-                        }
-                    }
-
-                    opt_idx += 1;
-                }
-                idx = idx_replacement.unwrap_or(idx + 1);
-            } else {
-                if pointed_str.len() == 2 {
-                    //This is a double-dash
-                    parsed_cmdline.push(CLElement::CLDoubleDash);
-                    idx += 1;
+fn dataset_diff_command(path: &Path, json: bool, configs: &ConfigArgs) -> ExitCode {
+    let Some(dataset) = read_dataset(path) else {
+        return ExitCode::FAILURE;
+    };
+    let analyzer = match configs.analyzer() {
+        Ok(analyzer) => analyzer,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let diff = dataset::diff(&dataset, analyzer.cache());
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&diff).expect("Dataset diffs are serializable")
+        );
+    } else {
+        for program in &diff.uncovered_programs {
+            println!("uncovered  {program}: in the dataset, but has no config");
+        }
+        for config in &diff.unlabelled_configs {
+            println!("unlabelled {config}: has a config, but is not in the dataset");
+        }
+        for missing in &diff.missing_options {
+            println!(
+                "missing    {} {}: in the dataset, not in the config",
+                missing.program, missing.option
+            );
+        }
+        for unlabelled in &diff.unlabelled_options {
+            println!(
+                "unlabelled {} {}: in the config, not in the dataset",
+                unlabelled.program, unlabelled.option
+            );
+        }
+        for disagreement in &diff.label_disagreements {
+            let option = disagreement
+                .option
+                .as_ref()
+                .map(|option| format!(" {option}"))
+                .unwrap_or_default();
+            println!(
+                "labels     {}{}: dataset {:?}, config {:?}",
+                disagreement.program, option, disagreement.dataset, disagreement.config
+            );
+        }
+        for (config, err) in &diff.errors {
+            println!("error      {config}: {err}");
+        }
+        eprintln!(
+            "{} program(s) in the dataset: {} uncovered, {} missing option(s), {} label disagreement(s)",
+            dataset.programs.len(),
+            diff.uncovered_programs.len(),
+            diff.missing_options.len(),
+            diff.label_disagreements.len()
+        );
+    }
+    if diff.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn dataset_import_command(path: &Path, into: &Path, programs: &[String]) -> ExitCode {
+    let Some(dataset) = read_dataset(path) else {
+        return ExitCode::FAILURE;
+    };
+    if !into.is_dir() {
+        eprintln!("{} is not a directory", into.display());
+        return ExitCode::FAILURE;
+    }
+    let store = ConfigStore::dir(into);
+    let registry = ProgramRegistry::scan(&store);
+
+    let mut failed = false;
+    for program in &dataset.programs {
+        if !programs.is_empty() && !programs.contains(&program.program) {
+            continue;
+        }
+        // The config the program resolves to, else a new one named after it
+        let entry = registry
+            .resolve(&program.program)
+            .and_then(|config| registry.get(config));
+        let (file, existing) = match entry {
+            Some(entry) => match std::fs::read_to_string(&entry.path) {
+                Ok(existing) => (entry.path.clone(), Some(existing)),
+                Err(err) => {
+                    eprintln!("Failed to read {}: {err}", entry.path.display());
+                    failed = true;
                     continue;
                 }
-                //It's a string option, get everything after the two dashes
-                let option_name: String = pointed_str.clone().split_off(2);
-                let does_opt_have_arg = parser_config.does_string_option_have_arg(&option_name);
-                match does_opt_have_arg {
-                    Ok(has_argument) => {
-                        if has_argument {
-                            idx += 1; //Skip the separator
-                            let arg_str_res = get_argument_string(&parser_config, &split_vec, idx);
-                            match arg_str_res {
-                                Ok((argument_string, new_idx)) => {
-                                    idx = new_idx;
-                                    let mut argument = CLArgument::String(argument_string);
-                                    argument.identify_type();
-                                    parsed_cmdline
-                                        .push(CLElement::CLOption((option_name, Some(argument))));
-                                }
-                                Err(err_msg) => {
-                                    parsed_cmdline.push(CLElement::ParsingError(Some(err_msg)));
-                                    parsed_cmdline.push(CLElement::CLOption((option_name, None)));
-                                    idx += 1;
-                                }
-                            }
-                        } else {
-                            parsed_cmdline.push(CLElement::CLOption((option_name, None)));
-                            idx += 1;
-                        }
-                    }
-                    Err(err_msg) => {
-                        tracing::debug!(option_name = %option_name, "String option not recognized, trying embedded separator split");
-
-                        // Try to split by embedded separators before giving up
-                        if let Some((split_option, split_arg)) =
-                            parser_config.try_split_embedded_option(&option_name)
-                        {
-                            tracing::debug!(
-                                original_option = %option_name,
-                                split_option = %split_option,
-                                split_arg = %split_arg,
-                                "Successfully split embedded option"
-                            );
-
-                            // Found a valid split! Check if this split option expects an argument
-                            match parser_config.does_string_option_have_arg(&split_option) {
-                                Ok(true) => {
-                                    // The split option expects an argument, use the split result
-                                    let mut argument = CLArgument::String(split_arg);
-                                    argument.identify_type();
-                                    parsed_cmdline
-                                        .push(CLElement::CLOption((split_option, Some(argument))));
-                                    idx += 1;
-                                }
-                                Ok(false) => {
-                                    // The split option doesn't expect an argument, but we found one embedded
-                                    // This is likely a parsing error, but let's be permissive and use the split anyway
-                                    let mut argument = CLArgument::String(split_arg);
-                                    argument.identify_type();
-                                    parsed_cmdline
-                                        .push(CLElement::CLOption((split_option, Some(argument))));
-                                    idx += 1;
-                                }
-                                Err(_) => {
-                                    // This shouldn't happen since try_split_embedded_option already checked it
-                                    parsed_cmdline.push(CLElement::ParsingError(Some(err_msg)));
-                                    parsed_cmdline.push(CLElement::CLOption((option_name, None)));
-                                    idx += 1;
-                                }
-                            }
-                        } else {
-                            // No valid split found, proceed with original error
-                            tracing::debug!(option_name = %option_name, "No valid embedded split found, treating as unknown option");
-                            parsed_cmdline.push(CLElement::ParsingError(Some(err_msg)));
-                            parsed_cmdline.push(CLElement::CLOption((option_name, None)));
-                            idx += 1;
-                        }
-                    }
-                }
+            },
+            None if is_config_name(&program.program) => {
+                (into.join(format!("{}.toml", program.program)), None)
             }
-        } else if pointed_str.len() == 1
-            && parser_config.is_separator(pointed_str.chars().nth(0).unwrap())
-        {
-            //This is a separator
-            parsed_cmdline.push(CLElement::CLSep(pointed_str.chars().nth(0).unwrap()));
-            idx += 1;
+            None => {
+                eprintln!("{}: not a valid config name", program.program);
+                failed = true;
+                continue;
+            }
+        };
+
+        // Options inherited from bases and fragments are not added again
+        let merged = entry.and_then(|entry| {
+            ParserConfig::from_store(&store, &entry.file)
+                .inspect_err(|err| {
+                    eprintln!(
+                        "Failed to load {}, importing without its bases: {err}",
+                        entry.config
+                    )
+                })
+                .ok()
+                .map(|(merged, _)| merged)
+        });
+        let imported = match &existing {
+            Some(existing) => edit_as_toml(&file, existing, |existing| {
+                dataset::import_program(Some(existing), merged.as_ref(), program)
+            }),
+            None => dataset::import_program(None, None, program),
+        };
+        let (config, summary) = match imported {
+            Ok(imported) => imported,
+            Err(err) => {
+                eprintln!("Failed to update {}: {err}", file.display());
+                failed = true;
+                continue;
+            }
+        };
+        if existing.is_some() && summary.added == 0 && summary.updated == 0 {
+            continue;
+        }
+        if let Err(err) = std::fs::write(&file, config) {
+            eprintln!("Failed to write {}: {err}", file.display());
+            failed = true;
+            continue;
+        }
+        if existing.is_some() {
+            eprintln!(
+                "{} updated: {} option(s) added, {} relabelled",
+                file.display(),
+                summary.added,
+                summary.updated
+            );
         } else {
-            //It's a free-standing argument, let's retrieve it
-            match get_argument_string(&parser_config, &split_vec, idx) {
-                Ok((arg_str, new_idx)) => {
-                    let mut argument = CLArgument::String(arg_str);
-                    argument.identify_type();
-                    parsed_cmdline.push(CLElement::CLArgument(argument));
-                    idx = new_idx;
-                }
-                Err(err_str) => parsed_cmdline.push(CLElement::ParsingError(Some(err_str))),
+            eprintln!("{} created: {} option(s)", file.display(), summary.added);
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Whether `name` can name a (not hidden) config file of the config directory itself
+fn is_config_name(name: &str) -> bool {
+    let path = Path::new(name);
+    !name.starts_with('.')
+        && config_store::is_plain_relative(path)
+        && path.components().count() == 1
+}
+
+fn dataset_export_command(output: Option<&Path>, configs: &ConfigArgs) -> ExitCode {
+    let analyzer = match configs.analyzer() {
+        Ok(analyzer) => analyzer,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    // Configs that fail to load are left out of the dataset
+    let mut dataset = Dataset::default();
+    let mut failed = false;
+    for config in analyzer.cache().registry().entries() {
+        match analyzer.cache().get_config(&config.config) {
+            Ok(loaded) => dataset
+                .programs
+                .push(DatasetProgram::from_config(&config.config, &loaded)),
+            Err(err) => {
+                eprintln!("Failed to load {}: {err}", config.config);
+                failed = true;
             }
         }
     }
-    parsed_cmdline
+    let csv = dataset.to_csv();
+    match output {
+        Some(output) => {
+            let written = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(output)
+                .and_then(|mut file| std::io::Write::write_all(&mut file, csv.as_bytes()));
+            if let Err(err) = written {
+                eprintln!("Failed to write {}: {err}", output.display());
+                return ExitCode::FAILURE;
+            }
+            eprintln!(
+                "{} program(s) written to {}",
+                dataset.programs.len(),
+                output.display()
+            );
+        }
+        None => print!("{csv}"),
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Edit `existing`, the contents of the config file `file`, with `edit`, which works on TOML
+/// configs: JSON and YAML configs are converted back and forth, losing their comments
+fn edit_as_toml<T>(
+    file: &Path,
+    existing: &str,
+    edit: impl FnOnce(&str) -> Result<(String, T), String>,
+) -> Result<(String, T), String> {
+    let format = ConfigFormat::from_path(file).unwrap_or(ConfigFormat::Toml);
+    let (edited, result) = edit(&ConfigFormat::Toml.convert(existing, format)?)?;
+    Ok((format.convert(&edited, ConfigFormat::Toml)?, result))
+}
+
+/// Write a generated config to the new file `output`, in the format of its extension, or to the
+/// standard output
+fn write_config(config: &str, options: usize, output: Option<&Path>) -> ExitCode {
+    let format = output.and_then(ConfigFormat::from_path);
+    let config = match format.map(|format| format.convert(config, ConfigFormat::Toml)) {
+        Some(Ok(converted)) => converted,
+        Some(Err(err)) => {
+            eprintln!("Failed to convert the config: {err}");
+            return ExitCode::FAILURE;
+        }
+        None => config.to_string(),
+    };
+    match output {
+        Some(output) => {
+            let written = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(output)
+                .and_then(|mut file| std::io::Write::write_all(&mut file, config.as_bytes()));
+            if let Err(err) = written {
+                eprintln!("Failed to write {}: {err}", output.display());
+                return ExitCode::FAILURE;
+            }
+            eprintln!("{options} option(s) written to {}", output.display());
+        }
+        None => print!("{config}"),
+    }
+    ExitCode::SUCCESS
 }
 
-pub fn get_split_vec(cmd_line: &str, parser_config: &ParserConfig) -> Vec<String> {
-    let mut split_vec: Vec<String> = vec![];
-
-    let mut current_str_buffer: String = String::new();
-    for current_char in cmd_line.chars() {
-        //If the current char is a separator
-        //Or if it is a space character
-        if parser_config.is_separator(current_char) || current_char == ' ' {
-            //Push the buffer into split_vec
-            split_vec.push(current_str_buffer);
-            //Create a string with only our separator and push it into split_vec
-            current_str_buffer = String::new();
-            current_str_buffer.push(current_char);
-            split_vec.push(current_str_buffer);
-            //Reset the buffer
-            current_str_buffer = String::new();
+fn validate_command(paths: &[PathBuf], strict: bool) -> ExitCode {
+    if paths.is_empty() {
+        return validate_builtin_configs(strict);
+    }
+    let mut config_files: Vec<PathBuf> = vec![];
+    for path in paths {
+        if path.is_dir() {
+            match std::fs::read_dir(path) {
+                Ok(entries) => {
+                    let mut dir_files: Vec<PathBuf> = entries
+                        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                        .filter(|file| ConfigFormat::from_path(file).is_some())
+                        .collect();
+                    dir_files.sort();
+                    config_files.extend(dir_files);
+                }
+                Err(err) => {
+                    eprintln!("ERROR {}: {err}", path.display());
+                    return ExitCode::FAILURE;
+                }
+            }
         } else {
-            current_str_buffer.push(current_char);
+            config_files.push(path.clone());
         }
     }
-    if current_str_buffer.len() > 0 {
-        split_vec.push(current_str_buffer);
+
+    let mut summary = ValidationSummary::default();
+    for file in &config_files {
+        let issues = config_lint::lint_file(file);
+        let loaded = ParserConfig::from_file(file);
+        summary.report(file, &issues, loaded);
     }
-    split_vec
+    summary.finish(strict)
 }
 
-pub fn get_argument_string(
-    parser_config: &ParserConfig,
-    split_vec: &Vec<String>,
-    idx: usize,
-) -> Result<(String, usize), String> {
-    let mut arg_string_buffer: String = String::new();
-    let mut idx = idx;
-    if idx >= split_vec.len() {
-        let err = format!(
-            "get_argument_string attempted to index element {} of a split_vec of size {}",
-            idx,
-            split_vec.len()
-        );
-        eprintln!("{}", err);
-        return Err(err);
+fn validate_builtin_configs(strict: bool) -> ExitCode {
+    let store = ConfigStore::builtin();
+    let mut summary = ValidationSummary::default();
+    for (_, file, _) in store.configs() {
+        let contents = store.contents(&file).unwrap_or_default();
+        let mut issues = config_lint::lint_source(&file, &contents);
+        let loaded = match store.relative_name(&file) {
+            Some(name) => ParserConfig::from_store(&store, &name).map(|(config, _)| config),
+            None => Err(format!("{}: not a built-in config", file.display())),
+        };
+        if let Err(err) = &loaded
+            && !issues.iter().any(|issue| issue.severity == Severity::Error)
+        {
+            issues.push(LintIssue {
+                file: file.clone(),
+                line: None,
+                severity: Severity::Error,
+                message: err.clone(),
+            });
+        }
+        summary.report(&file, &issues, loaded);
     }
+    summary.finish(strict)
+}
 
-    //TODO Handle quotes
-    let obtained_string = split_vec.get(idx).unwrap();
-    if !obtained_string.starts_with("\"") || !parser_config.handle_quotes {
-        arg_string_buffer.push_str(obtained_string.as_str());
-        idx += 1;
-    } else {
-        arg_string_buffer.push_str(obtained_string.as_str().split_at(1).1); //Push the first part without its quote
-        idx += 1;
-        loop {
-            let next_string = split_vec.get(idx).unwrap();
-            let next_string = next_string.as_str();
-            if next_string.ends_with("\"") {
-                //This is the last one, copy it without its last char
-                let next_string = next_string.split_at(next_string.len() - 1).0;
-                arg_string_buffer.push_str(next_string);
-                idx += 1;
-                break;
-            } else {
-                arg_string_buffer.push_str(next_string);
-                idx += 1;
+fn config_schema_command(output: Option<&Path>) -> ExitCode {
+    let schema = serde_json::to_string_pretty(&parser_config::config_schema())
+        .expect("Schemas serialize to JSON")
+        + "\n";
+    match output {
+        Some(output) => {
+            if let Err(err) = std::fs::write(output, schema) {
+                eprintln!("Failed to write {}: {err}", output.display());
+                return ExitCode::FAILURE;
+            }
+            eprintln!("Config schema written to {}", output.display());
+        }
+        None => print!("{schema}"),
+    }
+    ExitCode::SUCCESS
+}
+
+#[derive(Default)]
+struct ValidationSummary {
+    configs: usize,
+    errors: usize,
+    warnings: usize,
+}
+
+impl ValidationSummary {
+    fn report(&mut self, file: &Path, issues: &[LintIssue], loaded: Result<ParserConfig, String>) {
+        self.configs += 1;
+        for issue in issues {
+            match issue.severity {
+                Severity::Error => self.errors += 1,
+                Severity::Warning => self.warnings += 1,
             }
+            println!("{issue}");
+        }
+        if !issues.iter().any(|issue| issue.severity == Severity::Error)
+            && let Ok(parser_config) = loaded
+        {
+            println!(
+                "OK    {} ({}: {} char options, {} string options)",
+                file.display(),
+                parser_config.name,
+                parser_config.char_options.len(),
+                parser_config.string_options.len()
+            );
         }
     }
 
-    Ok((arg_string_buffer, idx))
+    fn finish(&self, strict: bool) -> ExitCode {
+        println!(
+            "{} config(s) checked, {} error(s), {} warning(s)",
+            self.configs, self.errors, self.warnings
+        );
+        if self.errors > 0 || (strict && self.warnings > 0) {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        }
+    }
 }
@@ -0,0 +1,388 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use once_cell::sync::Lazy;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, PoisonError};
+
+use crate::analyzer::{AnalyzerError, BehaviourTree};
+use crate::parser_config::{IndexedOption, ParserConfig};
+use crate::parser_structs::{CLArgument, CLElement};
+
+/// Parse a command-line into `CLOption`s and arguments, borrowed from `split_vec`. Unknown options
+/// are reported as `ParsingError`s.
+pub fn parse_the_split<'a, S: AsRef<str>>(
+    split_vec: &'a [S],
+    parser_config: &ParserConfig,
+) -> Vec<CLElement<'a>> {
+    Parser::new(split_vec, parser_config, false)
+        .run()
+        .expect("Unknown options are parsing errors when behaviours are not attached")
+}
+
+/// Parse a command-line and attach behaviours in the same pass: every option becomes a
+/// `CLBehaviouredOption`, preceded by the inherent behaviours of the program. Fails on the first
+/// unknown option.
+pub fn analyze_the_split<'a, S: AsRef<str>>(
+    split_vec: &'a [S],
+    parser_config: &ParserConfig,
+) -> Result<BehaviourTree<'a>, AnalyzerError> {
+    Parser::new(split_vec, parser_config, true).run()
+}
+
+struct Parser<'a, 'c, S> {
+    split_vec: &'a [S],
+    parser_config: &'c ParserConfig,
+    /// Whether options carry their behaviours, unknown options being errors
+    with_behaviours: bool,
+    parsed_cmdline: Vec<CLElement<'a>>,
+}
+
+impl<'a, 'c, S: AsRef<str>> Parser<'a, 'c, S> {
+    fn new(split_vec: &'a [S], parser_config: &'c ParserConfig, with_behaviours: bool) -> Self {
+        let mut parsed_cmdline = Vec::with_capacity(split_vec.len() + 1);
+        let inherent_behaviours = parser_config.inherent_behaviours();
+        if with_behaviours && !inherent_behaviours.is_empty() {
+            parsed_cmdline.push(CLElement::CLInherentBehaviour(inherent_behaviours));
+        }
+        Parser {
+            split_vec,
+            parser_config,
+            with_behaviours,
+            parsed_cmdline,
+        }
+    }
+
+    fn run(mut self) -> Result<Vec<CLElement<'a>>, AnalyzerError> {
+        let mut idx = 0; //Index into the split
+        while idx < self.split_vec.len() {
+            let pointed_str: &'a str = self.split_vec[idx].as_ref();
+            //TODO Watch out for anomic CLI programs (e.g., "-cache" instead of "--cache")
+            idx = if pointed_str == "--" {
+                self.parsed_cmdline.push(CLElement::CLDoubleDash);
+                idx + 1
+            } else if let Some(option_name) = pointed_str.strip_prefix("--") {
+                self.string_option(option_name, idx)?
+            } else if let Some(option_names) = pointed_str.strip_prefix('-') {
+                self.char_options(pointed_str, option_names, idx)?
+            } else if let Some(separator) = single_separator(pointed_str, self.parser_config) {
+                self.parsed_cmdline.push(CLElement::CLSep(separator));
+                idx + 1
+            } else {
+                //It's a free-standing argument, let's retrieve it
+                match get_argument_string(self.parser_config, self.split_vec, idx) {
+                    Ok((arg_str, new_idx)) => {
+                        self.push_argument(CLArgument::identified(arg_str));
+                        new_idx
+                    }
+                    Err(err_str) => {
+                        self.error("missing_argument", err_str);
+                        self.split_vec.len()
+                    }
+                }
+            };
+        }
+        Ok(self.parsed_cmdline)
+    }
+
+    /// Parse a char option block (`-xvf`), returning the index of the next element
+    fn char_options(
+        &mut self,
+        pointed_str: &str,
+        option_names: &'a str,
+        idx: usize,
+    ) -> Result<usize, AnalyzerError> {
+        let mut next_idx = idx + 1;
+        for (opt_pos, option_name) in option_names.char_indices() {
+            let name = &option_names[opt_pos..opt_pos + option_name.len_utf8()];
+            let rest = &option_names[opt_pos + option_name.len_utf8()..];
+            match self.parser_config.char_option(option_name) {
+                Some(option) if option.has_arg && !rest.is_empty() => {
+                    if self.parser_config.has_separatorless_args_for_char_options {
+                        // `-n5`: the rest of the block is the argument
+                        let argument = CLArgument::typed(rest, option.arg_type);
+                        self.push_option(name, Some(option), Some(argument))?;
+                        break;
+                    }
+                    self.error("misplaced_char_option_argument", format!("The following char option ('{}') was supposed to take an argument but was not the last char option of its option list (\"{}\"", option_name, pointed_str));
+                    self.push_option(name, Some(option), None)?;
+                }
+                Some(option) if option.has_arg => {
+                    //Get the argument that comes after it like we would for a string option
+                    match get_argument_string(self.parser_config, self.split_vec, idx + 1) {
+                        Ok((argument_string, new_idx)) => {
+                            next_idx = new_idx;
+                            let argument = CLArgument::typed(argument_string, option.arg_type);
+                            self.push_option(name, Some(option), Some(argument))?;
+                        }
+                        Err(err_msg) => {
+                            self.error("missing_argument", err_msg);
+                            self.push_option(name, Some(option), None)?;
+                            next_idx = self.split_vec.len();
+                        }
+                    }
+                }
+                Some(option) => self.push_option(name, Some(option), None)?,
+                None => {
+                    record_unknown_option(self.parser_config, name);
+                    self.error(
+                        "unknown_char_option",
+                        format!("The \"{}\" char option is unknown", option_name),
+                    );
+                    self.push_option(name, None, None)?;
+                }
+            }
+        }
+        Ok(next_idx)
+    }
+
+    /// Parse a string option (`--file`), returning the index of the next element
+    fn string_option(&mut self, option_name: &'a str, idx: usize) -> Result<usize, AnalyzerError> {
+        match self.parser_config.string_option(option_name) {
+            Some(option) if option.has_arg => {
+                match get_argument_string(self.parser_config, self.split_vec, idx + 1) {
+                    Ok((argument_string, new_idx)) => {
+                        let argument = CLArgument::typed(argument_string, option.arg_type);
+                        self.push_option(option_name, Some(option), Some(argument))?;
+                        Ok(new_idx)
+                    }
+                    Err(err_msg) => {
+                        self.error("missing_argument", err_msg);
+                        self.push_option(option_name, Some(option), None)?;
+                        Ok(self.split_vec.len())
+                    }
+                }
+            }
+            Some(option) => {
+                self.push_option(option_name, Some(option), None)?;
+                Ok(idx + 1)
+            }
+            None => {
+                tracing::debug!(option_name = %option_name, "String option not recognized, trying embedded separator split");
+
+                // Try to split by embedded separators before giving up
+                if let Some((split_option, split_arg)) =
+                    self.parser_config.try_split_embedded_option(option_name)
+                {
+                    tracing::debug!(
+                        original_option = %option_name,
+                        split_option = %split_option,
+                        split_arg = %split_arg,
+                        "Successfully split embedded option"
+                    );
+                    // Even if the split option doesn't expect an argument, let's be permissive
+                    // and use the split anyway
+                    let option = self.parser_config.string_option(split_option);
+                    let argument =
+                        CLArgument::typed(split_arg, option.and_then(|option| option.arg_type));
+                    self.push_option(split_option, option, Some(argument))?;
+                } else {
+                    tracing::debug!(option_name = %option_name, "No valid embedded split found, treating as unknown option");
+                    record_unknown_option(self.parser_config, option_name);
+                    self.error(
+                        "unknown_string_option",
+                        format!("The \"{}\" string option is unknown", option_name),
+                    );
+                    self.push_option(option_name, None, None)?;
+                }
+                Ok(idx + 1)
+            }
+        }
+    }
+
+    /// Push an option, with its behaviours if they are attached. `option` is `None` for options
+    /// missing from the config.
+    fn push_option(
+        &mut self,
+        name: &'a str,
+        option: Option<IndexedOption>,
+        argument: Option<CLArgument<'a>>,
+    ) -> Result<(), AnalyzerError> {
+        let name = Cow::Borrowed(name);
+        let element = if !self.with_behaviours {
+            CLElement::CLOption((name, argument))
+        } else {
+            let option = option.ok_or_else(|| AnalyzerError::UnknownOption {
+                program: self.parser_config.name.clone(),
+                option: name.to_string(),
+            })?;
+            let behaviours = match &argument {
+                Some(argument) => option.behaviours.union(argument.implied_behaviours()),
+                None => option.behaviours,
+            };
+            CLElement::CLBehaviouredOption((name, behaviours, argument))
+        };
+        self.parsed_cmdline.push(element);
+        Ok(())
+    }
+
+    /// Push a free-standing argument, with the behaviours it implies if behaviours are attached
+    fn push_argument(&mut self, argument: CLArgument<'a>) {
+        let behaviours = argument.implied_behaviours();
+        let element = if self.with_behaviours && !behaviours.is_empty() {
+            CLElement::CLBehaviouredArgument((argument, behaviours))
+        } else {
+            CLElement::CLArgument(argument)
+        };
+        self.parsed_cmdline.push(element);
+    }
+
+    fn error(&mut self, error_type: &'static str, msg: String) {
+        let element = parsing_error(self.parser_config, error_type, msg);
+        self.parsed_cmdline.push(element);
+    }
+}
+
+/// The separator `pointed_str` is made of, if it is a single separator
+fn single_separator(pointed_str: &str, parser_config: &ParserConfig) -> Option<char> {
+    let mut chars = pointed_str.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if parser_config.is_separator(c) => Some(c),
+        _ => None,
+    }
+}
+
+/// Build a `ParsingError` element, counting it in the `ucp_parse_errors_total` metric
+fn parsing_error(
+    parser_config: &ParserConfig,
+    error_type: &'static str,
+    msg: String,
+) -> CLElement<'static> {
+    metrics::counter!(
+        "ucp_parse_errors_total",
+        "program" => parser_config.name.clone(),
+        "error_type" => error_type
+    )
+    .increment(1);
+    CLElement::ParsingError(Some(msg))
+}
+
+/// Distinct options labelled per program in `ucp_unknown_options_total`, so that clients cannot
+/// create unbounded series
+const MAX_UNKNOWN_OPTION_LABELS: usize = 64;
+
+/// Count an option missing from the program's config in the `ucp_unknown_options_total` metric
+fn record_unknown_option(parser_config: &ParserConfig, option_name: &str) {
+    tracing::debug!(program = %parser_config.name, option = option_name, "Unknown option");
+    metrics::counter!(
+        "ucp_unknown_options_total",
+        "program" => parser_config.name.clone(),
+        "option" => unknown_option_label(&parser_config.name, option_name)
+    )
+    .increment(1);
+}
+
+/// The `option` label of an unknown option of `program`: its name for the first
+/// `MAX_UNKNOWN_OPTION_LABELS` distinct ones, `other` for the rest
+fn unknown_option_label(program: &str, option_name: &str) -> String {
+    static LABELS: Lazy<Mutex<HashMap<String, HashSet<String>>>> = Lazy::new(Default::default);
+
+    let mut labels = LABELS.lock().unwrap_or_else(PoisonError::into_inner);
+    let options = labels.entry(program.to_string()).or_default();
+    if options.contains(option_name) || options.len() < MAX_UNKNOWN_OPTION_LABELS {
+        options.insert(option_name.to_string());
+        option_name.to_string()
+    } else {
+        "other".to_string()
+    }
+}
+
+/// Split a command-line on spaces and separators, which are kept as elements of their own
+pub fn get_split_vec<'a>(cmd_line: &'a str, parser_config: &ParserConfig) -> Vec<&'a str> {
+    let mut split_vec: Vec<&str> = vec![];
+
+    let mut start = 0;
+    for (pos, current_char) in cmd_line.char_indices() {
+        //If the current char is a separator
+        //Or if it is a space character
+        if parser_config.is_separator(current_char) || current_char == ' ' {
+            let end = pos + current_char.len_utf8();
+            split_vec.push(&cmd_line[start..pos]);
+            split_vec.push(&cmd_line[pos..end]);
+            start = end;
+        }
+    }
+    if start < cmd_line.len() {
+        split_vec.push(&cmd_line[start..]);
+    }
+    split_vec
+}
+
+/// The argument starting at `idx` and the index of the element after it. A quoted argument
+/// spans the elements up to the closing quote, and is the only one to be copied.
+pub fn get_argument_string<'a, S: AsRef<str>>(
+    parser_config: &ParserConfig,
+    split_vec: &'a [S],
+    idx: usize,
+) -> Result<(Cow<'a, str>, usize), String> {
+    let Some(obtained_string) = split_vec.get(idx).map(AsRef::as_ref) else {
+        let err = format!(
+            "get_argument_string attempted to index element {} of a split_vec of size {}",
+            idx,
+            split_vec.len()
+        );
+        tracing::debug!("{}", err);
+        return Err(err);
+    };
+
+    let Some(unquoted) = obtained_string
+        .strip_prefix('"')
+        .filter(|_| parser_config.handle_quotes)
+    else {
+        return Ok((Cow::Borrowed(obtained_string), idx + 1));
+    };
+    if let Some(unquoted) = unquoted.strip_suffix('"') {
+        return Ok((Cow::Borrowed(unquoted), idx + 1));
+    }
+    let mut arg_string_buffer = unquoted.to_string(); //Push the first part without its quote
+    for (next_idx, next_string) in split_vec.iter().enumerate().skip(idx + 1) {
+        match next_string.as_ref().strip_suffix('"') {
+            //This is the last one, copy it without its quote
+            Some(last) => {
+                arg_string_buffer.push_str(last);
+                return Ok((Cow::Owned(arg_string_buffer), next_idx + 1));
+            }
+            None => arg_string_buffer.push_str(next_string.as_ref()),
+        }
+    }
+    Err(format!(
+        "The quote opening \"{}\" is never closed",
+        obtained_string
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_option_labels_are_bounded() {
+        let program = "ucp-test-unknown-option-labels";
+        for idx in 0..MAX_UNKNOWN_OPTION_LABELS {
+            let option_name = format!("opt{idx}");
+            assert_eq!(unknown_option_label(program, &option_name), option_name);
+        }
+        assert_eq!(unknown_option_label(program, "one-too-many"), "other");
+        // Options already labelled keep their label, and other programs have their own
+        assert_eq!(unknown_option_label(program, "opt0"), "opt0");
+        assert_eq!(
+            unknown_option_label("ucp-test-other-program", "one-too-many"),
+            "one-too-many"
+        );
+    }
+}
@@ -15,10 +15,20 @@
    along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
+use schemars::JsonSchema;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
+use std::path::{Path, PathBuf};
 
-#[derive(Deserialize, Clone)]
+use crate::config_format::{ConfigFormat, format_variants};
+use crate::config_store::ConfigStore;
+use crate::env_vars::EnvVarRule;
+use crate::parser_structs::ArgumentType;
+use crate::registry::RegistryHeader;
+use crate::taxonomy::{self, Behaviour, BehaviourSet};
+
+#[derive(Clone)]
 pub struct ConfigFile {
     name: String,
     has_separatorless_args_for_char_options: bool,
@@ -26,21 +36,107 @@ pub struct ConfigFile {
     string_separators: Vec<String>,
     string_options: Vec<StringOption>,
     char_options: Vec<CharOption>,
-    pub(crate) behaviours: Vec<String>,
+    env_vars: Vec<EnvVar>,
+    pub(crate) behaviours: Vec<Behaviour>,
 }
 
-#[derive(Deserialize, Clone)]
+/// A config file as written, before `extends` and `include` are resolved. Without `extends`,
+/// the header fields (`name`, `string_separators`, ...) are mandatory.
+#[derive(Deserialize, JsonSchema)]
+struct ConfigFileSource {
+    /// Program whose config (a sibling file) this one is based on
+    extends: Option<String>,
+    /// Fragments (paths relative to this file) whose options are added to this config
+    #[serde(default)]
+    include: Vec<String>,
+    /// Program described by the config, the file name (without `@dialect`) by default
+    name: Option<String>,
+    /// Whether char options take their argument glued to them (`-n5`)
+    has_separatorless_args_for_char_options: Option<bool>,
+    /// Whether quoted arguments are kept together
+    handle_quotes: Option<bool>,
+    /// Characters separating a string option from its argument (`=`)
+    string_separators: Option<Vec<String>>,
+    #[serde(default)]
+    string_options: Vec<StringOption>,
+    #[serde(default)]
+    char_options: Vec<CharOption>,
+    /// Behaviours of every invocation of the program
+    behaviours: Option<Vec<Behaviour>>,
+    /// Environment variables the program reads, on top of (or instead of) the global rules
+    #[serde(default)]
+    env_vars: Vec<EnvVar>,
+    /// Inherited or included string options to drop
+    #[serde(default)]
+    remove_string_options: Vec<String>,
+    /// Inherited or included char options to drop
+    #[serde(default)]
+    remove_char_options: Vec<char>,
+}
+
+/// An `include`d file, which may only define options and include other fragments
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFragment {
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    string_options: Vec<StringOption>,
+    #[serde(default)]
+    char_options: Vec<CharOption>,
+}
+
+/// A long option (`--verbose`)
+#[derive(Deserialize, JsonSchema, Clone)]
 struct StringOption {
+    /// Name of the option, without its dashes
     option_name: String,
+    /// Whether the option takes an argument
     has_arg: Option<bool>,
-    behaviours: Vec<String>,
+    /// Type of the argument, when it can't be told from the argument alone (e.g. a port)
+    arg_type: Option<ArgumentType>,
+    behaviours: Vec<Behaviour>,
 }
 
-#[derive(Deserialize, Clone)]
+/// A short option (`-v`)
+#[derive(Deserialize, JsonSchema, Clone)]
 struct CharOption {
     option_name: char,
+    /// Whether the option takes an argument
     has_arg: Option<bool>,
-    behaviours: Vec<String>,
+    /// Type of the argument, when it can't be told from the argument alone (e.g. a port)
+    arg_type: Option<ArgumentType>,
+    behaviours: Vec<Behaviour>,
+}
+
+/// An environment variable read by the program (`CURL_HOME`)
+#[derive(Deserialize, JsonSchema, Clone)]
+struct EnvVar {
+    name: String,
+    /// Behaviours of the program when the variable is set
+    behaviours: Vec<Behaviour>,
+    /// Whether the value is a command-line the program runs, to be parsed in turn
+    #[serde(default)]
+    command: bool,
+}
+
+/// Every field of a config file, for the schema: the `ConfigFileSource` ones and the
+/// `RegistryHeader` ones
+#[derive(JsonSchema)]
+#[schemars(title = "ConfigFile")]
+#[allow(dead_code)]
+struct ConfigFileSchema {
+    #[serde(flatten)]
+    source: ConfigFileSource,
+    #[serde(flatten)]
+    header: RegistryHeader,
+}
+
+/// JSON Schema of config files, whatever their format, for editors to validate and complete them.
+/// Behaviours are the ones of the current taxonomy (see `Taxonomy::current`).
+pub fn config_schema() -> serde_json::Value {
+    serde_json::to_value(schemars::schema_for!(ConfigFileSchema))
+        .expect("Schemas serialize to JSON")
 }
 
 #[derive(Clone)]
@@ -52,6 +148,100 @@ pub struct ParserConfig {
     pub has_separatorless_args_for_char_options: bool,
     pub handle_quotes: bool,
     pub config_file: ConfigFile,
+    /// Options by name, built once when the config is created
+    index: OptionIndex,
+}
+
+/// What the parser needs to know about an option
+#[derive(Clone, Copy)]
+pub(crate) struct IndexedOption {
+    pub(crate) has_arg: bool,
+    pub(crate) arg_type: Option<ArgumentType>,
+    pub(crate) behaviours: BehaviourSet,
+}
+
+/// Indexes of the options of a config: a table for ASCII char options, hash maps for the others.
+/// When an option is defined twice, the first definition wins, as with a linear search.
+#[derive(Clone)]
+struct OptionIndex {
+    /// Inherent behaviours of the program
+    behaviours: BehaviourSet,
+    ascii_char_options: Box<[Option<IndexedOption>; 128]>,
+    other_char_options: HashMap<char, IndexedOption>,
+    string_options: HashMap<String, IndexedOption>,
+    env_vars: HashMap<String, EnvVarRule>,
+}
+
+impl OptionIndex {
+    fn new(
+        char_options: &[(char, bool)],
+        string_options: &[(String, bool)],
+        config_file: &ConfigFile,
+    ) -> OptionIndex {
+        let mut index = OptionIndex {
+            behaviours: taxonomy::intern_behaviours(&config_file.behaviours),
+            ascii_char_options: Box::new([const { None }; 128]),
+            other_char_options: HashMap::new(),
+            string_options: HashMap::new(),
+            env_vars: HashMap::new(),
+        };
+        // The arguments come from the option lists, their types and the behaviours from the
+        // config file
+        for (name, has_arg) in char_options {
+            let defined = config_file
+                .char_options
+                .iter()
+                .find(|opt| opt.option_name == *name);
+            let option = || IndexedOption {
+                has_arg: *has_arg,
+                arg_type: defined.and_then(|opt| opt.arg_type),
+                behaviours: defined
+                    .map(|opt| taxonomy::intern_behaviours(&opt.behaviours))
+                    .unwrap_or_default(),
+            };
+            match index.ascii_char_options.get_mut(*name as usize) {
+                Some(slot) => {
+                    slot.get_or_insert_with(option);
+                }
+                None => {
+                    index.other_char_options.entry(*name).or_insert_with(option);
+                }
+            }
+        }
+        for (name, has_arg) in string_options {
+            let defined = config_file
+                .string_options
+                .iter()
+                .find(|opt| opt.option_name == *name);
+            index
+                .string_options
+                .entry(name.clone())
+                .or_insert_with(|| IndexedOption {
+                    has_arg: *has_arg,
+                    arg_type: defined.and_then(|opt| opt.arg_type),
+                    behaviours: defined
+                        .map(|opt| taxonomy::intern_behaviours(&opt.behaviours))
+                        .unwrap_or_default(),
+                });
+        }
+        for env_var in &config_file.env_vars {
+            index
+                .env_vars
+                .entry(env_var.name.clone())
+                .or_insert_with(|| EnvVarRule {
+                    behaviours: taxonomy::intern_behaviours(&env_var.behaviours),
+                    command: env_var.command,
+                });
+        }
+        index
+    }
+
+    fn char_option(&self, name: char) -> Option<&IndexedOption> {
+        match self.ascii_char_options.get(name as usize) {
+            Some(slot) => slot.as_ref(),
+            None => self.other_char_options.get(&name),
+        }
+    }
 }
 
 impl ParserConfig {
@@ -64,6 +254,7 @@ impl ParserConfig {
         handle_quotes: bool,
         config_file: ConfigFile,
     ) -> ParserConfig {
+        let index = OptionIndex::new(&char_options, &string_options, &config_file);
         ParserConfig {
             name,
             string_separators,
@@ -72,6 +263,7 @@ impl ParserConfig {
             has_separatorless_args_for_char_options,
             handle_quotes,
             config_file,
+            index,
         }
     }
 
@@ -80,122 +272,103 @@ impl ParserConfig {
     }
 
     pub fn does_char_option_have_arg(&self, option_name: &char) -> Result<bool, String> {
-        for str_opt in &self.char_options {
-            if str_opt.0.eq(option_name) {
-                //println!(
-                //    "\tFound char option {}, argument : {}",
-                //    str_opt.0, str_opt.1
-                //);
-                return Ok(str_opt.1);
-            }
-        }
-        Err(format!("The \"{}\" char option is unknown", option_name))
+        self.index
+            .char_option(*option_name)
+            .map(|option| option.has_arg)
+            .ok_or_else(|| format!("The \"{}\" char option is unknown", option_name))
     }
 
     pub fn does_string_option_have_arg(&self, option_name: &String) -> Result<bool, String> {
-        for str_opt in &self.string_options {
-            if str_opt.0.eq(option_name) {
-                //println!(
-                //    "\tFound string option {}, argument : {}",
-                //    str_opt.0, str_opt.1
-                //);
-                return Ok(str_opt.1);
-            }
-        }
-        Err(format!("The \"{}\" string option is unknown", option_name))
+        self.string_option(option_name)
+            .map(|option| option.has_arg)
+            .ok_or_else(|| format!("The \"{}\" string option is unknown", option_name))
     }
 
     pub fn get_behaviours_for_char_option(
         &self,
         option_name: &char,
-    ) -> Result<Vec<String>, String> {
-        //Look in the config_file member
-        for char_opt in &self.config_file.char_options {
-            if char_opt.option_name.eq(option_name) {
-                //println!(
-                //    "\tFound char option {}, behaviours : {:?}",
-                //    char_opt.option_name, char_opt.behaviours
-                //);
-                return Ok(char_opt.behaviours.clone());
-            }
-        }
-        Err(format!("The \"{}\" char option is unknown", option_name))
+    ) -> Result<Vec<Behaviour>, String> {
+        self.char_option_behaviours(*option_name)
+            .map(|behaviours| behaviours.to_vec())
+            .ok_or_else(|| format!("The \"{}\" char option is unknown", option_name))
     }
 
     pub fn get_behaviours_for_string_option(
         &self,
         option_name: &str,
-    ) -> Result<Vec<String>, String> {
-        //Look in the config_file member
-        for str_opt in &self.config_file.string_options {
-            if str_opt.option_name.eq(option_name) {
-                //println!(
-                //    "\tFound string option {}, behaviours : {:?}",
-                //    str_opt.option_name, str_opt.behaviours
-                //);
-                return Ok(str_opt.behaviours.clone());
-            }
-        }
-        Err(format!("The \"{}\" string option is unknown", option_name))
+    ) -> Result<Vec<Behaviour>, String> {
+        self.string_option_behaviours(option_name)
+            .map(|behaviours| behaviours.to_vec())
+            .ok_or_else(|| format!("The \"{}\" string option is unknown", option_name))
     }
 
-    pub fn get_behaviours(&self, name: &String) -> Result<Vec<String>, String> {
+    pub fn get_behaviours(&self, name: &str) -> Result<Vec<Behaviour>, String> {
         if name.len() == 1 {
             //It's a char option
             self.get_behaviours_for_char_option(&name.chars().next().unwrap())
         } else {
-            self.get_behaviours_for_string_option(name.as_str())
+            self.get_behaviours_for_string_option(name)
+        }
+    }
+
+    /// Behaviours of a char option, without copying them
+    pub fn char_option_behaviours(&self, option_name: char) -> Option<BehaviourSet> {
+        self.char_option(option_name)
+            .map(|option| option.behaviours)
+    }
+
+    /// Behaviours of a string option, without copying them
+    pub fn string_option_behaviours(&self, option_name: &str) -> Option<BehaviourSet> {
+        self.string_option(option_name)
+            .map(|option| option.behaviours)
+    }
+
+    /// Inherent behaviours of the program, without copying them
+    pub fn inherent_behaviours(&self) -> BehaviourSet {
+        self.index.behaviours
+    }
+
+    /// Behaviours of an option, a char option if `name` is a single character, like
+    /// `get_behaviours` but without copying them
+    pub fn behaviours(&self, name: &str) -> Option<BehaviourSet> {
+        if name.len() == 1 {
+            self.char_option_behaviours(name.chars().next().unwrap())
+        } else {
+            self.string_option_behaviours(name)
         }
     }
 
+    /// What setting the variable `name` does to the program, according to its config only (see
+    /// `env_vars::global_rule` for the other variables)
+    pub fn env_var(&self, name: &str) -> Option<EnvVarRule> {
+        self.index.env_vars.get(name).copied()
+    }
+
+    pub(crate) fn char_option(&self, option_name: char) -> Option<IndexedOption> {
+        self.index.char_option(option_name).copied()
+    }
+
+    pub(crate) fn string_option(&self, option_name: &str) -> Option<IndexedOption> {
+        self.index.string_options.get(option_name).copied()
+    }
+
     /// Try to split an option string by configured separators and return (option_name, argument) if found
     /// Will try all possible splits and return the longest matching option name
-    pub fn try_split_embedded_option(&self, option_str: &str) -> Option<(String, String)> {
+    pub fn try_split_embedded_option<'o>(&self, option_str: &'o str) -> Option<(&'o str, &'o str)> {
         tracing::debug!(option_str = %option_str, separators = ?self.string_separators, "Attempting to split embedded option");
 
-        let mut best_match: Option<(String, String)> = None;
-        let mut longest_option_len = 0;
-
-        for separator in &self.string_separators {
-            // Find all positions where this separator appears
-            let mut start_pos = 0;
-            while let Some(split_pos) = option_str[start_pos..].find(*separator) {
-                let actual_split_pos = start_pos + split_pos;
-                let (option_part, remaining) = option_str.split_at(actual_split_pos);
-                let arg_part = &remaining[1..]; // Remove the separator character
-
-                tracing::debug!(
-                    option_part = %option_part,
-                    arg_part = %arg_part,
-                    separator = %separator,
-                    "Found split candidate"
-                );
-
-                // Check if the option part (before separator) is a known string option
-                if self
-                    .does_string_option_have_arg(&option_part.to_string())
-                    .is_ok()
-                {
-                    tracing::debug!(option_part = %option_part, "Split option is known");
-
-                    // Keep track of the longest matching option
-                    if option_part.len() > longest_option_len {
-                        longest_option_len = option_part.len();
-                        best_match = Some((option_part.to_string(), arg_part.to_string()));
-                        tracing::debug!(
-                            option_part = %option_part,
-                            arg_part = %arg_part,
-                            "New best match found (longer option name)"
-                        );
-                    }
-                } else {
-                    tracing::debug!(option_part = %option_part, "Split option is not known");
-                }
-
-                // Move to the next potential split position
-                start_pos = actual_split_pos + 1;
-            }
-        }
+        // The longest option name ends at the last separator after which the split is known
+        let best_match = option_str
+            .char_indices()
+            .rev()
+            .filter(|(_, c)| self.is_separator(*c))
+            .find(|(split_pos, _)| self.string_option(&option_str[..*split_pos]).is_some())
+            .map(|(split_pos, separator)| {
+                (
+                    &option_str[..split_pos],
+                    &option_str[split_pos + separator.len_utf8()..],
+                )
+            });
 
         if let Some((option, arg)) = &best_match {
             tracing::debug!(option = %option, arg = %arg, "Best embedded split found");
@@ -207,16 +380,87 @@ impl ParserConfig {
     }
 
     pub fn from_toml_file(file_path: &str) -> Result<ParserConfig, String> {
-        let contents = fs::read_to_string(file_path).map_err(|e| e.to_string())?;
-        let config_file: ConfigFile = toml::from_str(&contents).map_err(|e| e.to_string())?;
+        ParserConfig::from_file(Path::new(file_path))
+    }
+
+    pub fn from_toml_file_with_sources(
+        file_path: &Path,
+    ) -> Result<(ParserConfig, Vec<PathBuf>), String> {
+        ParserConfig::from_file_with_sources(file_path)
+    }
+
+    /// Load a config file of any format, chosen by its extension (TOML when it has none)
+    pub fn from_file(file_path: &Path) -> Result<ParserConfig, String> {
+        ParserConfig::from_file_with_sources(file_path).map(|(config, _)| config)
+    }
 
+    /// Load a config file, resolving its `extends` and `include` directives. Also returns every
+    /// file the config was built from, starting with `file_path` itself.
+    pub fn from_file_with_sources(
+        file_path: &Path,
+    ) -> Result<(ParserConfig, Vec<PathBuf>), String> {
+        let read = |path: &Path| {
+            // Bases and fragments may be written in another format than the file naming them
+            let variants = format_variants(path);
+            let path = variants
+                .iter()
+                .find(|variant| variant.is_file())
+                .map_or(path, PathBuf::as_path);
+            fs::read_to_string(path)
+                .map(|contents| (contents, path.to_path_buf()))
+                .map_err(|e| format!("{}: {}", path.display(), e))
+        };
+        let mut sources = vec![];
+        let config_file = resolve_config_file(file_path, &read, &mut vec![], &mut sources)?;
+        Ok((ParserConfig::from_config_file(config_file)?, sources))
+    }
+
+    /// Load the config file `name` (e.g. `tar.toml`) of `store`, resolving its `extends` and
+    /// `include` directives within the store. Also returns every file the config was built from.
+    pub fn from_store(
+        store: &ConfigStore,
+        name: &Path,
+    ) -> Result<(ParserConfig, Vec<PathBuf>), String> {
+        let read = |path: &Path| store.read(path);
+        let mut sources = vec![];
+        let config_file = resolve_config_file(name, &read, &mut vec![], &mut sources)?;
+        Ok((ParserConfig::from_config_file(config_file)?, sources))
+    }
+
+    pub fn from_toml_str(contents: &str) -> Result<ParserConfig, String> {
+        ParserConfig::from_str(contents, ConfigFormat::Toml)
+    }
+
+    /// Load a config from its contents. `extends` and `include` need the path of the config to be
+    /// resolved, use `from_file` for such configs.
+    pub fn from_str(contents: &str, format: ConfigFormat) -> Result<ParserConfig, String> {
+        let source: ConfigFileSource = format.deserialize(contents)?;
+        if source.extends.is_some() || !source.include.is_empty() {
+            return Err(
+                "extends and include can only be resolved when loading a config file".to_string(),
+            );
+        }
+        let config_file = merge_config_file(source, None, vec![], None)?;
+        ParserConfig::from_config_file(config_file)
+    }
+
+    fn from_config_file(config_file: ConfigFile) -> Result<ParserConfig, String> {
         let name = config_file.name.clone();
 
         let string_separators: Vec<char> = config_file
             .string_separators
             .iter()
-            .map(|s| s.chars().next().unwrap())
-            .collect();
+            .map(|s| {
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(separator), None) => Ok(separator),
+                    _ => Err(format!(
+                        "String separator \"{}\" must be exactly one character",
+                        s
+                    )),
+                }
+            })
+            .collect::<Result<Vec<char>, String>>()?;
 
         let char_options: Vec<(char, bool)> = config_file
             .char_options
@@ -230,15 +474,409 @@ impl ParserConfig {
             .map(|opt| (opt.option_name.clone(), opt.has_arg.unwrap_or(false)))
             .collect();
 
-        Ok(ParserConfig {
+        Ok(ParserConfig::new(
             name,
             string_separators,
             char_options,
             string_options,
-            has_separatorless_args_for_char_options: config_file
-                .has_separatorless_args_for_char_options,
-            handle_quotes: config_file.handle_quotes,
+            config_file.has_separatorless_args_for_char_options,
+            config_file.handle_quotes,
             config_file,
-        })
+        ))
+    }
+}
+
+/// Reads a config file, returning its contents and the path of the file actually read
+type ReadFile<'a> = &'a dyn Fn(&Path) -> Result<(String, PathBuf), String>;
+
+/// Load the config file at `file_path` along with its bases and fragments. `stack` holds the files
+/// being resolved, to detect cycles, and `sources` collects every file read.
+fn resolve_config_file(
+    file_path: &Path,
+    read: ReadFile,
+    stack: &mut Vec<PathBuf>,
+    sources: &mut Vec<PathBuf>,
+) -> Result<ConfigFile, String> {
+    let (contents, source_path) = read_source(file_path, read, stack, sources)?;
+    let source: ConfigFileSource = source_format(&source_path)
+        .deserialize(&contents)
+        .map_err(|e| format!("{}: {}", source_path.display(), e))?;
+    let dir = file_path.parent().unwrap_or(Path::new(""));
+
+    let base = match &source.extends {
+        Some(base_program) => {
+            if base_program.contains(['/', '\\']) || base_program.starts_with('.') {
+                return Err(format!(
+                    "{}: extends must name a program, not a path (\"{}\")",
+                    file_path.display(),
+                    base_program
+                ));
+            }
+            let base_path = dir.join(format!("{}.toml", base_program));
+            Some(resolve_config_file(&base_path, read, stack, sources)?)
+        }
+        None => None,
+    };
+
+    let mut included = vec![];
+    for fragment in &source.include {
+        included.push(resolve_fragment(&dir.join(fragment), read, stack, sources)?);
+    }
+
+    stack.pop();
+    // `tar@bsd.toml` describes `tar`
+    let default_name = file_path.file_stem().map(|stem| {
+        let stem = stem.to_string_lossy();
+        stem.split('@').next().unwrap_or_default().to_string()
+    });
+    merge_config_file(source, base, included, default_name)
+        .map_err(|e| format!("{}: {}", source_path.display(), e))
+}
+
+fn resolve_fragment(
+    file_path: &Path,
+    read: ReadFile,
+    stack: &mut Vec<PathBuf>,
+    sources: &mut Vec<PathBuf>,
+) -> Result<ConfigFragment, String> {
+    let (contents, source_path) = read_source(file_path, read, stack, sources)?;
+    let mut fragment: ConfigFragment = source_format(&source_path)
+        .deserialize(&contents)
+        .map_err(|e| format!("{}: {}", source_path.display(), e))?;
+    let dir = file_path.parent().unwrap_or(Path::new(""));
+
+    let mut string_options = vec![];
+    let mut char_options = vec![];
+    for nested in std::mem::take(&mut fragment.include) {
+        let nested = resolve_fragment(&dir.join(nested), read, stack, sources)?;
+        override_options(&mut string_options, nested.string_options, |opt| {
+            opt.option_name.clone()
+        });
+        override_options(&mut char_options, nested.char_options, |opt| {
+            opt.option_name
+        });
+    }
+    override_options(&mut string_options, fragment.string_options, |opt| {
+        opt.option_name.clone()
+    });
+    override_options(&mut char_options, fragment.char_options, |opt| {
+        opt.option_name
+    });
+
+    stack.pop();
+    Ok(ConfigFragment {
+        include: vec![],
+        string_options,
+        char_options,
+    })
+}
+
+/// Format of a config or fragment file, TOML unless its extension says otherwise
+fn source_format(source_path: &Path) -> ConfigFormat {
+    ConfigFormat::from_path(source_path).unwrap_or(ConfigFormat::Toml)
+}
+
+/// Read a config or fragment file, pushing it onto `stack` (the caller pops it once resolved)
+fn read_source(
+    file_path: &Path,
+    read: ReadFile,
+    stack: &mut Vec<PathBuf>,
+    sources: &mut Vec<PathBuf>,
+) -> Result<(String, PathBuf), String> {
+    let (contents, source_path) = read(file_path)?;
+    let key = fs::canonicalize(&source_path).unwrap_or_else(|_| source_path.clone());
+    if stack.contains(&key) {
+        let cycle: Vec<String> = stack
+            .iter()
+            .chain(std::iter::once(&key))
+            .map(|path| path.display().to_string())
+            .collect();
+        return Err(format!("Config inheritance cycle: {}", cycle.join(" -> ")));
+    }
+    stack.push(key);
+    sources.push(source_path.clone());
+    Ok((contents, source_path))
+}
+
+/// Build the final config: the base's options, then the included ones, then the file's own ones,
+/// each layer replacing the options of the previous layers that have the same name
+fn merge_config_file(
+    source: ConfigFileSource,
+    base: Option<ConfigFile>,
+    included: Vec<ConfigFragment>,
+    default_name: Option<String>,
+) -> Result<ConfigFile, String> {
+    let (mut string_options, mut char_options) = match &base {
+        Some(base) => (base.string_options.clone(), base.char_options.clone()),
+        None => (vec![], vec![]),
+    };
+    for fragment in included {
+        override_options(&mut string_options, fragment.string_options, |opt| {
+            opt.option_name.clone()
+        });
+        override_options(&mut char_options, fragment.char_options, |opt| {
+            opt.option_name
+        });
+    }
+    override_options(&mut string_options, source.string_options, |opt| {
+        opt.option_name.clone()
+    });
+    override_options(&mut char_options, source.char_options, |opt| {
+        opt.option_name
+    });
+
+    let mut env_vars = match &base {
+        Some(base) => base.env_vars.clone(),
+        None => vec![],
+    };
+    override_options(&mut env_vars, source.env_vars, |var| var.name.clone());
+
+    string_options.retain(|opt| !source.remove_string_options.contains(&opt.option_name));
+    char_options.retain(|opt| !source.remove_char_options.contains(&opt.option_name));
+
+    fn inherit<T: Clone>(
+        value: Option<T>,
+        base: &Option<ConfigFile>,
+        base_value: impl Fn(&ConfigFile) -> T,
+        field: &str,
+    ) -> Result<T, String> {
+        value
+            .or_else(|| base.as_ref().map(base_value))
+            .ok_or_else(|| format!("missing field `{}`", field))
+    }
+
+    Ok(ConfigFile {
+        name: match (source.name, &base) {
+            (Some(name), _) => name,
+            (None, Some(_)) => default_name.ok_or("missing field `name`")?,
+            (None, None) => return Err("missing field `name`".to_string()),
+        },
+        has_separatorless_args_for_char_options: inherit(
+            source.has_separatorless_args_for_char_options,
+            &base,
+            |base| base.has_separatorless_args_for_char_options,
+            "has_separatorless_args_for_char_options",
+        )?,
+        handle_quotes: inherit(
+            source.handle_quotes,
+            &base,
+            |base| base.handle_quotes,
+            "handle_quotes",
+        )?,
+        string_separators: inherit(
+            source.string_separators,
+            &base,
+            |base| base.string_separators.clone(),
+            "string_separators",
+        )?,
+        string_options,
+        char_options,
+        env_vars,
+        behaviours: inherit(
+            source.behaviours,
+            &base,
+            |base| base.behaviours.clone(),
+            "behaviours",
+        )?,
+    })
+}
+
+/// Append `overrides` to `options`, dropping the options they redefine
+fn override_options<T, K: PartialEq>(
+    options: &mut Vec<T>,
+    overrides: Vec<T>,
+    key: impl Fn(&T) -> K,
+) {
+    let overridden: Vec<K> = overrides.iter().map(&key).collect();
+    options.retain(|opt| !overridden.contains(&key(opt)));
+    options.extend(overrides);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_store::ConfigDir;
+
+    const BASE: &str = r#"
+name = "base"
+has_separatorless_args_for_char_options = true
+string_separators = ["="]
+handle_quotes = false
+behaviours = ["NEUTRAL"]
+
+[[char_options]]
+option_name = "v"
+behaviours = ["NEUTRAL"]
+
+[[char_options]]
+option_name = "f"
+has_arg = true
+behaviours = ["FILE_READ"]
+
+[[string_options]]
+option_name = "file"
+has_arg = true
+behaviours = ["FILE_READ"]
+"#;
+
+    fn load(dir: &ConfigDir, name: &str) -> Result<(ParserConfig, Vec<PathBuf>), String> {
+        ParserConfig::from_file_with_sources(&dir.path().join(name))
+    }
+
+    fn names(behaviours: Result<Vec<Behaviour>, String>) -> Vec<&'static str> {
+        behaviours.unwrap().iter().map(|b| b.name()).collect()
+    }
+
+    #[test]
+    fn extends_overrides_options() {
+        let child = r#"
+extends = "base"
+
+[[char_options]]
+option_name = "v"
+behaviours = ["CMD_EXEC"]
+
+[[char_options]]
+option_name = "z"
+behaviours = ["NEUTRAL"]
+"#;
+        let dir = ConfigDir::new(
+            "parser-config-override",
+            &[("base.toml", BASE), ("child.toml", child)],
+        );
+        let (config, sources) = load(&dir, "child.toml").unwrap();
+        assert_eq!(config.name, "child");
+        assert!(config.has_separatorless_args_for_char_options);
+        assert!(config.is_separator('='));
+        assert_eq!(
+            names(config.get_behaviours_for_char_option(&'v')),
+            ["CMD_EXEC"]
+        );
+        assert_eq!(
+            names(config.get_behaviours_for_char_option(&'f')),
+            ["FILE_READ"]
+        );
+        assert_eq!(
+            names(config.get_behaviours_for_char_option(&'z')),
+            ["NEUTRAL"]
+        );
+        assert_eq!(
+            config.does_string_option_have_arg(&"file".to_string()),
+            Ok(true)
+        );
+        assert_eq!(
+            sources,
+            [dir.path().join("child.toml"), dir.path().join("base.toml")]
+        );
+    }
+
+    #[test]
+    fn remove_options() {
+        let child = r#"
+extends = "base"
+name = "other"
+remove_char_options = ["f"]
+remove_string_options = ["file"]
+"#;
+        let dir = ConfigDir::new(
+            "parser-config-remove",
+            &[("base.toml", BASE), ("child.toml", child)],
+        );
+        let (config, _) = load(&dir, "child.toml").unwrap();
+        assert_eq!(config.name, "other");
+        assert!(config.does_char_option_have_arg(&'f').is_err());
+        assert!(config.get_behaviours_for_string_option("file").is_err());
+        assert_eq!(
+            names(config.get_behaviours_for_char_option(&'v')),
+            ["NEUTRAL"]
+        );
+    }
+
+    #[test]
+    fn nested_includes() {
+        let child = r#"
+extends = "base"
+include = ["fragments/outer.toml"]
+
+[[string_options]]
+option_name = "own"
+behaviours = ["CMD_EXEC"]
+"#;
+        let outer = r#"
+include = ["inner.toml"]
+
+[[char_options]]
+option_name = "f"
+has_arg = true
+behaviours = ["FILE_WRITE"]
+
+[[string_options]]
+option_name = "shared"
+behaviours = ["FILE_WRITE"]
+"#;
+        let inner = r#"
+[[string_options]]
+option_name = "shared"
+behaviours = ["NEUTRAL"]
+
+[[string_options]]
+option_name = "inner"
+behaviours = ["FILE_READ"]
+
+[[string_options]]
+option_name = "own"
+behaviours = ["NEUTRAL"]
+"#;
+        let dir = ConfigDir::new(
+            "parser-config-include",
+            &[
+                ("base.toml", BASE),
+                ("child.toml", child),
+                ("fragments/outer.toml", outer),
+                ("fragments/inner.toml", inner),
+            ],
+        );
+        let (config, sources) = load(&dir, "child.toml").unwrap();
+        // Fragments override the base, the including file its fragments, and the config them all
+        assert_eq!(
+            names(config.get_behaviours_for_char_option(&'f')),
+            ["FILE_WRITE"]
+        );
+        assert_eq!(
+            names(config.get_behaviours_for_string_option("shared")),
+            ["FILE_WRITE"]
+        );
+        assert_eq!(
+            names(config.get_behaviours_for_string_option("inner")),
+            ["FILE_READ"]
+        );
+        assert_eq!(
+            names(config.get_behaviours_for_string_option("own")),
+            ["CMD_EXEC"]
+        );
+        assert_eq!(
+            names(config.get_behaviours_for_char_option(&'v')),
+            ["NEUTRAL"]
+        );
+        assert_eq!(sources.len(), 4);
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let dir = ConfigDir::new(
+            "parser-config-cycle",
+            &[
+                ("a.toml", "extends = \"b\"\n"),
+                ("b.toml", "extends = \"a\"\n"),
+                ("c.toml", "extends = \"base\"\ninclude = [\"d.toml\"]\n"),
+                ("d.toml", "include = [\"d.toml\"]\n"),
+                ("base.toml", BASE),
+            ],
+        );
+        let err = load(&dir, "a.toml").err().unwrap();
+        assert!(err.starts_with("Config inheritance cycle: "), "{err}");
+        assert!(err.ends_with("a.toml"), "{err}");
+        let err = load(&dir, "c.toml").err().unwrap();
+        assert!(err.starts_with("Config inheritance cycle: "), "{err}");
     }
 }
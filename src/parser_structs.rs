@@ -15,90 +15,730 @@
    along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-use std::default;
+use std::borrow::Cow;
 
 use once_cell::sync::Lazy;
 use regex::Regex;
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::env_vars::EnvAssignment;
+use crate::path_classifier::{LocalPath, PathContext, is_local_path};
+use crate::taxonomy::{self, Behaviour, BehaviourSet};
 
 #[derive(Debug, Serialize, Clone)]
-pub enum CLElement {
-    CLOption((String, Option<CLArgument>)), //Denotes command-line option with an optional argument
-    CLBehaviouredOption((String, Vec<String>, Option<CLArgument>)), //Denotes command-line option with a list of behaviours and an optional argument
-    CLInherentBehaviour(Vec<String>), //Denotes an inherent behaviour of the program
-    CLArgument(CLArgument),           //Denotes a free-standing argument, such as a URI for curl
-    CLSep(char),                      //Denotes a separator
-    ParsingError(Option<String>),     //Used to express errors in the parsing process,
+pub enum CLElement<'a> {
+    CLOption((Cow<'a, str>, Option<CLArgument<'a>>)), //Denotes command-line option with an optional argument
+    CLBehaviouredOption((Cow<'a, str>, BehaviourSet, Option<CLArgument<'a>>)), //Denotes command-line option with a list of behaviours and an optional argument
+    CLInherentBehaviour(BehaviourSet), //Denotes an inherent behaviour of the program
+    CLArgument(CLArgument<'a>),        //Denotes a free-standing argument, such as a URI for curl
+    CLSep(char),                       //Denotes a separator
+    ParsingError(Option<String>),      //Used to express errors in the parsing process,
     CLDoubleDash, //Used to designate free-standing a double-dash sequence, usually used to indicate that input should be read from stdin
+    CLBehaviouredArgument((CLArgument<'a>, BehaviourSet)), //Denotes a free-standing argument with the behaviours it suggests (heuristic parses) or implies (`file:` URLs...)
+    CLHeuristic(f32), //Marks a command-line parsed without a config, by guessing, with the confidence of the guess
+    CLEnvAssignment(EnvAssignment<'a>), //Denotes an environment variable set for the program (`LD_PRELOAD=... ls`), with the behaviours it brings
 }
 
+/// An argument, borrowed from the command-line whenever it is used as is
 #[derive(Debug, Serialize, Clone)]
-pub enum CLArgument {
-    String(String),
-    U16(u16),
+#[allow(clippy::upper_case_acronyms)]
+pub enum CLArgument<'a> {
+    String(Cow<'a, str>),
     Integer(i64),
     Float(f32),
     Boolean(bool),
-    IPAddress(String),
-    RemotePath(String),
-    LocalPath(String),
-    URL(String),
+    IPv4Address(Ipv4Addr),
+    IPv6Address(Ipv6Addr),
+    /// A host name (`example.com`), or a network interface when the config says so
+    Hostname(Cow<'a, str>),
+    /// `proxy:8080`, `10.0.0.1:22`, `[::1]:443`
+    HostPort {
+        host: Host<'a>,
+        port: u16,
+    },
+    /// A network, `10.0.0.0/8`
+    Cidr {
+        address: IpAddr,
+        prefix_length: u8,
+    },
+    /// A port number, only when the config says the option takes one
+    Port(u16),
+    Email {
+        user: Cow<'a, str>,
+        domain: Cow<'a, str>,
+    },
+    /// An account on a host, `root@server` (ssh, scp...)
+    UserAtHost {
+        user: Cow<'a, str>,
+        host: Host<'a>,
+    },
+    RemotePath(Cow<'a, str>),
+    LocalPath(LocalPath<'a>),
+    URL(Url<'a>),
+}
+
+/// The host part of a network argument
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Host<'a> {
+    IPv4(Ipv4Addr),
+    IPv6(Ipv6Addr),
+    Name(Cow<'a, str>),
+}
+
+/// A URL split into its parts, which borrow from the argument
+#[derive(Debug, Serialize, Clone)]
+pub struct Url<'a> {
+    /// The URL as written
+    pub url: Cow<'a, str>,
+    pub scheme: Cow<'a, str>,
+    pub user: Option<Cow<'a, str>>,
+    pub password: Option<Cow<'a, str>>,
+    pub host: Option<Host<'a>>,
+    pub port: Option<u16>,
+    pub path: Cow<'a, str>,
+    pub query: Option<Cow<'a, str>>,
+    pub fragment: Option<Cow<'a, str>>,
+    /// Whether the URL embeds credentials (`user:pass@host`)
+    pub has_credentials: bool,
+    /// Whether the scheme gives access to more than a web resource (see `DANGEROUS_SCHEMES`)
+    pub dangerous_scheme: bool,
+}
+
+/// Schemes that reach local files or arbitrary network services, with the behaviour they imply.
+/// `file:` reads local files, `dict:` and `gopher:` talk to any TCP service (e.g. to forge
+/// requests to internal services).
+pub const DANGEROUS_SCHEMES: &[(&str, &str)] = &[
+    ("file", "FILE_READ"),
+    ("dict", "NET_COMS"),
+    ("gopher", "NET_COMS"),
+];
+
+/// Schemes recognized without `//` (`file:/etc/passwd`, `mailto:root@example.com`)
+const OPAQUE_SCHEMES: &[&str] = &["file", "mailto", "dict", "gopher", "data", "tel"];
+
+/// Type of the argument of an option, as given by `arg_type` in configs. Arguments that do not
+/// have the type are identified as if it was not given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ArgumentType {
+    /// Kept as is, even if it looks like something else
+    String,
+    Integer,
+    Float,
+    Port,
+    IpAddress,
+    /// A host name or address, or a network interface (`eth0`)
+    Hostname,
+    HostPort,
+    Cidr,
+    Email,
+    UserAtHost,
+    Url,
+    /// A file, even if its name does not look like one
+    LocalPath,
 }
 
-impl CLArgument {
+impl CLElement<'_> {
+    /// A copy of this element that does not borrow from the command-line
+    pub fn into_owned(self) -> CLElement<'static> {
+        match self {
+            CLElement::CLOption((name, argument)) => CLElement::CLOption((
+                Cow::Owned(name.into_owned()),
+                argument.map(CLArgument::into_owned),
+            )),
+            CLElement::CLBehaviouredOption((name, behaviours, argument)) => {
+                CLElement::CLBehaviouredOption((
+                    Cow::Owned(name.into_owned()),
+                    behaviours,
+                    argument.map(CLArgument::into_owned),
+                ))
+            }
+            CLElement::CLInherentBehaviour(behaviours) => {
+                CLElement::CLInherentBehaviour(behaviours)
+            }
+            CLElement::CLArgument(argument) => CLElement::CLArgument(argument.into_owned()),
+            CLElement::CLSep(separator) => CLElement::CLSep(separator),
+            CLElement::ParsingError(message) => CLElement::ParsingError(message),
+            CLElement::CLDoubleDash => CLElement::CLDoubleDash,
+            CLElement::CLBehaviouredArgument((argument, behaviours)) => {
+                CLElement::CLBehaviouredArgument((argument.into_owned(), behaviours))
+            }
+            CLElement::CLHeuristic(confidence) => CLElement::CLHeuristic(confidence),
+            CLElement::CLEnvAssignment(assignment) => {
+                CLElement::CLEnvAssignment(assignment.into_owned())
+            }
+        }
+    }
+
+    /// Resolve the local paths of this element in `context` (see `LocalPath::resolve`)
+    pub fn resolve_paths(&mut self, context: &PathContext) {
+        let argument = match self {
+            CLElement::CLOption((_, argument))
+            | CLElement::CLBehaviouredOption((_, _, argument)) => argument.as_mut(),
+            CLElement::CLArgument(argument) | CLElement::CLBehaviouredArgument((argument, _)) => {
+                Some(argument)
+            }
+            CLElement::CLEnvAssignment(assignment) => Some(&mut assignment.value),
+            _ => None,
+        };
+        if let Some(CLArgument::LocalPath(path)) = argument {
+            path.resolve(context);
+        }
+    }
+}
+
+impl<'a> CLArgument<'a> {
     pub fn identify_type(&mut self) {
-        static REMOTE_PATH_REGEX: Lazy<Regex> = Lazy::new(|| {
-            Regex::new(
-                r"(([a-zA-Z]+[a-zA-Z0-9]*)@)?(([a-zA-Z0-9]+)(\\.([a-zA-Z0-9]+))*):(\/?)([a-zA-Z]|\\/)+",
-            )
-            .unwrap()
-        });
-        //FIXME Very approximate regex. Matches absolute paths, some relative paths (with either ../ or ./ at the beginning) as well as "path-less" filenames with a few file extensions
-        static LOCAL_PATH_REGEX: Lazy<Regex> = Lazy::new(|| {
-            Regex::new(r"(((\.|\.\.)?\/)([a-zA-Z]+[a-zA-Z0-9]*)(\/([a-zA-Z]+[a-zA-Z0-9]*))*)|(([a-zA-Z]+[a-zA-Z0-9]*)\.((tar)|(tar\.gz)|(tar\.bz2)|(png)|(sh)))$")
-                .unwrap()
-        });
-
-        static URL_REGEX: Lazy<Regex> = Lazy::new(|| {
-            Regex::new(r"https?:\/\/([a-z][a-z0-9-]*\.)?([a-z][a-z0-9-]*\.)([a-z][a-z0-9-]*)(\/([a-zA-Z0-9-.]*))*")
-                .unwrap()
-        });
-
-        static IPV4_REGEX: Lazy<Regex> = Lazy::new(|| {
-            Regex::new(r"^((25[0-5]|(2[0-4]|1\d|[1-9]|)\d)\.?\b){4}(:[0-9]{1,5})?$").unwrap() // From https://stackoverflow.com/a/36760050
-        });
-
-        static IPV6_REGEX: Lazy<Regex> = Lazy::new(|| {
-            Regex::new(r"^(([0-9a-fA-F]{1,4}:){7,7}[0-9a-fA-F]{1,4}|([0-9a-fA-F]{1,4}:){1,7}:|([0-9a-fA-F]{1,4}:){1,6}:[0-9a-fA-F]{1,4}|([0-9a-fA-F]{1,4}:){1,5}(:[0-9a-fA-F]{1,4}){1,2}|([0-9a-fA-F]{1,4}:){1,4}(:[0-9a-fA-F]{1,4}){1,3}|([0-9a-fA-F]{1,4}:){1,3}(:[0-9a-fA-F]{1,4}){1,4}|([0-9a-fA-F]{1,4}:){1,2}(:[0-9a-fA-F]{1,4}){1,5}|[0-9a-fA-F]{1,4}:((:[0-9a-fA-F]{1,4}){1,6})|:((:[0-9a-fA-F]{1,4}){1,7}|:)|fe80:(:[0-9a-fA-F]{0,4}){0,4}%[0-9a-zA-Z]{1,}|::(ffff(:0{1,4}){0,1}:){0,1}((25[0-5]|(2[0-4]|1{0,1}[0-9]){0,1}[0-9])\.){3,3}(25[0-5]|(2[0-4]|1{0,1}[0-9]){0,1}[0-9])|([0-9a-fA-F]{1,4}:){1,4}:((25[0-5]|(2[0-4]|1{0,1}[0-9]){0,1}[0-9])\.){3,3}(25[0-5]|(2[0-4]|1{0,1}[0-9]){0,1}[0-9]))(:[0-9]{1,5})?$")
-                .unwrap() //From https://stackoverflow.com/a/17871737
-        });
+        if let CLArgument::String(str_val) = self {
+            *self = identify(std::mem::take(str_val));
+        }
+    }
 
+    /// A copy of this argument that does not borrow from the command-line
+    pub fn into_owned(self) -> CLArgument<'static> {
+        let owned = |value: Cow<'_, str>| Cow::Owned(value.into_owned());
         match self {
-            CLArgument::String(str_val) => {
-                //Check if the string is a valid IP address
-                if let Ok(ip) = str_val.parse::<std::net::IpAddr>() {
-                    *self = CLArgument::IPAddress(ip.to_string());
-                } else if let Ok(num) = str_val.parse::<u16>() {
-                    *self = CLArgument::U16(num);
-                } else if let Ok(num) = str_val.parse::<i64>() {
-                    *self = CLArgument::Integer(num);
-                } else if let Ok(num) = str_val.parse::<f32>() {
-                    *self = CLArgument::Float(num);
-                } else if let Ok(bool) = str_val.parse::<bool>() {
-                    *self = CLArgument::Boolean(bool);
-                } else if REMOTE_PATH_REGEX.is_match(str_val) {
-                    *self = CLArgument::RemotePath(str_val.clone());
-                } else if LOCAL_PATH_REGEX.is_match(str_val) {
-                    *self = CLArgument::LocalPath(str_val.clone());
-                } else if URL_REGEX.is_match(str_val) {
-                    *self = CLArgument::URL(str_val.clone());
-                } else if IPV4_REGEX.is_match(str_val) || IPV6_REGEX.is_match(str_val) {
-                    *self = CLArgument::IPAddress(str_val.clone());
-                } else {
-                    *self = CLArgument::String(str_val.clone());
+            CLArgument::String(value) => CLArgument::String(owned(value)),
+            CLArgument::Integer(value) => CLArgument::Integer(value),
+            CLArgument::Float(value) => CLArgument::Float(value),
+            CLArgument::Boolean(value) => CLArgument::Boolean(value),
+            CLArgument::IPv4Address(address) => CLArgument::IPv4Address(address),
+            CLArgument::IPv6Address(address) => CLArgument::IPv6Address(address),
+            CLArgument::Hostname(name) => CLArgument::Hostname(owned(name)),
+            CLArgument::HostPort { host, port } => CLArgument::HostPort {
+                host: host.into_owned(),
+                port,
+            },
+            CLArgument::Cidr {
+                address,
+                prefix_length,
+            } => CLArgument::Cidr {
+                address,
+                prefix_length,
+            },
+            CLArgument::Port(port) => CLArgument::Port(port),
+            CLArgument::Email { user, domain } => CLArgument::Email {
+                user: owned(user),
+                domain: owned(domain),
+            },
+            CLArgument::UserAtHost { user, host } => CLArgument::UserAtHost {
+                user: owned(user),
+                host: host.into_owned(),
+            },
+            CLArgument::RemotePath(value) => CLArgument::RemotePath(owned(value)),
+            CLArgument::LocalPath(path) => CLArgument::LocalPath(path.into_owned()),
+            CLArgument::URL(url) => CLArgument::URL(url.into_owned()),
+        }
+    }
+
+    /// Behaviours the argument implies whatever the option or program it is given to, those of
+    /// dangerous URL schemes (`curl file:///etc/shadow` reads a file)
+    pub fn implied_behaviours(&self) -> BehaviourSet {
+        match self {
+            CLArgument::URL(url) if url.dangerous_scheme => url.scheme_behaviours(),
+            _ => BehaviourSet::EMPTY,
+        }
+    }
+
+    /// The argument of type `String` holding `value`, identified (see `identify_type`)
+    pub fn identified(value: impl Into<Cow<'a, str>>) -> CLArgument<'a> {
+        let mut argument = CLArgument::String(value.into());
+        argument.identify_type();
+        argument
+    }
+
+    /// The argument holding `value`, of type `arg_type` if it has it, else identified
+    pub fn typed(value: impl Into<Cow<'a, str>>, arg_type: Option<ArgumentType>) -> CLArgument<'a> {
+        let value = value.into();
+        match arg_type.and_then(|arg_type| as_type(&value, arg_type)) {
+            Some(argument) => argument,
+            None => identify(value),
+        }
+    }
+}
+
+impl<'a> Url<'a> {
+    /// Split `value` into the parts of a URL, if it is one: a scheme followed by `//`, or by `:`
+    /// for the schemes that do without an authority
+    pub fn parse(value: &Cow<'a, str>) -> Option<Url<'a>> {
+        let colon = value.find(':')?;
+        let scheme = &value[..colon];
+        let mut scheme_chars = scheme.chars();
+        if !scheme_chars.next()?.is_ascii_alphabetic()
+            || !scheme_chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))
+        {
+            return None;
+        }
+
+        let mut start = colon + 1;
+        let (mut user, mut password, mut host, mut port) = (None, None, None, None);
+        if value[start..].starts_with("//") {
+            start += 2;
+            let end = value[start..]
+                .find(['/', '?', '#'])
+                .map_or(value.len(), |end| start + end);
+            // `user:pass@host:port`
+            let mut host_start = start;
+            if let Some(at) = value[start..end].rfind('@') {
+                let userinfo_end = start + at;
+                match value[start..userinfo_end].find(':') {
+                    Some(colon) => {
+                        user = Some(slice(value, start, start + colon));
+                        password = Some(slice(value, start + colon + 1, userinfo_end));
+                    }
+                    None => user = Some(slice(value, start, userinfo_end)),
+                }
+                host_start = userinfo_end + 1;
+            }
+            let mut host_end = end;
+            let port_colon = match value[host_start..end].rfind(']') {
+                Some(bracket) => value[host_start + bracket..end]
+                    .find(':')
+                    .map(|colon| host_start + bracket + colon),
+                None => value[host_start..end]
+                    .rfind(':')
+                    .map(|colon| host_start + colon),
+            };
+            if let Some(port_colon) = port_colon {
+                let port_str = &value[port_colon + 1..end];
+                if !port_str.is_empty() {
+                    port = Some(port_str.parse().ok()?);
                 }
+                host_end = port_colon;
+            }
+            if host_start < host_end {
+                host = Some(self::host(value, host_start, host_end, is_url_host)?);
             }
-            _ => {}
+            start = end;
+        } else if !OPAQUE_SCHEMES
+            .iter()
+            .any(|opaque| scheme.eq_ignore_ascii_case(opaque))
+        {
+            return None;
+        }
+
+        let fragment_start = value[start..].find('#').map(|hash| start + hash);
+        let path_end = fragment_start.unwrap_or(value.len());
+        let query_start = value[start..path_end].find('?').map(|mark| start + mark);
+        let dangerous_scheme = scheme_behaviour(scheme).is_some();
+        Some(Url {
+            url: value.clone(),
+            scheme: slice(value, 0, colon),
+            has_credentials: user.is_some(),
+            user,
+            password,
+            host,
+            port,
+            path: slice(value, start, query_start.unwrap_or(path_end)),
+            query: query_start.map(|query_start| slice(value, query_start + 1, path_end)),
+            fragment: fragment_start
+                .map(|fragment_start| slice(value, fragment_start + 1, value.len())),
+            dangerous_scheme,
+        })
+    }
+
+    /// Behaviours implied by the scheme of the URL, for dangerous schemes only
+    pub fn scheme_behaviours(&self) -> BehaviourSet {
+        // A custom taxonomy may not have the behaviour
+        match scheme_behaviour(&self.scheme).and_then(Behaviour::from_name) {
+            Some(behaviour) => taxonomy::intern_behaviours(&[behaviour]),
+            None => BehaviourSet::EMPTY,
+        }
+    }
+
+    pub fn into_owned(self) -> Url<'static> {
+        let owned = |value: Cow<'_, str>| Cow::Owned(value.into_owned());
+        Url {
+            url: owned(self.url),
+            scheme: owned(self.scheme),
+            user: self.user.map(owned),
+            password: self.password.map(owned),
+            host: self.host.map(Host::into_owned),
+            port: self.port,
+            path: owned(self.path),
+            query: self.query.map(owned),
+            fragment: self.fragment.map(owned),
+            has_credentials: self.has_credentials,
+            dangerous_scheme: self.dangerous_scheme,
+        }
+    }
+}
+
+/// Name of the behaviour implied by a dangerous scheme
+fn scheme_behaviour(scheme: &str) -> Option<&'static str> {
+    DANGEROUS_SCHEMES
+        .iter()
+        .find(|(dangerous, _)| scheme.eq_ignore_ascii_case(dangerous))
+        .map(|(_, behaviour)| *behaviour)
+}
+
+impl Host<'_> {
+    pub fn into_owned(self) -> Host<'static> {
+        match self {
+            Host::IPv4(address) => Host::IPv4(address),
+            Host::IPv6(address) => Host::IPv6(address),
+            Host::Name(name) => Host::Name(Cow::Owned(name.into_owned())),
+        }
+    }
+}
+
+/// Top-level domains of the names taken for host names without a hint. Country codes are left out,
+/// as many are also file extensions (`.sh`, `.py`, `.rs`...), and so are the generic domains that
+/// are (`README.org`, `notes.info`, `Safari.app`, `app.dev`, `backup.local`).
+const HOSTNAME_TLDS: &[&str] = &[
+    "com",
+    "net",
+    "io",
+    "edu",
+    "gov",
+    "mil",
+    "int",
+    "biz",
+    "cloud",
+    "localdomain",
+    "internal",
+    "lan",
+    "corp",
+    "onion",
+];
+
+/// Top-level domains of private networks, whose `user@host` are accounts rather than mail addresses
+const PRIVATE_TLDS: &[&str] = &["localdomain", "internal", "lan", "home", "corp", "local"];
+
+/// Users of `user@host` that are accounts on the host rather than mailboxes (`git@github.com`)
+const SYSTEM_ACCOUNTS: &[&str] = &[
+    "root", "admin", "git", "hg", "svn", "ubuntu", "debian", "centos", "fedora", "ec2-user", "pi",
+    "vagrant", "deploy", "www-data", "postgres", "oracle",
+];
+
+/// The type of `value`, guessed from its contents alone
+fn identify(value: Cow<'_, str>) -> CLArgument<'_> {
+    static REMOTE_PATH_REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(
+            r"(([a-zA-Z]+[a-zA-Z0-9]*)@)?(([a-zA-Z0-9]+)(\\.([a-zA-Z0-9]+))*):(\/?)([a-zA-Z]|\\/)+",
+        )
+        .unwrap()
+    });
+
+    if let Ok(address) = value.parse::<IpAddr>() {
+        ip_address(address)
+    } else if let Ok(num) = value.parse::<i64>() {
+        CLArgument::Integer(num)
+    } else if let Ok(num) = value.parse::<f32>() {
+        CLArgument::Float(num)
+    } else if let Ok(bool) = value.parse::<bool>() {
+        CLArgument::Boolean(bool)
+    } else if let Some(url) = Url::parse(&value) {
+        CLArgument::URL(url)
+    } else if let Some(cidr) = as_type(&value, ArgumentType::Cidr) {
+        cidr
+    } else if let Some((host, port)) = host_port(&value, is_hostname) {
+        CLArgument::HostPort { host, port }
+    } else if let Some((user, domain)) = user_at_host(&value, is_hostname) {
+        // Mail addresses and accounts look alike: addresses at public domains are taken for mail
+        // addresses, unless their user is a system account
+        match domain {
+            Host::Name(domain) if is_mail_address(&user, &domain) => {
+                CLArgument::Email { user, domain }
+            }
+            host => CLArgument::UserAtHost { user, host },
+        }
+    } else if REMOTE_PATH_REGEX.is_match(&value) {
+        CLArgument::RemotePath(value)
+    } else if is_known_hostname(&value) {
+        CLArgument::Hostname(value)
+    } else if is_local_path(&value) {
+        CLArgument::LocalPath(LocalPath::new(value))
+    } else {
+        CLArgument::String(value)
+    }
+}
+
+/// `value` as an argument of type `arg_type`, if it has the syntax of that type
+fn as_type<'a>(value: &Cow<'a, str>, arg_type: ArgumentType) -> Option<CLArgument<'a>> {
+    match arg_type {
+        ArgumentType::String => Some(CLArgument::String(value.clone())),
+        ArgumentType::Integer => value.parse().ok().map(CLArgument::Integer),
+        ArgumentType::Float => value.parse().ok().map(CLArgument::Float),
+        ArgumentType::Port => value.parse().ok().map(CLArgument::Port),
+        ArgumentType::IpAddress => value.parse().ok().map(ip_address),
+        ArgumentType::Hostname => match value.parse() {
+            Ok(address) => Some(ip_address(address)),
+            Err(_) => is_hostname(value).then(|| CLArgument::Hostname(value.clone())),
+        },
+        ArgumentType::HostPort => {
+            host_port(value, is_hostname).map(|(host, port)| CLArgument::HostPort { host, port })
+        }
+        ArgumentType::Cidr => {
+            let (address, prefix_length) = value.split_once('/')?;
+            let address: IpAddr = address.parse().ok()?;
+            let prefix_length: u8 = prefix_length.parse().ok()?;
+            let max_length = if address.is_ipv4() { 32 } else { 128 };
+            (prefix_length <= max_length).then_some(CLArgument::Cidr {
+                address,
+                prefix_length,
+            })
+        }
+        ArgumentType::Email => {
+            let (user, domain) = user_at_host(value, is_hostname)?;
+            match domain {
+                Host::Name(domain) => Some(CLArgument::Email { user, domain }),
+                _ => None,
+            }
+        }
+        ArgumentType::UserAtHost => user_at_host(value, is_hostname)
+            .map(|(user, host)| CLArgument::UserAtHost { user, host }),
+        ArgumentType::Url => Url::parse(value).map(CLArgument::URL),
+        ArgumentType::LocalPath => Some(CLArgument::LocalPath(LocalPath::new(value.clone()))),
+    }
+}
+
+fn ip_address(address: IpAddr) -> CLArgument<'static> {
+    match address {
+        IpAddr::V4(address) => CLArgument::IPv4Address(address),
+        IpAddr::V6(address) => CLArgument::IPv6Address(address),
+    }
+}
+
+/// `value[start..end]`, borrowed if `value` is
+fn slice<'a>(value: &Cow<'a, str>, start: usize, end: usize) -> Cow<'a, str> {
+    match value {
+        Cow::Borrowed(value) => Cow::Borrowed(&value[start..end]),
+        Cow::Owned(value) => Cow::Owned(value[start..end].to_string()),
+    }
+}
+
+/// The host of `value[start..end]`: an address, or a name accepted by `is_name`
+fn host<'a>(
+    value: &Cow<'a, str>,
+    start: usize,
+    end: usize,
+    is_name: fn(&str) -> bool,
+) -> Option<Host<'a>> {
+    let host = &value[start..end];
+    if let Some(address) = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+    {
+        return address.parse().ok().map(Host::IPv6);
+    }
+    match host.parse() {
+        Ok(IpAddr::V4(address)) => Some(Host::IPv4(address)),
+        // IPv6 addresses are written in brackets when followed by a port
+        Ok(IpAddr::V6(_)) => None,
+        Err(_) => is_name(host).then(|| Host::Name(slice(value, start, end))),
+    }
+}
+
+/// `host:port`
+fn host_port<'a>(value: &Cow<'a, str>, is_name: fn(&str) -> bool) -> Option<(Host<'a>, u16)> {
+    let colon = value.rfind(':')?;
+    let port = &value[colon + 1..];
+    if port.is_empty() || !port.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((host(value, 0, colon, is_name)?, port.parse().ok()?))
+}
+
+/// `user@host`
+fn user_at_host<'a>(
+    value: &Cow<'a, str>,
+    is_name: fn(&str) -> bool,
+) -> Option<(Cow<'a, str>, Host<'a>)> {
+    let at = value.find('@')?;
+    let user = &value[..at];
+    if user.is_empty()
+        || !user
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '+'))
+    {
+        return None;
+    }
+    Some((
+        slice(value, 0, at),
+        host(value, at + 1, value.len(), is_name)?,
+    ))
+}
+
+/// Whether `name` can be the host of a URL, which is more lenient than host names (`my_host`,
+/// percent-encoded names...)
+fn is_url_host(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-._~%!$&'()*+,;=".contains(c))
+}
+
+/// Whether `name` is a syntactically valid host name (`eth0` and `localhost` included)
+fn is_hostname(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+}
+
+/// Whether `name` is a host name even without a hint: `localhost`, or a name under a
+/// well-known top-level domain
+fn is_known_hostname(name: &str) -> bool {
+    name == "localhost"
+        || (is_hostname(name)
+            && name.rsplit_once('.').is_some_and(|(_, tld)| {
+                HOSTNAME_TLDS
+                    .iter()
+                    .any(|known| tld.eq_ignore_ascii_case(known))
+            }))
+}
+
+/// Whether `user@domain` is more likely a mail address than an account on a host: `domain` is
+/// public (`example.com`, not `db` or `db.lan`) and `user` is not a system account
+fn is_mail_address(user: &str, domain: &str) -> bool {
+    let is_public = domain.rsplit_once('.').is_some_and(|(_, tld)| {
+        !PRIVATE_TLDS
+            .iter()
+            .any(|private| tld.eq_ignore_ascii_case(private))
+    });
+    is_public && !SYSTEM_ACCOUNTS.contains(&user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(value: &str) -> Url<'_> {
+        Url::parse(&Cow::Borrowed(value)).unwrap()
+    }
+
+    fn implied_behaviours(value: &str) -> Vec<&'static str> {
+        CLArgument::identified(value)
+            .implied_behaviours()
+            .as_slice()
+            .iter()
+            .map(|behaviour| behaviour.name())
+            .collect()
+    }
+
+    #[test]
+    fn url_parts() {
+        let url = url("ftp://user:pw@[::1]:21/x?q#f");
+        assert_eq!(url.scheme, "ftp");
+        assert_eq!(url.user.as_deref(), Some("user"));
+        assert_eq!(url.password.as_deref(), Some("pw"));
+        assert_eq!(url.host, Some(Host::IPv6(Ipv6Addr::LOCALHOST)));
+        assert_eq!(url.port, Some(21));
+        assert_eq!(url.path, "/x");
+        assert_eq!(url.query.as_deref(), Some("q"));
+        assert_eq!(url.fragment.as_deref(), Some("f"));
+        assert!(url.has_credentials);
+        assert!(!url.dangerous_scheme);
+    }
+
+    #[test]
+    fn file_urls_read_files() {
+        let with_authority = url("file:///etc/shadow");
+        assert_eq!(with_authority.host, None);
+        assert_eq!(with_authority.path, "/etc/shadow");
+        assert!(with_authority.dangerous_scheme);
+        assert_eq!(implied_behaviours("file:///etc/shadow"), ["FILE_READ"]);
+
+        let opaque = url("file:/etc/passwd");
+        assert_eq!(opaque.host, None);
+        assert_eq!(opaque.path, "/etc/passwd");
+        assert_eq!(implied_behaviours("file:/etc/passwd"), ["FILE_READ"]);
+    }
+
+    #[test]
+    fn gopher_urls_reach_any_service() {
+        let url = url("gopher://h:70/_x");
+        assert_eq!(url.host, Some(Host::Name(Cow::Borrowed("h"))));
+        assert_eq!(url.port, Some(70));
+        assert_eq!(url.path, "/_x");
+        assert_eq!(implied_behaviours("gopher://h:70/_x"), ["NET_COMS"]);
+    }
+
+    #[test]
+    fn out_of_range_ports_are_not_urls() {
+        assert!(Url::parse(&Cow::Borrowed("http://example.com:65536/")).is_none());
+        assert!(!matches!(
+            CLArgument::identified("http://example.com:65536/"),
+            CLArgument::URL(_)
+        ));
+        assert_eq!(url("http://example.com:65535/").port, Some(65535));
+    }
+
+    #[test]
+    fn host_port_is_not_a_url() {
+        assert!(Url::parse(&Cow::Borrowed("proxy:8080")).is_none());
+        assert!(matches!(
+            CLArgument::identified("proxy:8080"),
+            CLArgument::HostPort {
+                host: Host::Name(host),
+                port: 8080,
+            } if host == "proxy"
+        ));
+        assert!(implied_behaviours("proxy:8080").is_empty());
+    }
+
+    #[test]
+    fn mail_addresses_and_accounts() {
+        for value in [
+            "alice@example.com",
+            "john@corp.io",
+            "John.Doe@mail.example.fr",
+        ] {
+            assert!(
+                matches!(CLArgument::identified(value), CLArgument::Email { .. }),
+                "{value}"
+            );
+        }
+        for value in [
+            "git@github.com",
+            "root@example.com",
+            "alice@db",
+            "alice@db.lan",
+            "alice@10.0.0.1",
+            "alice@[::1]",
+        ] {
+            assert!(
+                matches!(CLArgument::identified(value), CLArgument::UserAtHost { .. }),
+                "{value}"
+            );
+        }
+    }
+
+    #[test]
+    fn file_names_are_not_host_names() {
+        for value in ["README.org", "foo.info", "notes.info"] {
+            assert!(
+                matches!(CLArgument::identified(value), CLArgument::LocalPath(_)),
+                "{value}"
+            );
+        }
+        for value in ["app.dev", "Safari.app", "backup.local"] {
+            assert!(
+                !matches!(CLArgument::identified(value), CLArgument::Hostname(_)),
+                "{value}"
+            );
+        }
+        for value in ["example.com", "www.example.io", "localhost", "db.internal"] {
+            assert!(
+                matches!(CLArgument::identified(value), CLArgument::Hostname(_)),
+                "{value}"
+            );
         }
+        // Hosts under the other domains need a hint
+        assert!(matches!(
+            as_type(&Cow::Borrowed("example.org"), ArgumentType::Hostname),
+            Some(CLArgument::Hostname(_))
+        ));
     }
 }
@@ -0,0 +1,749 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Tells local paths apart from other arguments, and tags them with the categories of sensitive
+//! locations they point to. Given the context of the command-line (`PathContext`), relative and
+//! home paths are resolved to the locations they stand for.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// A kind of location worth noticing in a command-line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathCategory {
+    /// Keys, password stores and files holding secrets (`~/.ssh/id_rsa`, `/etc/shadow`, `*.pem`)
+    Credentials,
+    /// System-wide configuration (`/etc/`, `/boot/`)
+    SystemConfig,
+    /// Configuration of a user (dotfiles of home directories)
+    UserConfig,
+    /// Kernel and process information (`/proc/`, `/sys/`)
+    Kernel,
+    /// System logs (`/var/log/`)
+    Logs,
+    /// World-writable temporary directories (`/tmp/`, `/dev/shm/`)
+    Temp,
+    /// Device files (`/dev/sda`)
+    Device,
+    /// Bash's `/dev/tcp/host/port` and `/dev/udp/host/port` pseudo-devices, which open connections
+    NetworkDevice,
+    /// Locations run automatically: cron tables, systemd units, init and shell startup scripts
+    Persistence,
+}
+
+impl PathCategory {
+    /// Name of the category, as serialized
+    pub fn name(self) -> &'static str {
+        match self {
+            PathCategory::Credentials => "credentials",
+            PathCategory::SystemConfig => "system_config",
+            PathCategory::UserConfig => "user_config",
+            PathCategory::Kernel => "kernel",
+            PathCategory::Logs => "logs",
+            PathCategory::Temp => "temp",
+            PathCategory::Device => "device",
+            PathCategory::NetworkDevice => "network_device",
+            PathCategory::Persistence => "persistence",
+        }
+    }
+
+    /// Whether reading or writing such a location is noteworthy in itself
+    pub fn is_sensitive(self) -> bool {
+        self != PathCategory::Temp
+    }
+}
+
+/// How a rule matches a path
+enum Pattern {
+    /// The path is under this directory, or is this file
+    Prefix(&'static str),
+    /// The path, relative to the home directory it is in (`~/`, `/root/`, `/home/user/`), is
+    /// under this directory or is this file
+    Home(&'static str),
+    /// The file has this name
+    Name(&'static str),
+    /// The file name has this extension
+    Extension(&'static str),
+}
+
+use Pattern::{Extension, Home, Name, Prefix};
+
+/// Categories of the locations, a path getting those of every rule it matches
+const PATH_RULES: &[(Pattern, PathCategory)] = &[
+    (Prefix("/etc/shadow"), PathCategory::Credentials),
+    (Prefix("/etc/gshadow"), PathCategory::Credentials),
+    (Prefix("/etc/sudoers"), PathCategory::Credentials),
+    (Prefix("/etc/ssh/ssh_host_"), PathCategory::Credentials),
+    (Prefix("/etc/ssl/private/"), PathCategory::Credentials),
+    (Home(".ssh/"), PathCategory::Credentials),
+    (Home(".gnupg/"), PathCategory::Credentials),
+    (Home(".aws/"), PathCategory::Credentials),
+    (Home(".kube/config"), PathCategory::Credentials),
+    (Home(".docker/config.json"), PathCategory::Credentials),
+    (Home(".netrc"), PathCategory::Credentials),
+    (Home(".pgpass"), PathCategory::Credentials),
+    (Home(".git-credentials"), PathCategory::Credentials),
+    (Home(".password-store/"), PathCategory::Credentials),
+    (Name("id_rsa"), PathCategory::Credentials),
+    (Name("id_dsa"), PathCategory::Credentials),
+    (Name("id_ecdsa"), PathCategory::Credentials),
+    (Name("id_ed25519"), PathCategory::Credentials),
+    (Name("authorized_keys"), PathCategory::Credentials),
+    (Name(".env"), PathCategory::Credentials),
+    (Name(".htpasswd"), PathCategory::Credentials),
+    (Extension("pem"), PathCategory::Credentials),
+    (Extension("key"), PathCategory::Credentials),
+    (Extension("p12"), PathCategory::Credentials),
+    (Extension("pfx"), PathCategory::Credentials),
+    (Extension("jks"), PathCategory::Credentials),
+    (Extension("kdbx"), PathCategory::Credentials),
+    (Extension("gpg"), PathCategory::Credentials),
+    (Prefix("/etc/"), PathCategory::SystemConfig),
+    (Prefix("/boot/"), PathCategory::SystemConfig),
+    (Home("."), PathCategory::UserConfig),
+    (Prefix("/proc/"), PathCategory::Kernel),
+    (Prefix("/sys/"), PathCategory::Kernel),
+    (Prefix("/var/log/"), PathCategory::Logs),
+    (Prefix("/tmp/"), PathCategory::Temp),
+    (Prefix("/var/tmp/"), PathCategory::Temp),
+    (Prefix("/dev/shm/"), PathCategory::Temp),
+    (Prefix("/dev/tcp/"), PathCategory::NetworkDevice),
+    (Prefix("/dev/udp/"), PathCategory::NetworkDevice),
+    (Prefix("/dev/"), PathCategory::Device),
+    (Prefix("/etc/cron"), PathCategory::Persistence),
+    (Prefix("/etc/anacrontab"), PathCategory::Persistence),
+    (Prefix("/var/spool/cron/"), PathCategory::Persistence),
+    (Prefix("/etc/systemd/"), PathCategory::Persistence),
+    (Prefix("/lib/systemd/"), PathCategory::Persistence),
+    (Prefix("/usr/lib/systemd/"), PathCategory::Persistence),
+    (Prefix("/etc/init.d/"), PathCategory::Persistence),
+    (Prefix("/etc/rc.local"), PathCategory::Persistence),
+    (Prefix("/etc/profile"), PathCategory::Persistence),
+    (Prefix("/etc/bash.bashrc"), PathCategory::Persistence),
+    (Prefix("/etc/ld.so.preload"), PathCategory::Persistence),
+    (Home(".config/systemd/"), PathCategory::Persistence),
+    (Home(".config/autostart/"), PathCategory::Persistence),
+    (Home(".bashrc"), PathCategory::Persistence),
+    (Home(".bash_profile"), PathCategory::Persistence),
+    (Home(".profile"), PathCategory::Persistence),
+    (Home(".zshrc"), PathCategory::Persistence),
+    (Extension("service"), PathCategory::Persistence),
+    (Extension("timer"), PathCategory::Persistence),
+];
+
+/// Extensions of the files taken for local paths even without a directory (`foo.py`). Top-level
+/// domains that are also extensions (`.com`) are left out, `example.com` being a host, unless
+/// they are mostly extensions (`.org`, `.info`).
+const FILE_EXTENSIONS: &[&str] = &[
+    // Archives and packages
+    "tar",
+    "gz",
+    "tgz",
+    "bz2",
+    "xz",
+    "zst",
+    "lz",
+    "lzma",
+    "zip",
+    "7z",
+    "rar",
+    "cpio",
+    "deb",
+    "rpm",
+    "apk",
+    "jar",
+    "war",
+    "whl",
+    "iso",
+    "img",
+    "dmg",
+    // Code and scripts
+    "sh",
+    "bash",
+    "zsh",
+    "py",
+    "pyc",
+    "rb",
+    "pl",
+    "pm",
+    "php",
+    "js",
+    "mjs",
+    "ts",
+    "lua",
+    "go",
+    "rs",
+    "c",
+    "h",
+    "cc",
+    "cpp",
+    "hpp",
+    "java",
+    "class",
+    "cs",
+    "ps1",
+    "bat",
+    "cmd",
+    "vbs",
+    "awk",
+    "sed",
+    // Binaries
+    "so",
+    "o",
+    "a",
+    "ko",
+    "bin",
+    "elf",
+    "exe",
+    "dll",
+    "out",
+    // Configuration and data
+    "conf",
+    "cfg",
+    "ini",
+    "toml",
+    "yaml",
+    "yml",
+    "json",
+    "xml",
+    "env",
+    "properties",
+    "csv",
+    "tsv",
+    "db",
+    "sqlite",
+    "sqlite3",
+    "sql",
+    "log",
+    "pid",
+    "lock",
+    "sock",
+    "bak",
+    "old",
+    "orig",
+    "tmp",
+    "swp",
+    "service",
+    "timer",
+    "socket",
+    "rules",
+    "list",
+    // Documents and media
+    "txt",
+    "org",
+    "info",
+    "md",
+    "rst",
+    "html",
+    "htm",
+    "css",
+    "pdf",
+    "doc",
+    "docx",
+    "xls",
+    "xlsx",
+    "odt",
+    "png",
+    "jpg",
+    "jpeg",
+    "gif",
+    "bmp",
+    "svg",
+    "ico",
+    "webp",
+    "mp3",
+    "mp4",
+    "wav",
+    "avi",
+    "mkv",
+    // Keys and certificates
+    "pem",
+    "key",
+    "crt",
+    "cer",
+    "der",
+    "csr",
+    "pub",
+    "p12",
+    "pfx",
+    "jks",
+    "kdbx",
+    "gpg",
+    "asc",
+    "sig",
+];
+
+/// Whether `value` looks like a local path: absolute, relative to the current (`./`, `../`) or a
+/// home (`~/`) directory, with a directory (`dir/file`), a dotfile (`.bashrc`), a glob (`*.log`),
+/// or a file name with a known extension (`foo.py`, `x.tar.xz`) or of a sensitive file (`id_rsa`)
+pub fn is_local_path(value: &str) -> bool {
+    if value.is_empty() || value.contains('\0') {
+        return false;
+    }
+    if matches!(value, "." | ".." | "~")
+        || value.starts_with('/')
+        || value.starts_with("./")
+        || value.starts_with("../")
+        || (value.starts_with('~') && home_relative(value).is_some())
+    {
+        return true;
+    }
+    // Other strings with spaces are more likely to be messages than file names
+    if value.contains(char::is_whitespace) || value.contains("://") {
+        return false;
+    }
+    (value.contains('/') && !is_mime_type(value))
+        || is_glob(value)
+        || is_known_file_name(value)
+        || (file_name(value).starts_with('.') && file_name(value).len() > 1)
+        || extension(value).is_some_and(|extension| {
+            FILE_EXTENSIONS
+                .iter()
+                .any(|known| extension.eq_ignore_ascii_case(known))
+        })
+}
+
+/// Top-level MIME types, for media types (`curl -H` and `--data-binary` values) not to be taken
+/// for relative paths
+const MIME_TOP_LEVEL_TYPES: &[&str] = &[
+    "application",
+    "audio",
+    "font",
+    "image",
+    "message",
+    "model",
+    "multipart",
+    "text",
+    "video",
+];
+
+/// Whether `value` is a bare media type (`application/json`, `text/plain;charset=utf-8`), rather
+/// than a file of a directory named after one (`image/logo.png`)
+fn is_mime_type(value: &str) -> bool {
+    let media_type = value.split(';').next().unwrap_or(value);
+    let Some((top_level, subtype)) = media_type.split_once('/') else {
+        return false;
+    };
+    MIME_TOP_LEVEL_TYPES
+        .iter()
+        .any(|known| top_level.eq_ignore_ascii_case(known))
+        && !subtype.is_empty()
+        && subtype
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_'))
+        && !extension(subtype).is_some_and(|extension| {
+            FILE_EXTENSIONS
+                .iter()
+                .any(|known| extension.eq_ignore_ascii_case(known))
+        })
+}
+
+/// Whether `value` is the name of a file of the rules (`id_rsa`, `authorized_keys`)
+fn is_known_file_name(value: &str) -> bool {
+    PATH_RULES
+        .iter()
+        .any(|(pattern, _)| matches!(pattern, Name(name) if *name == value))
+}
+
+/// Whether `path` has glob characters (`*`, `?`, `[...]`)
+pub fn is_glob(path: &str) -> bool {
+    path.contains(['*', '?']) || (path.contains('[') && path.contains(']'))
+}
+
+/// The categories of the locations `path` points to, without duplicates
+pub fn classify(path: &str) -> Vec<PathCategory> {
+    classify_in_home(path, None)
+}
+
+/// `classify`, `home` being a home directory besides the usual ones
+fn classify_in_home(path: &str, home: Option<&str>) -> Vec<PathCategory> {
+    let mut categories = vec![];
+    let home_path = home
+        .and_then(|home| {
+            path.strip_prefix(home.trim_end_matches('/'))?
+                .strip_prefix('/')
+        })
+        .or_else(|| home_relative(path));
+    let name = file_name(path);
+    let path_extension = extension(path);
+    for (pattern, category) in PATH_RULES {
+        let matches = match pattern {
+            Prefix(prefix) => is_under(path, prefix),
+            Home(prefix) => home_path.is_some_and(|home_path| is_under(home_path, prefix)),
+            Name(file_name) => name == *file_name,
+            Extension(known) => path_extension.is_some_and(|ext| ext.eq_ignore_ascii_case(known)),
+        };
+        if matches && !categories.contains(category) {
+            categories.push(*category);
+        }
+    }
+    categories
+}
+
+/// Where a command-line runs, to resolve the relative and home paths of its arguments
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PathContext {
+    /// Working directory of the command-line
+    pub cwd: Option<String>,
+    /// User running the command-line, whose home is `/root` or `/home/<user>` unless given
+    pub user: Option<String>,
+    /// Home directory of the user (by default, `HOME` in `env`)
+    pub home: Option<String>,
+    /// Environment of the command-line, whose variables are expanded at the start of paths
+    pub env: HashMap<String, String>,
+}
+
+impl PathContext {
+    pub fn is_empty(&self) -> bool {
+        self.cwd.is_none() && self.user.is_none() && self.home.is_none() && self.env.is_empty()
+    }
+
+    /// `path` made absolute and normalized (without `.`, `..` nor repeated slashes), lexically:
+    /// the file system is not looked at. `None` when the context lacks the working directory,
+    /// home directory or variable the path depends on.
+    pub fn resolve(&self, path: &str) -> Option<String> {
+        let expanded = self.expand(path)?;
+        if expanded.starts_with('/') {
+            return Some(normalize(&expanded));
+        }
+        // The working directory may itself be written `~/src`
+        let cwd = self.expand(self.cwd.as_deref()?)?;
+        cwd.starts_with('/')
+            .then(|| normalize(&format!("{cwd}/{expanded}")))
+    }
+
+    fn home_dir(&self) -> Option<String> {
+        self.home
+            .clone()
+            .or_else(|| self.env.get("HOME").cloned())
+            .or_else(|| self.user.as_deref().map(user_home))
+    }
+
+    /// `path` with its leading `~`, `~user`, `$VAR` or `${VAR}` expanded
+    fn expand<'p>(&self, path: &'p str) -> Option<Cow<'p, str>> {
+        if let Some(rest) = path.strip_prefix('~') {
+            let (user, rest) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+            let home = if user.is_empty() || self.user.as_deref() == Some(user) {
+                self.home_dir()?
+            } else {
+                user_home(user)
+            };
+            return Some(Cow::Owned(home + rest));
+        }
+        if let Some(rest) = path.strip_prefix('$') {
+            let (variable, rest) = match rest.strip_prefix('{') {
+                Some(braced) => braced.split_once('}')?,
+                None => rest.split_at(
+                    rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                        .unwrap_or(rest.len()),
+                ),
+            };
+            let value = match variable {
+                "HOME" => self.home_dir()?,
+                "PWD" => self.env.get("PWD").or(self.cwd.as_ref())?.clone(),
+                variable => self.env.get(variable)?.clone(),
+            };
+            return Some(Cow::Owned(value + rest));
+        }
+        Some(Cow::Borrowed(path))
+    }
+}
+
+/// A local path with the categories of the locations it points to
+#[derive(Debug, Serialize, Clone)]
+pub struct LocalPath<'a> {
+    /// The path as written
+    pub path: Cow<'a, str>,
+    /// The absolute path it stands for, once resolved in the context of the command-line
+    pub resolved: Option<String>,
+    /// Categories of the resolved path if any, of the path as written otherwise
+    pub categories: Vec<PathCategory>,
+    /// Whether the path is a glob pattern, which the shell may expand to several files
+    pub glob: bool,
+}
+
+impl<'a> LocalPath<'a> {
+    pub fn new(path: Cow<'a, str>) -> LocalPath<'a> {
+        LocalPath {
+            resolved: None,
+            categories: classify(&path),
+            glob: is_glob(&path),
+            path,
+        }
+    }
+
+    /// Resolve the path in `context` (see `PathContext::resolve`) and classify what it stands for
+    pub fn resolve(&mut self, context: &PathContext) {
+        if let Some(resolved) = context.resolve(&self.path) {
+            let home = context.home_dir().map(|home| normalize(&home));
+            self.categories = classify_in_home(&resolved, home.as_deref());
+            self.resolved = Some(resolved);
+        }
+    }
+
+    /// Whether the path points to a sensitive location
+    pub fn is_sensitive(&self) -> bool {
+        self.categories
+            .iter()
+            .any(|category| category.is_sensitive())
+    }
+
+    pub fn into_owned(self) -> LocalPath<'static> {
+        LocalPath {
+            path: Cow::Owned(self.path.into_owned()),
+            resolved: self.resolved,
+            categories: self.categories,
+            glob: self.glob,
+        }
+    }
+}
+
+/// Whether `path` is `prefix`, or under it if it is a directory (ending with `/`)
+fn is_under(path: &str, prefix: &str) -> bool {
+    path.starts_with(prefix) || prefix.strip_suffix('/') == Some(path)
+}
+
+/// `path` relative to the home directory it is in, if it is in one: `~/`, `~user/`, `$HOME/`,
+/// `/root/` or `/home/user/`
+fn home_relative(path: &str) -> Option<&str> {
+    if let Some(rest) = path.strip_prefix('~') {
+        return rest.split_once('/').map(|(_, relative)| relative);
+    }
+    if let Some(relative) = path
+        .strip_prefix("$HOME/")
+        .or_else(|| path.strip_prefix("${HOME}/"))
+        .or_else(|| path.strip_prefix("/root/"))
+    {
+        return Some(relative);
+    }
+    path.strip_prefix("/home/")?
+        .split_once('/')
+        .map(|(_, relative)| relative)
+}
+
+/// Home directory of `user` by convention
+fn user_home(user: &str) -> String {
+    match user {
+        "root" => "/root".to_string(),
+        user => format!("/home/{user}"),
+    }
+}
+
+/// Absolute `path` without `.` and `..` components nor repeated slashes, `..` stopping at the root
+fn normalize(path: &str) -> String {
+    let mut components: Vec<&str> = vec![];
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    let mut normalized = format!("/{}", components.join("/"));
+    // Directories keep their trailing slash
+    if path.ends_with('/') && !components.is_empty() {
+        normalized.push('/');
+    }
+    normalized
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Extension of the file name of `path`, if it has one and is not a dotfile without one
+fn extension(path: &str) -> Option<&str> {
+    let (stem, extension) = file_name(path).rsplit_once('.')?;
+    (!stem.is_empty() && !extension.is_empty()).then_some(extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_paths() {
+        for path in [
+            "/tmp/a-b_1.txt",
+            "~/.ssh/id_rsa",
+            "foo.py",
+            "../x.tar.xz",
+            "./run",
+            "/dev/tcp/h/p",
+            "dir/file",
+            ".bashrc",
+            "*.log",
+            "id_rsa",
+            "image/logo.png",
+        ] {
+            assert!(is_local_path(path), "{path}");
+        }
+        for value in [
+            "",
+            "hello world",
+            "https://example.com/a",
+            "example.com",
+            "application/json",
+            "application/vnd.api+json",
+            "text/plain;charset=utf-8",
+            "image/svg+xml",
+        ] {
+            assert!(!is_local_path(value), "{value}");
+        }
+    }
+
+    #[test]
+    fn sensitive_locations() {
+        use PathCategory::*;
+        assert_eq!(classify("/tmp/a-b_1.txt"), [Temp]);
+        assert_eq!(classify("~/.ssh/id_rsa"), [Credentials, UserConfig]);
+        assert_eq!(classify("foo.py"), []);
+        assert_eq!(classify("../x.tar.xz"), []);
+        assert_eq!(classify("/dev/tcp/h/p"), [NetworkDevice, Device]);
+        assert_eq!(classify("/etc/shadow"), [Credentials, SystemConfig]);
+        assert_eq!(classify("/etc/crontab"), [SystemConfig, Persistence]);
+        assert_eq!(classify("/var/spool/cron/crontabs/root"), [Persistence]);
+        assert_eq!(
+            classify("/etc/systemd/system/x.service"),
+            [SystemConfig, Persistence]
+        );
+        assert_eq!(
+            classify("/home/user/.config/systemd/user/x.service"),
+            [UserConfig, Persistence]
+        );
+        assert!(!LocalPath::new(Cow::Borrowed("/tmp/x")).is_sensitive());
+        assert!(LocalPath::new(Cow::Borrowed("/etc/cron.d/x")).is_sensitive());
+    }
+
+    fn context(cwd: Option<&str>, user: Option<&str>, env: &[(&str, &str)]) -> PathContext {
+        PathContext {
+            cwd: cwd.map(str::to_string),
+            user: user.map(str::to_string),
+            home: None,
+            env: env
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn relative_paths_resolve_against_the_working_directory() {
+        let context = context(Some("/srv/app/"), None, &[]);
+        assert_eq!(
+            context.resolve("../../etc/shadow").as_deref(),
+            Some("/etc/shadow")
+        );
+        assert_eq!(
+            context.resolve("./logs//a.log").as_deref(),
+            Some("/srv/app/logs/a.log")
+        );
+        assert_eq!(context.resolve("dir/").as_deref(), Some("/srv/app/dir/"));
+        // Without a working directory, only absolute paths resolve
+        assert_eq!(PathContext::default().resolve("a.txt"), None);
+        assert_eq!(
+            PathContext::default().resolve("/a/./b/../c").as_deref(),
+            Some("/a/c")
+        );
+    }
+
+    #[test]
+    fn dot_dot_stops_at_the_root() {
+        let context = context(Some("/"), None, &[]);
+        assert_eq!(
+            context.resolve("../../etc/passwd").as_deref(),
+            Some("/etc/passwd")
+        );
+        assert_eq!(context.resolve("/../..").as_deref(), Some("/"));
+        assert_eq!(context.resolve("/../../tmp/x").as_deref(), Some("/tmp/x"));
+    }
+
+    #[test]
+    fn home_directories() {
+        let context = context(None, Some("alice"), &[]);
+        assert_eq!(context.resolve("~").as_deref(), Some("/home/alice"));
+        assert_eq!(
+            context.resolve("~/.ssh/id_rsa").as_deref(),
+            Some("/home/alice/.ssh/id_rsa")
+        );
+        assert_eq!(
+            context.resolve("~alice/x").as_deref(),
+            Some("/home/alice/x")
+        );
+        assert_eq!(
+            context.resolve("~bob/.netrc").as_deref(),
+            Some("/home/bob/.netrc")
+        );
+        assert_eq!(context.resolve("~root/x").as_deref(), Some("/root/x"));
+        // `HOME` takes precedence over the user's conventional home, `home` over both
+        let context = PathContext {
+            env: HashMap::from([("HOME".to_string(), "/data/alice".to_string())]),
+            ..context
+        };
+        assert_eq!(context.resolve("~/x").as_deref(), Some("/data/alice/x"));
+        let context = PathContext {
+            home: Some("/homes/a".to_string()),
+            ..context
+        };
+        assert_eq!(context.resolve("~").as_deref(), Some("/homes/a"));
+        // Without a user nor home, `~` cannot be resolved, unlike `~user`
+        assert_eq!(PathContext::default().resolve("~/x"), None);
+        assert_eq!(
+            PathContext::default().resolve("~bob").as_deref(),
+            Some("/home/bob")
+        );
+    }
+
+    #[test]
+    fn environment_variables() {
+        let context = context(
+            Some("/srv"),
+            None,
+            &[("HOME", "/home/carol"), ("DATA", "/var/data")],
+        );
+        assert_eq!(
+            context.resolve("$HOME/.bashrc").as_deref(),
+            Some("/home/carol/.bashrc")
+        );
+        assert_eq!(
+            context.resolve("${HOME}/.bashrc").as_deref(),
+            Some("/home/carol/.bashrc")
+        );
+        assert_eq!(context.resolve("$DATA/../x").as_deref(), Some("/var/x"));
+        assert_eq!(context.resolve("$PWD/x").as_deref(), Some("/srv/x"));
+        assert_eq!(context.resolve("$UNSET/x"), None);
+        assert_eq!(context.resolve("${UNSET}/x"), None);
+        assert_eq!(context.resolve("${HOME/x"), None);
+    }
+
+    #[test]
+    fn resolved_paths_are_classified() {
+        let mut path = LocalPath::new(Cow::Borrowed("../.ssh/id_rsa"));
+        assert_eq!(path.categories, [PathCategory::Credentials]);
+        path.resolve(&context(Some("/home/dave/src"), Some("dave"), &[]));
+        assert_eq!(path.resolved.as_deref(), Some("/home/dave/.ssh/id_rsa"));
+        assert_eq!(
+            path.categories,
+            [PathCategory::Credentials, PathCategory::UserConfig]
+        );
+    }
+}
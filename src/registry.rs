@@ -0,0 +1,349 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::config_format::ConfigFormat;
+use crate::config_store::{ConfigOrigin, ConfigStore};
+
+/// Programs that run one of their applets, named by their first argument (`busybox tar -xf a.tar`)
+pub const MULTI_CALL_PROGRAMS: &[&str] = &["busybox", "toybox"];
+
+/// Header fields of a config saying which program invocations it applies to
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct RegistryHeader {
+    /// Part of `ConfigFileSource` as well
+    #[schemars(skip)]
+    name: Option<String>,
+    /// Other names of the program (e.g. `gtar`)
+    #[serde(default)]
+    aliases: Vec<String>,
+    /// Regexes matched against the file name of the program (e.g. `^python3(\.\d+)*$`)
+    #[serde(default)]
+    name_patterns: Vec<String>,
+    /// Dialect or version of the program described by the config (e.g. `bsd`, `3.11`)
+    dialect: Option<String>,
+    /// Whether this config is used when no (known) dialect is requested
+    #[serde(default)]
+    default_dialect: bool,
+}
+
+/// A config of the registry and the invocations it applies to
+#[derive(Debug, Clone)]
+pub struct RegistryEntry {
+    /// Name of the config, i.e. its file name without extension (e.g. `tar@bsd`)
+    pub config: String,
+    /// File of the config within the config store (e.g. `tar@bsd.toml` or `tar@bsd.yaml`)
+    pub file: PathBuf,
+    /// File the config is actually read from
+    pub path: PathBuf,
+    pub origin: ConfigOrigin,
+    pub program: String,
+    pub dialect: Option<String>,
+    pub default_dialect: bool,
+    pub aliases: Vec<String>,
+    pub name_patterns: Vec<Regex>,
+}
+
+/// Index of the configs of a config store, built by scanning it: requests only ever resolve against
+/// it, never against paths derived from their contents.
+///
+/// Maps the program names of requests (`tar`, `gtar`, `/usr/bin/curl`, `python3.11`, `busybox tar`,
+/// `tar@bsd`...) to the configs of a config store
+#[derive(Debug, Clone, Default)]
+pub struct ProgramRegistry {
+    entries: Vec<RegistryEntry>,
+}
+
+impl ProgramRegistry {
+    /// Index the configs of `store`. Configs whose header can't be read are only known by their
+    /// file name; loading them reports the actual problem.
+    pub fn scan(store: &ConfigStore) -> ProgramRegistry {
+        let entries = store
+            .configs()
+            .into_iter()
+            .map(|(config, path, origin)| {
+                let file = path
+                    .file_name()
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| PathBuf::from(format!("{}.toml", config)));
+                let contents = store.contents(&path).unwrap_or_default();
+                RegistryEntry::from_header(config, file, path, origin, &contents)
+            })
+            .collect();
+        ProgramRegistry { entries }
+    }
+
+    /// Indexed configs, sorted by name
+    pub fn entries(&self) -> &[RegistryEntry] {
+        &self.entries
+    }
+
+    /// The indexed config named `config`
+    pub fn get(&self, config: &str) -> Option<&RegistryEntry> {
+        self.entries.iter().find(|entry| entry.config == config)
+    }
+
+    /// Name of the config to use for `program`, which may be a path, carry a `@dialect` hint or
+    /// be prefixed by a multi-call program
+    pub fn resolve(&self, program: &str) -> Option<&str> {
+        let mut words = program.split_whitespace();
+        let mut program = words.next()?;
+        if let Some(applet) = words.next()
+            && self.resolve_name(program, None).is_none()
+            && is_multi_call(program)
+        {
+            program = applet;
+        }
+
+        let (name, dialect) = match program.split_once('@') {
+            Some((name, dialect)) => (name, Some(dialect)),
+            None => (program, None),
+        };
+        // Only the file name matters, `/usr/bin/curl` being `curl`
+        let name = Path::new(name).file_name()?.to_str()?;
+        self.resolve_name(name, dialect)
+            .map(|entry| entry.config.as_str())
+    }
+
+    fn resolve_name(&self, name: &str, dialect: Option<&str>) -> Option<&RegistryEntry> {
+        // Unless it is the name of a program, a config name (`tar@bsd`) or an alias designates a
+        // single config, if no dialect is requested
+        let is_program = self.entries.iter().any(|entry| entry.program == name);
+        if !is_program
+            && dialect.is_none()
+            && let Some(entry) = self.entries.iter().find(|entry| {
+                entry.config == name || entry.aliases.iter().any(|alias| alias == name)
+            })
+        {
+            return Some(entry);
+        }
+
+        let program = self
+            .entries
+            .iter()
+            .find(|entry| {
+                entry.program == name
+                    || entry.config == name
+                    || entry.aliases.iter().any(|alias| alias == name)
+            })
+            .or_else(|| {
+                self.entries.iter().find(|entry| {
+                    entry
+                        .name_patterns
+                        .iter()
+                        .any(|pattern| pattern.is_match(name))
+                })
+            })?
+            .program
+            .as_str();
+        let dialects: Vec<&RegistryEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.program == program)
+            .collect();
+
+        if let Some(dialect) = dialect {
+            if let Some(entry) = select_dialect(&dialects, dialect) {
+                return Some(entry);
+            }
+            tracing::debug!(program = %program, dialect = %dialect, "Unknown dialect, using the default one");
+        }
+        dialects
+            .iter()
+            .find(|entry| entry.default_dialect)
+            .or_else(|| dialects.iter().find(|entry| entry.dialect.is_none()))
+            .or_else(|| dialects.first())
+            .copied()
+    }
+}
+
+impl RegistryEntry {
+    fn from_header(
+        config: String,
+        file: PathBuf,
+        path: PathBuf,
+        origin: ConfigOrigin,
+        contents: &str,
+    ) -> RegistryEntry {
+        // `tar@bsd.toml` defaults to the `bsd` dialect of `tar`
+        let (stem_program, stem_dialect) = match config.split_once('@') {
+            Some((program, dialect)) => (program.to_string(), Some(dialect.to_string())),
+            None => (config.clone(), None),
+        };
+        let format = ConfigFormat::from_path(&file).unwrap_or(ConfigFormat::Toml);
+        let header = match format.deserialize::<RegistryHeader>(contents) {
+            Ok(header) => header,
+            Err(err) => {
+                tracing::debug!(config = %config, error = %err, "Unreadable config header");
+                return RegistryEntry {
+                    file,
+                    path,
+                    origin,
+                    program: stem_program,
+                    dialect: stem_dialect,
+                    config,
+                    default_dialect: false,
+                    aliases: vec![],
+                    name_patterns: vec![],
+                };
+            }
+        };
+
+        let name_patterns = header
+            .name_patterns
+            .iter()
+            .filter_map(|pattern| {
+                Regex::new(pattern)
+                    .inspect_err(|err| {
+                        tracing::warn!(config = %config, error = %err, "Invalid name pattern");
+                    })
+                    .ok()
+            })
+            .collect();
+        RegistryEntry {
+            file,
+            path,
+            origin,
+            program: header.name.unwrap_or(stem_program),
+            dialect: header.dialect.or(stem_dialect),
+            config,
+            default_dialect: header.default_dialect,
+            aliases: header.aliases,
+            name_patterns,
+        }
+    }
+}
+
+/// The dialect of `dialects` named `requested`, or else the most specific version `requested`
+/// belongs to (`3.11.4` selecting `3.11` over `3`)
+fn select_dialect<'a>(
+    dialects: &[&'a RegistryEntry],
+    requested: &str,
+) -> Option<&'a RegistryEntry> {
+    dialects
+        .iter()
+        .filter_map(|entry| {
+            let dialect = entry.dialect.as_deref()?;
+            let matches = requested == dialect
+                || requested
+                    .strip_prefix(dialect)
+                    .is_some_and(|rest| rest.starts_with('.'));
+            matches.then_some((dialect.len(), *entry))
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, entry)| entry)
+}
+
+/// Whether `program` (possibly a path) is one of the `MULTI_CALL_PROGRAMS`
+pub fn is_multi_call(program: &str) -> bool {
+    Path::new(program)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| MULTI_CALL_PROGRAMS.contains(&name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_store::ConfigDir;
+
+    fn registry(test: &str, files: &[(&str, &str)]) -> ProgramRegistry {
+        let dir = ConfigDir::new(&format!("registry-{test}"), files);
+        ProgramRegistry::scan(&dir.store())
+    }
+
+    fn tar_registry(test: &str) -> ProgramRegistry {
+        registry(
+            test,
+            &[
+                (
+                    "tar.toml",
+                    "aliases = [\"gtar\", \"gnutar\"]\ndialect = \"gnu\"\ndefault_dialect = true\n",
+                ),
+                (
+                    "tar@bsd.toml",
+                    "aliases = [\"bsdtar\"]\ndialect = \"bsd\"\n",
+                ),
+                ("ls.toml", "name = \"ls\"\n"),
+            ],
+        )
+    }
+
+    #[test]
+    fn names_aliases_and_paths() {
+        let registry = tar_registry("names");
+        assert_eq!(registry.resolve("tar"), Some("tar"));
+        assert_eq!(registry.resolve("gtar"), Some("tar"));
+        assert_eq!(registry.resolve("gnutar"), Some("tar"));
+        assert_eq!(registry.resolve("bsdtar"), Some("tar@bsd"));
+        assert_eq!(registry.resolve("/usr/bin/tar"), Some("tar"));
+        assert_eq!(registry.resolve("/usr/local/bin/bsdtar"), Some("tar@bsd"));
+        assert_eq!(registry.resolve("./ls"), Some("ls"));
+        assert_eq!(registry.resolve("no-such-program"), None);
+        assert_eq!(registry.resolve("../../etc/passwd"), None);
+        assert_eq!(registry.resolve(""), None);
+    }
+
+    #[test]
+    fn dialects() {
+        let registry = tar_registry("dialects");
+        assert_eq!(registry.resolve("tar@bsd"), Some("tar@bsd"));
+        assert_eq!(registry.resolve("tar@gnu"), Some("tar"));
+        assert_eq!(registry.resolve("gtar@bsd"), Some("tar@bsd"));
+        // An unknown dialect falls back to the default one rather than failing
+        assert_eq!(registry.resolve("tar@nonexistent"), Some("tar"));
+        assert_eq!(registry.resolve("ls@anything"), Some("ls"));
+        assert_eq!(registry.resolve("nothing@bsd"), None);
+    }
+
+    #[test]
+    fn versions_and_name_patterns() {
+        let registry = registry(
+            "versions",
+            &[
+                ("python3.toml", "name_patterns = ['^python3(\\.\\d+)*$']\n"),
+                ("python3@3.11.toml", "name = \"python3\"\n"),
+                ("python3@3.toml", "name = \"python3\"\n"),
+            ],
+        );
+        assert_eq!(registry.resolve("python3"), Some("python3"));
+        assert_eq!(registry.resolve("/usr/bin/python3.12"), Some("python3"));
+        assert_eq!(registry.resolve("python3.12@3.11"), Some("python3@3.11"));
+        // The most specific version the requested one belongs to
+        assert_eq!(registry.resolve("python3@3.11.4"), Some("python3@3.11"));
+        assert_eq!(registry.resolve("python3@3.9"), Some("python3@3"));
+        assert_eq!(registry.resolve("python3@3.110"), Some("python3@3"));
+        assert_eq!(registry.resolve("python3@4"), Some("python3"));
+        assert_eq!(registry.resolve("python2"), None);
+    }
+
+    #[test]
+    fn multi_call_applets() {
+        let registry = tar_registry("applets");
+        assert_eq!(registry.resolve("busybox tar"), Some("tar"));
+        assert_eq!(registry.resolve("/bin/busybox tar"), Some("tar"));
+        assert_eq!(registry.resolve("toybox ls"), Some("ls"));
+        assert_eq!(registry.resolve("busybox"), None);
+        assert_eq!(registry.resolve("busybox nope"), None);
+        assert!(is_multi_call("/usr/bin/busybox"));
+        assert!(!is_multi_call("tar"));
+    }
+}
@@ -0,0 +1,678 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+#[cfg(unix)]
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Instant;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::sync::watch;
+
+use crate::analyzer::{Analyzer, AnalyzerError, roll_up_behaviour_tree};
+use crate::config_cache::{ConfigStatus, ParserConfigCache};
+use crate::config_store::ConfigOrigin;
+use crate::path_classifier::PathContext;
+use crate::taxonomy::{Behaviour, BehaviourInfo};
+use crate::{APP_NAME, APP_VERSION};
+
+/// Latency buckets (in seconds) of the `ucp_request_duration_seconds` histogram
+const REQUEST_DURATION_BUCKETS: &[f64] = &[
+    0.00005, 0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1,
+];
+
+/// Build the HTTP API on top of `analyzer`, without the admin endpoints
+pub fn router(analyzer: Analyzer) -> Router {
+    Router::new()
+        // `GET /` goes to `root`
+        .route("/", get(root))
+        .route("/parse", post(parse_request))
+        .route("/behaviours", post(behaviours_request))
+        .route("/taxonomy", get(taxonomy_request))
+        .route("/programs", get(programs_request))
+        .with_state(analyzer)
+        .route(
+            "/metrics",
+            get(metrics_request).with_state(metrics_handle()),
+        )
+}
+
+/// Build the HTTP API on top of `analyzer` along with the unauthenticated `/admin` endpoints.
+/// Only serve it where every client is trusted, e.g. on a Unix domain socket or on localhost.
+pub fn admin_router(analyzer: Analyzer) -> Router {
+    Router::new()
+        .route("/admin/configs", get(configs_request))
+        .route("/admin/reload", post(reload_request))
+        .with_state(analyzer.clone())
+        .merge(router(analyzer))
+}
+
+/// Install the Prometheus recorder on first use and return a handle to render it.
+/// If the embedding application already installed its own recorder, the returned handle renders
+/// nothing.
+pub fn metrics_handle() -> PrometheusHandle {
+    static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+    HANDLE
+        .get_or_init(|| {
+            let builder = PrometheusBuilder::new()
+                .set_buckets_for_metric(
+                    Matcher::Full("ucp_request_duration_seconds".to_string()),
+                    REQUEST_DURATION_BUCKETS,
+                )
+                .expect("REQUEST_DURATION_BUCKETS is not empty");
+            let recorder = builder.build_recorder();
+            let handle = recorder.handle();
+            if metrics::set_global_recorder(recorder).is_err() {
+                tracing::warn!("A metrics recorder is already installed, /metrics will be empty");
+            }
+            handle
+        })
+        .clone()
+}
+
+/// Count a request and its latency in `ucp_requests_total` and `ucp_request_duration_seconds`.
+/// Requests are labelled with the config their program resolves to (see `Analyzer::config_name`),
+/// never with the program they name, and programs without a config (`None`) with `unknown`, to
+/// keep the label set bounded.
+/// The HTTP and gRPC services share the HTTP status codes as `status` label.
+pub(crate) fn record_request(
+    route: &'static str,
+    program: Option<&str>,
+    status: StatusCode,
+    start_time: Instant,
+) {
+    let program = program.unwrap_or("unknown").to_string();
+    metrics::counter!(
+        "ucp_requests_total",
+        "route" => route,
+        "program" => program.clone(),
+        "status" => status.as_str().to_string()
+    )
+    .increment(1);
+    metrics::histogram!(
+        "ucp_request_duration_seconds",
+        "route" => route,
+        "program" => program
+    )
+    .record(start_time.elapsed().as_secs_f64());
+}
+
+/// Message of a failed analysis sent to clients. It only names the program: load errors carry
+/// paths of the config directories, which are logged instead.
+pub(crate) fn error_message(err: &AnalyzerError) -> String {
+    match err {
+        AnalyzerError::ConfigNotFound { program, .. } => {
+            format!("Failed to load config for program {program}")
+        }
+        AnalyzerError::ConfigDir { .. } => "Invalid config directory".to_string(),
+        err => err.to_string(),
+    }
+}
+
+/// HTTP status of a failed analysis
+pub(crate) fn error_status(err: &AnalyzerError) -> StatusCode {
+    match err {
+        AnalyzerError::UnknownOption { .. } => StatusCode::IM_A_TEAPOT,
+        _ => StatusCode::NOT_FOUND,
+    }
+}
+
+/// Where the HTTP API (and the gRPC service) listen. At least one listener must be set.
+#[derive(Clone, Debug, Default)]
+pub struct ListenConfig {
+    /// TCP address to listen on (e.g. `0.0.0.0:6880`)
+    pub tcp: Option<SocketAddr>,
+    /// Unix domain socket to listen on, replaced if it already exists
+    pub unix_socket: Option<PathBuf>,
+    /// Permissions of the Unix domain socket (e.g. `0o660`)
+    pub unix_socket_mode: Option<u32>,
+    /// TCP address of the gRPC service (e.g. `0.0.0.0:6881`)
+    #[cfg(feature = "grpc")]
+    pub grpc: Option<SocketAddr>,
+}
+
+impl ListenConfig {
+    fn has_listener(&self) -> bool {
+        #[cfg(feature = "grpc")]
+        if self.grpc.is_some() {
+            return true;
+        }
+        self.tcp.is_some() || self.unix_socket.is_some()
+    }
+}
+
+/// Serve the HTTP API until SIGTERM or Ctrl-C is received, then drain in-flight requests
+pub async fn serve(listen: &ListenConfig, analyzer: Analyzer) -> std::io::Result<()> {
+    println!("{APP_NAME} version: {APP_VERSION}");
+
+    if !listen.has_listener() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "No TCP address nor Unix domain socket to listen on",
+        ));
+    }
+
+    // Keep the watcher alive for as long as the server runs
+    let _config_watcher = match analyzer.cache().watch() {
+        Ok(watcher) => Some(watcher),
+        Err(err) => {
+            tracing::warn!(error = %err, "Failed to watch the config directory, configs will only be reloaded through /admin/reload");
+            None
+        }
+    };
+
+    #[cfg(feature = "grpc")]
+    let grpc_analyzer = analyzer.clone();
+    // The admin endpoints are only served to local clients
+    let app = router(analyzer.clone());
+    let admin_app = admin_router(analyzer);
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    // run our app with hyper, on every configured listener
+    let tcp_server = async {
+        let Some(address) = listen.tcp else {
+            return Ok(());
+        };
+        let listener = tokio::net::TcpListener::bind(address).await?;
+        println!("Starting server on {address}");
+        let app = match address.ip().is_loopback() {
+            true => admin_app.clone(),
+            false => app.clone(),
+        };
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown_requested(shutdown_rx.clone()))
+            .await
+    };
+    #[cfg(unix)]
+    let unix_server = async {
+        let Some(socket_path) = &listen.unix_socket else {
+            return Ok(());
+        };
+        // The guard removes the socket file however this future ends, including when it is
+        // dropped because another listener failed
+        let (listener, _socket_file) = bind_unix_socket(socket_path, listen.unix_socket_mode)?;
+        println!("Starting server on {}", socket_path.display());
+        axum::serve(listener, admin_app.clone())
+            .with_graceful_shutdown(shutdown_requested(shutdown_rx.clone()))
+            .await
+    };
+    #[cfg(not(unix))]
+    let unix_server = async {
+        match &listen.unix_socket {
+            Some(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Unix domain sockets are not supported on this platform",
+            )),
+            None => Ok(()),
+        }
+    };
+    #[cfg(feature = "grpc")]
+    let grpc_server = async {
+        let Some(address) = listen.grpc else {
+            return Ok(());
+        };
+        crate::grpc::serve(
+            address,
+            grpc_analyzer,
+            shutdown_requested(shutdown_rx.clone()),
+        )
+        .await
+    };
+    #[cfg(not(feature = "grpc"))]
+    let grpc_server = async { std::io::Result::Ok(()) };
+
+    // Ask every listener to drain in-flight requests and stop on SIGTERM or Ctrl-C
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        tracing::info!("Shutdown signal received, draining in-flight requests");
+        let _ = shutdown_tx.send(true);
+    });
+
+    tokio::try_join!(tcp_server, unix_server, grpc_server)?;
+    println!("Bye !");
+    Ok(())
+}
+
+/// Removes the Unix domain socket file when dropped
+#[cfg(unix)]
+#[derive(Debug)]
+struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Bind a Unix domain socket, replacing a stale socket file left by a previous run.
+/// The socket is bound in a private 0700 directory and only moved to `socket_path` once its
+/// permissions are set, so it is never reachable with looser ones.
+#[cfg(unix)]
+fn bind_unix_socket(
+    socket_path: &Path,
+    mode: Option<u32>,
+) -> std::io::Result<(UnixListener, SocketFile)> {
+    if let Ok(metadata) = std::fs::symlink_metadata(socket_path) {
+        if !metadata.file_type().is_socket() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", socket_path.display()),
+            ));
+        }
+        std::fs::remove_file(socket_path)?;
+    }
+    let file_name = socket_path.file_name().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} is not a socket file path", socket_path.display()),
+        )
+    })?;
+    let staging_dir = socket_path.with_file_name(format!(
+        ".{}.{}",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&staging_dir)?;
+    let listener = bind_staged_unix_socket(&staging_dir.join(file_name), socket_path, mode);
+    let _ = std::fs::remove_dir(&staging_dir);
+    Ok((listener?, SocketFile(socket_path.to_path_buf())))
+}
+
+/// Bind a Unix domain socket at `staging_path`, set its permissions and move it to `socket_path`
+#[cfg(unix)]
+fn bind_staged_unix_socket(
+    staging_path: &Path,
+    socket_path: &Path,
+    mode: Option<u32>,
+) -> std::io::Result<UnixListener> {
+    let listener = UnixListener::bind(staging_path)?;
+    let staged = SocketFile(staging_path.to_path_buf());
+    if let Some(mode) = mode {
+        std::fs::set_permissions(staging_path, std::fs::Permissions::from_mode(mode))?;
+    }
+    std::fs::rename(staging_path, socket_path)?;
+    // Nothing is left at the staging path once the socket has moved
+    drop(staged);
+    Ok(listener)
+}
+
+/// Resolves once a shutdown has been requested through the watch channel
+async fn shutdown_requested(mut shutdown_rx: watch::Receiver<bool>) {
+    let _ = shutdown_rx.wait_for(|requested| *requested).await;
+}
+
+/// Resolves on SIGTERM or Ctrl-C
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install the Ctrl-C handler");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install the SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+// basic handler that responds with a static string
+async fn root() -> &'static str {
+    "Hello, World!"
+}
+
+async fn metrics_request(State(handle): State<PrometheusHandle>) -> String {
+    handle.render()
+}
+
+async fn taxonomy_request(State(analyzer): State<Analyzer>) -> Json<Vec<TaxonomyEntry>> {
+    Json(
+        analyzer
+            .taxonomy()
+            .behaviours()
+            .map(|(behaviour, info)| TaxonomyEntry {
+                info,
+                parent: behaviour.parent(),
+                depth: behaviour.depth(),
+                children: behaviour.children().collect(),
+            })
+            .collect(),
+    )
+}
+
+async fn programs_request(State(analyzer): State<Analyzer>) -> Json<Vec<ProgramInfo>> {
+    Json(
+        analyzer
+            .cache()
+            .registry()
+            .entries()
+            .iter()
+            .map(|entry| ProgramInfo {
+                config: entry.config.clone(),
+                program: entry.program.clone(),
+                dialect: entry.dialect.clone(),
+                aliases: entry.aliases.clone(),
+                origin: entry.origin.clone(),
+                path: entry.path.clone(),
+            })
+            .collect(),
+    )
+}
+
+async fn configs_request(State(analyzer): State<Analyzer>) -> Json<Vec<ConfigStatus>> {
+    Json(analyzer.cache().statuses())
+}
+
+async fn reload_request(
+    State(analyzer): State<Analyzer>,
+    Query(query): Query<ReloadQuery>,
+) -> (StatusCode, Json<ReloadReport>) {
+    let cache = analyzer.cache().clone();
+    let program = query.program.clone();
+    // Reloading reads and parses config files, keep it off the runtime
+    let (status_code, report) =
+        tokio::task::spawn_blocking(move || reload(&cache, program.as_deref()))
+            .await
+            .unwrap_or_else(|err| {
+                let error = format!("The reload failed: {err}");
+                let errors = HashMap::from([(query.program.unwrap_or_default(), error)]);
+                let report = ReloadReport {
+                    errors,
+                    configs: analyzer.cache().statuses(),
+                };
+                (StatusCode::INTERNAL_SERVER_ERROR, report)
+            });
+    (status_code, Json(report))
+}
+
+/// Reload the config of `program`, or every cached config
+fn reload(cache: &ParserConfigCache, program: Option<&str>) -> (StatusCode, ReloadReport) {
+    let (status_code, errors) = match program {
+        Some(program) => match cache.reload(program) {
+            Ok(_) => (StatusCode::OK, HashMap::new()),
+            Err(err)
+                if cache
+                    .statuses()
+                    .iter()
+                    .any(|status| status.program == program) =>
+            {
+                (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    HashMap::from([(program.to_string(), err)]),
+                )
+            }
+            Err(err) => (
+                StatusCode::NOT_FOUND,
+                HashMap::from([(program.to_string(), err)]),
+            ),
+        },
+        None => {
+            let errors: HashMap<String, String> = cache.reload_all().into_iter().collect();
+            let status_code = if errors.is_empty() {
+                StatusCode::OK
+            } else {
+                StatusCode::UNPROCESSABLE_ENTITY
+            };
+            (status_code, errors)
+        }
+    };
+    (
+        status_code,
+        ReloadReport {
+            errors,
+            configs: cache.statuses(),
+        },
+    )
+}
+
+async fn parse_request(
+    State(analyzer): State<Analyzer>,
+    Json(payload): Json<ParseRequest>,
+) -> Result<String, (StatusCode, String)> {
+    let start_time = Instant::now();
+    let program = payload.program;
+    let args = payload.args;
+
+    // Perform parsing logic here
+    match analyzer.parse_async(&program, &args).await {
+        Ok(mut parsed_cmdline) => {
+            for elem in &mut parsed_cmdline {
+                elem.resolve_paths(&payload.context);
+            }
+            let config = analyzer.config_name(&program, &args);
+            record_request("/parse", config.as_deref(), StatusCode::OK, start_time);
+            Ok(format!("{:?}", parsed_cmdline))
+        }
+        Err(err) => {
+            tracing::warn!(program = %program, error = %err, "/parse : Failed to load config for program");
+            let status = error_status(&err);
+            let config = analyzer.config_name(&program, &args);
+            record_request("/parse", config.as_deref(), status, start_time);
+            Err((status, error_message(&err)))
+        }
+    }
+}
+
+async fn behaviours_request(
+    State(analyzer): State<Analyzer>,
+    Json(payload): Json<ParseRequest>,
+) -> Result<Response, (StatusCode, String)> {
+    let start_time = Instant::now();
+    let program = payload.program;
+    let args = payload.args;
+
+    //Debug display of the request contents
+    tracing::debug!(program = %program, args = ?args, "/behaviours : Received request");
+    // The behaviour tree borrows from `args`, it is serialized before they go away
+    match analyzer
+        .analyze_in_async(&program, &args, &payload.context)
+        .await
+    {
+        Ok(behaviour_tree) => {
+            let behaviour_tree = match payload.depth {
+                Some(depth) => roll_up_behaviour_tree(behaviour_tree, depth),
+                None => behaviour_tree,
+            };
+            let elapsed = start_time.elapsed();
+            tracing::info!(
+                duration_us = elapsed.as_micros(),
+                program = %program,
+                "/behaviours : SUCCESS"
+            );
+            let config = analyzer.config_name(&program, &args);
+            record_request("/behaviours", config.as_deref(), StatusCode::OK, start_time);
+            Ok(Json(behaviour_tree).into_response())
+        }
+        Err(err @ AnalyzerError::UnknownOption { .. }) => {
+            let elapsed = start_time.elapsed();
+            tracing::error!(
+                duration_us = elapsed.as_micros(),
+                program = %program,
+                error = %err,
+                "/behaviours : Error getting behaviour for option"
+            );
+            let status = error_status(&err);
+            let config = analyzer.config_name(&program, &args);
+            record_request("/behaviours", config.as_deref(), status, start_time);
+            Err((status, error_message(&err)))
+        }
+        Err(err) => {
+            let elapsed = start_time.elapsed();
+            tracing::warn!(
+                duration_us = elapsed.as_micros(),
+                program = %program,
+                error = %err,
+                "/behaviours : Failed to load config for program"
+            );
+            let status = error_status(&err);
+            let config = analyzer.config_name(&program, &args);
+            record_request("/behaviours", config.as_deref(), status, start_time);
+            Err((status, error_message(&err)))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ReloadQuery {
+    /// Only reload this program, instead of every cached config
+    program: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ReloadReport {
+    /// Programs that failed to reload, with their error
+    errors: HashMap<String, String>,
+    configs: Vec<ConfigStatus>,
+}
+
+// the input to our `create_user` handler
+#[derive(Deserialize)]
+struct ParseRequest {
+    program: String,
+    args: Vec<String>,
+    /// Roll behaviours up to this depth of the taxonomy (0 being the most general level)
+    #[serde(default)]
+    depth: Option<usize>,
+    /// Working directory, user, home directory and environment to resolve local paths in
+    #[serde(flatten)]
+    context: PathContext,
+}
+
+#[derive(Serialize)]
+struct ProgramInfo {
+    config: String,
+    program: String,
+    dialect: Option<String>,
+    aliases: Vec<String>,
+    /// Built-in or overlay directory the config in use comes from
+    origin: ConfigOrigin,
+    path: PathBuf,
+}
+
+#[derive(Serialize)]
+struct TaxonomyEntry {
+    #[serde(flatten)]
+    info: &'static BehaviourInfo,
+    parent: Option<Behaviour>,
+    depth: usize,
+    children: Vec<Behaviour>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_store::ConfigDir;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    async fn get_status(app: Router, uri: &str) -> StatusCode {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn admin_endpoints_are_only_in_the_admin_router() {
+        let analyzer = Analyzer::builtin();
+        assert_eq!(
+            get_status(router(analyzer.clone()), "/admin/configs").await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            get_status(admin_router(analyzer.clone()), "/admin/configs").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            get_status(admin_router(analyzer), "/programs").await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn load_errors_do_not_reveal_config_paths() {
+        let dir = ConfigDir::new("server-configs", &[("broken.toml", "name = [")]);
+        let analyzer = Analyzer::from_config_dir(dir.path()).unwrap();
+
+        let request = Request::post("/behaviours")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"program": "broken", "args": []}"#))
+            .unwrap();
+        let response = router(analyzer).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"Failed to load config for program broken");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_socket_is_bound_with_its_mode_and_removed_on_drop() {
+        let dir = std::env::temp_dir().join(format!("ucp-server-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("ucp.sock");
+
+        let (listener, socket_file) = bind_unix_socket(&socket_path, Some(0o600)).unwrap();
+        let metadata = std::fs::symlink_metadata(&socket_path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        // Only the socket is left in the directory, not the staging one
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        drop(listener);
+        drop(socket_file);
+        assert!(!socket_path.exists());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_socket_does_not_replace_other_files() {
+        let dir = std::env::temp_dir().join(format!("ucp-server-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("ucp.sock");
+        std::fs::write(&socket_path, "").unwrap();
+
+        let err = bind_unix_socket(&socket_path, None).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,509 @@
+/*
+   Copyright (C) 2025  Antonin Verdier & Institut de Recherche en Informatique de Toulouse
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::pin::pin;
+use std::sync::{Mutex, OnceLock};
+
+/// Taxonomy built into the library, used unless another one is entered (see `Taxonomy::enter`)
+const DEFAULT_TAXONOMY: &str = include_str!("../taxonomy.toml");
+
+static BUILTIN_TAXONOMY: OnceLock<Taxonomy> = OnceLock::new();
+
+thread_local! {
+    /// Taxonomy behaviour names are looked up in on this thread, see `Taxonomy::current`
+    static CURRENT_TAXONOMY: Cell<Option<&'static Taxonomy>> = const { Cell::new(None) };
+}
+
+/// Behaviour lists are few and shared by many options: each distinct list is allocated once, and
+/// lives as long as the program
+static BEHAVIOUR_SETS: OnceLock<Mutex<HashSet<&'static [Behaviour]>>> = OnceLock::new();
+
+/// An interned list of behaviours (see `intern_behaviours`). Copying it is free, and it
+/// (de)serializes as the list of behaviour names.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BehaviourSet(&'static [Behaviour]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    None,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+/// A behaviour of a taxonomy. Behaviours are interned: this is an index into their taxonomy,
+/// (de)serialized as the behaviour name.
+#[derive(Clone, Copy)]
+pub struct Behaviour {
+    taxonomy: &'static Taxonomy,
+    id: u16,
+}
+
+/// Definition of a behaviour in the taxonomy
+#[derive(Debug, Clone, Serialize)]
+pub struct BehaviourInfo {
+    pub name: String,
+    /// Index of the parent in the taxonomy, see `Behaviour::parent`
+    #[serde(skip)]
+    parent: Option<u16>,
+    pub description: String,
+    pub severity: Severity,
+}
+
+/// The set of known behaviours and their hierarchy
+#[derive(Debug)]
+pub struct Taxonomy {
+    behaviours: Vec<BehaviourInfo>,
+    by_name: HashMap<String, u16>,
+}
+
+#[derive(Deserialize)]
+struct TaxonomyFile {
+    behaviours: Vec<RawBehaviour>,
+}
+
+#[derive(Deserialize)]
+struct RawBehaviour {
+    name: String,
+    parent: Option<String>,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    severity: Severity,
+}
+
+impl Taxonomy {
+    pub fn from_toml_file(file_path: &str) -> Result<Taxonomy, String> {
+        let contents = std::fs::read_to_string(file_path).map_err(|e| e.to_string())?;
+        Taxonomy::from_toml_str(&contents)
+    }
+
+    pub fn from_toml_str(contents: &str) -> Result<Taxonomy, String> {
+        let taxonomy_file: TaxonomyFile = toml::from_str(contents).map_err(|e| e.to_string())?;
+
+        let mut by_name = HashMap::new();
+        for (idx, raw) in taxonomy_file.behaviours.iter().enumerate() {
+            let id = u16::try_from(idx).map_err(|_| "Too many behaviours".to_string())?;
+            if by_name.insert(raw.name.clone(), id).is_some() {
+                return Err(format!("The \"{}\" behaviour is defined twice", raw.name));
+            }
+        }
+
+        let mut behaviours = Vec::with_capacity(taxonomy_file.behaviours.len());
+        for raw in taxonomy_file.behaviours {
+            let parent = match &raw.parent {
+                Some(parent) => Some(*by_name.get(parent).ok_or_else(|| {
+                    format!(
+                        "The parent \"{}\" of the \"{}\" behaviour is unknown",
+                        parent, raw.name
+                    )
+                })?),
+                None => None,
+            };
+            behaviours.push(BehaviourInfo {
+                name: raw.name,
+                parent,
+                description: raw.description,
+                severity: raw.severity,
+            });
+        }
+
+        let taxonomy = Taxonomy {
+            behaviours,
+            by_name,
+        };
+        // Every chain of parents must reach a root
+        for info in &taxonomy.behaviours {
+            let mut depth = 0;
+            let mut current = info.parent;
+            while let Some(parent) = current {
+                depth += 1;
+                if depth > taxonomy.behaviours.len() {
+                    return Err(format!(
+                        "The parents of the \"{}\" behaviour form a cycle",
+                        info.name
+                    ));
+                }
+                current = taxonomy.behaviours[parent as usize].parent;
+            }
+        }
+        Ok(taxonomy)
+    }
+
+    /// The default `taxonomy.toml` embedded in the binary
+    pub fn builtin() -> &'static Taxonomy {
+        BUILTIN_TAXONOMY.get_or_init(|| {
+            Taxonomy::from_toml_str(DEFAULT_TAXONOMY).expect("The default taxonomy is valid")
+        })
+    }
+
+    /// Keep the taxonomy for the rest of the program, as its behaviours refer to it. Meant to be
+    /// called once per taxonomy file, e.g. at startup.
+    pub fn leak(self) -> &'static Taxonomy {
+        Box::leak(Box::new(self))
+    }
+
+    /// The taxonomy behaviour names are looked up in on this thread (when deserializing configs
+    /// for instance): the one entered with `enter`, or the built-in one
+    pub fn current() -> &'static Taxonomy {
+        CURRENT_TAXONOMY.get().unwrap_or_else(Taxonomy::builtin)
+    }
+
+    /// Run `f` with this taxonomy as the current one
+    pub fn enter<R>(&'static self, f: impl FnOnce() -> R) -> R {
+        /// Restores the previous taxonomy, even if `f` panics
+        struct Restore(Option<&'static Taxonomy>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT_TAXONOMY.set(self.0);
+            }
+        }
+        let _restore = Restore(CURRENT_TAXONOMY.replace(Some(self)));
+        f()
+    }
+
+    /// Run `future` with this taxonomy as the current one, whatever thread polls it
+    pub async fn scope<F: Future>(&'static self, future: F) -> F::Output {
+        let mut future = pin!(future);
+        std::future::poll_fn(|cx| self.enter(|| future.as_mut().poll(cx))).await
+    }
+
+    /// Look a behaviour up by name
+    pub fn behaviour(&'static self, name: &str) -> Option<Behaviour> {
+        self.by_name
+            .get(name)
+            .map(|&id| Behaviour { taxonomy: self, id })
+    }
+
+    /// Every behaviour of the taxonomy, in definition order
+    pub fn behaviours(&'static self) -> impl Iterator<Item = (Behaviour, &'static BehaviourInfo)> {
+        self.behaviours.iter().enumerate().map(move |(idx, info)| {
+            let behaviour = Behaviour {
+                taxonomy: self,
+                id: idx as u16,
+            };
+            (behaviour, info)
+        })
+    }
+}
+
+impl Behaviour {
+    /// Look a behaviour up by name in the current taxonomy (see `Taxonomy::current`)
+    pub fn from_name(name: &str) -> Option<Behaviour> {
+        Taxonomy::current().behaviour(name)
+    }
+
+    /// The taxonomy this behaviour belongs to
+    pub fn taxonomy(self) -> &'static Taxonomy {
+        self.taxonomy
+    }
+
+    pub fn info(self) -> &'static BehaviourInfo {
+        &self.taxonomy.behaviours[self.id as usize]
+    }
+
+    pub fn name(self) -> &'static str {
+        &self.info().name
+    }
+
+    pub fn description(self) -> &'static str {
+        &self.info().description
+    }
+
+    pub fn severity(self) -> Severity {
+        self.info().severity
+    }
+
+    pub fn parent(self) -> Option<Behaviour> {
+        self.info().parent.map(|id| Behaviour {
+            taxonomy: self.taxonomy,
+            id,
+        })
+    }
+
+    /// Behaviours directly under this one
+    pub fn children(self) -> impl Iterator<Item = Behaviour> {
+        self.taxonomy
+            .behaviours()
+            .filter(move |(_, info)| info.parent == Some(self.id))
+            .map(|(behaviour, _)| behaviour)
+    }
+
+    /// Depth in the hierarchy, roots being at depth 0
+    pub fn depth(self) -> usize {
+        self.ancestors().count()
+    }
+
+    /// Parent, grand-parent, ... up to the root
+    pub fn ancestors(self) -> impl Iterator<Item = Behaviour> {
+        std::iter::successors(self.parent(), |behaviour| behaviour.parent())
+    }
+
+    /// Whether this behaviour is `other` or one of its descendants
+    pub fn is_a(self, other: Behaviour) -> bool {
+        self == other || self.ancestors().any(|ancestor| ancestor == other)
+    }
+
+    /// The ancestor of this behaviour at `depth`, or itself if it is not deeper than `depth`
+    pub fn at_depth(self, depth: usize) -> Behaviour {
+        let own_depth = self.depth();
+        if own_depth <= depth {
+            return self;
+        }
+        self.ancestors()
+            .nth(own_depth - depth - 1)
+            .expect("depth() counts the ancestors")
+    }
+}
+
+// Behaviours of different taxonomies never compare equal, even with the same name
+impl PartialEq for Behaviour {
+    fn eq(&self, other: &Behaviour) -> bool {
+        self.id == other.id && std::ptr::eq(self.taxonomy, other.taxonomy)
+    }
+}
+
+impl Eq for Behaviour {}
+
+impl Hash for Behaviour {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self.taxonomy, state);
+        self.id.hash(state);
+    }
+}
+
+impl PartialOrd for Behaviour {
+    fn partial_cmp(&self, other: &Behaviour) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Definition order within a taxonomy
+impl Ord for Behaviour {
+    fn cmp(&self, other: &Behaviour) -> Ordering {
+        let taxonomy = |behaviour: &Behaviour| behaviour.taxonomy as *const Taxonomy;
+        (taxonomy(self), self.id).cmp(&(taxonomy(other), other.id))
+    }
+}
+
+/// The shared copy of `behaviours`, allocated on first use
+pub fn intern_behaviours(behaviours: &[Behaviour]) -> BehaviourSet {
+    if behaviours.is_empty() {
+        return BehaviourSet::EMPTY;
+    }
+    let mut sets = BEHAVIOUR_SETS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(set) = sets.get(behaviours) {
+        return BehaviourSet(set);
+    }
+    let set: &'static [Behaviour] = Box::leak(behaviours.into());
+    sets.insert(set);
+    BehaviourSet(set)
+}
+
+impl BehaviourSet {
+    /// The empty set, without locking the interned sets
+    pub const EMPTY: BehaviourSet = BehaviourSet(&[]);
+
+    pub fn as_slice(&self) -> &'static [Behaviour] {
+        self.0
+    }
+
+    /// These behaviours followed by those of `other` they do not have
+    pub fn union(self, other: BehaviourSet) -> BehaviourSet {
+        if other.iter().all(|behaviour| self.contains(behaviour)) {
+            return self;
+        }
+        let mut behaviours = self.to_vec();
+        behaviours.extend(other.iter().filter(|behaviour| !self.contains(behaviour)));
+        intern_behaviours(&behaviours)
+    }
+}
+
+impl Default for BehaviourSet {
+    fn default() -> Self {
+        BehaviourSet::EMPTY
+    }
+}
+
+impl Deref for BehaviourSet {
+    type Target = [Behaviour];
+
+    fn deref(&self) -> &[Behaviour] {
+        self.0
+    }
+}
+
+impl From<&[Behaviour]> for BehaviourSet {
+    fn from(behaviours: &[Behaviour]) -> Self {
+        intern_behaviours(behaviours)
+    }
+}
+
+impl fmt::Debug for BehaviourSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.0, f)
+    }
+}
+
+impl Serialize for BehaviourSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BehaviourSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<Behaviour>::deserialize(deserializer).map(|behaviours| intern_behaviours(&behaviours))
+    }
+}
+
+/// Roll `behaviours` up to `depth` in the hierarchy, without duplicates
+pub fn roll_up(behaviours: &[Behaviour], depth: usize) -> Vec<Behaviour> {
+    let mut rolled_up: Vec<Behaviour> = Vec::with_capacity(behaviours.len());
+    for behaviour in behaviours {
+        let behaviour = behaviour.at_depth(depth);
+        if !rolled_up.contains(&behaviour) {
+            rolled_up.push(behaviour);
+        }
+    }
+    rolled_up
+}
+
+impl fmt::Debug for Behaviour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.name())
+    }
+}
+
+impl fmt::Display for Behaviour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Serialize for Behaviour {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/// Behaviours are written as the name of one of the behaviours of the current taxonomy
+impl JsonSchema for Behaviour {
+    fn schema_name() -> Cow<'static, str> {
+        "Behaviour".into()
+    }
+
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        let behaviours: Vec<serde_json::Value> = Taxonomy::current()
+            .behaviours()
+            .map(|(_, info)| serde_json::json!({"const": info.name, "description": info.description}))
+            .collect();
+        json_schema!({
+            "type": "string",
+            "oneOf": behaviours,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Behaviour {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Behaviour::from_name(&name).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "unknown behaviour \"{name}\", it is not part of the taxonomy"
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUSTOM_TAXONOMY: &str = r#"
+[[behaviours]]
+name = "FS_OP"
+
+[[behaviours]]
+name = "FILE_READ"
+parent = "FS_OP"
+severity = "low"
+"#;
+
+    #[test]
+    fn behaviours_are_looked_up_in_the_current_taxonomy() {
+        let custom = Taxonomy::from_toml_str(CUSTOM_TAXONOMY).unwrap().leak();
+        let builtin = Behaviour::from_name("FILE_READ").unwrap();
+        assert!(std::ptr::eq(builtin.taxonomy(), Taxonomy::builtin()));
+        assert!(Behaviour::from_name("CMD_EXEC").is_some());
+
+        let entered = custom.enter(|| Behaviour::from_name("FILE_READ").unwrap());
+        assert!(std::ptr::eq(entered.taxonomy(), custom));
+        assert_eq!(entered.name(), builtin.name());
+        assert_ne!(entered, builtin);
+        assert_eq!(entered.severity(), Severity::Low);
+        assert_eq!(entered.parent().map(Behaviour::name), Some("FS_OP"));
+        assert!(custom.enter(|| Behaviour::from_name("CMD_EXEC")).is_none());
+
+        // The previous taxonomy is back once `enter` returns
+        assert!(std::ptr::eq(Taxonomy::current(), Taxonomy::builtin()));
+    }
+
+    #[test]
+    fn behaviours_deserialize_in_the_current_taxonomy() {
+        let custom = Taxonomy::from_toml_str(CUSTOM_TAXONOMY).unwrap().leak();
+        let behaviours: BehaviourSet = custom
+            .enter(|| serde_json::from_str(r#"["FS_OP", "FILE_READ"]"#))
+            .unwrap();
+        assert!(
+            behaviours
+                .iter()
+                .all(|b| std::ptr::eq(b.taxonomy(), custom))
+        );
+        assert_eq!(
+            serde_json::to_string(&behaviours).unwrap(),
+            r#"["FS_OP","FILE_READ"]"#
+        );
+        let unknown = custom.enter(|| serde_json::from_str::<Behaviour>(r#""CMD_EXEC""#));
+        assert!(unknown.is_err());
+    }
+
+    #[test]
+    fn roll_up_stops_at_the_requested_depth() {
+        let file_read = Behaviour::from_name("FILE_READ").unwrap();
+        let fs_op = Behaviour::from_name("FS_OP").unwrap();
+        assert_eq!(file_read.at_depth(0), fs_op);
+        assert_eq!(roll_up(&[file_read, fs_op], 0), vec![fs_op]);
+        assert!(file_read.is_a(fs_op));
+        assert!(fs_op.children().any(|child| child == file_read));
+    }
+}